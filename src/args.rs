@@ -1,45 +1,490 @@
 use std::str::FromStr;
 use std::path::PathBuf;
-use clap::Args;
+use clap::{ArgGroup, Args, Subcommand, ValueEnum};
+use clap_complete::engine::ArgValueCompleter;
 use crate::chunk_type::ChunkType;
+use crate::completions;
+use crate::crypto::{Identity, PublicKey};
+use crate::embed::Method;
+use crate::envelope::Envelope;
+use crate::strip::{StripPolicy, StripScope};
 
 
 #[derive(Args)]
 pub struct EncodeArgs {
-    #[arg(short, long, help = "Path to the PNG file to process")]
-    pub(crate) input: PathBuf,
+    #[arg(short, long = "input", required = true, help = "PNG files, directories or glob patterns to process (repeatable)")]
+    pub(crate) inputs: Vec<PathBuf>,
 
-    #[arg(value_parser = ChunkType::from_str, help = "Chunk type (4 ASCII letters)")]
-    pub(crate) chunk_type: ChunkType,
-
-    #[arg(help = "Message to embed in the PNG file")]
-    pub(crate) message: String,
+    #[arg(long, help = "Descend into subdirectories of input directories")]
+    pub(crate) recursive: bool,
 
     #[arg(short, long, value_name = "FILE", help = "Output file path (defaults to input file if not specified)")]
     pub(crate) output: Option<PathBuf>,
+
+    #[arg(long, help = "Print the chunk-level changes instead of writing the file")]
+    pub(crate) dry_run: bool,
+
+    #[command(flatten)]
+    pub(crate) embed: EmbedOptions,
+}
+
+// What to embed and how, shared by `encode` and the encode step of scripts.
+#[derive(Args)]
+pub struct EmbedOptions {
+	#[arg(value_parser = ChunkType::from_str, help = "Chunk type (4 ASCII letters), required by the chunk method")]
+	pub(crate) chunk_type: Option<ChunkType>,
+
+	#[arg(help = "Message to embed in the PNG file")]
+	pub(crate) message: String,
+
+	#[arg(long, help = "Embed the message even with a critical or standard chunk type")]
+	pub(crate) force: bool,
+
+	#[arg(short, long = "recipient", value_name = "PUBKEY", value_parser = PublicKey::from_str, help = "Encrypt the message to this X25519 public key (repeatable)")]
+	pub(crate) recipients: Vec<PublicKey>,
+
+	#[arg(long, help = "Don't encrypt the message, even to recipients from the config file")]
+	pub(crate) plain: bool,
+
+	#[arg(short, long, value_enum, default_value_t = Method::Chunk, help = "Where to hide the message")]
+	pub(crate) method: Method,
+
+	#[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=4), help = "Bits per colour sample used by the lsb and apng methods")]
+	pub(crate) bits: u8,
+
+	#[arg(long, help = "Also use the lowest alpha bit of visible pixels (alpha method)")]
+	pub(crate) alpha_lsb: bool,
+
+	#[arg(short, long, value_name = "PASSPHRASE", help = "Scatter the message over the image in a keyed pseudo-random order (pixel-domain methods)")]
+	pub(crate) key: Option<String>,
+
+	#[arg(long, value_name = "PARITY", value_parser = clap::value_parser!(u8).range(Envelope::MIN_PARITY as i64..=Envelope::MAX_PARITY as i64), help = "Add Reed-Solomon error correction with PARITY check bytes per 255-byte block (corrects PARITY/2 damaged bytes)")]
+	pub(crate) ecc: Option<u8>,
+
+	#[arg(long, value_name = "LEVEL", default_value_t = 9, value_parser = clap::value_parser!(u32).range(0..=9), help = "zlib level (0-9) for image data that pixel-domain methods re-encode")]
+	pub(crate) compression: u32,
 }
 
 #[derive(Args)]
 pub struct DecodeArgs {
-	#[arg(short, long, help = "Path to the PNG file to process")]
-	pub(crate) input: PathBuf,
+	#[arg(short, long = "input", required = true, help = "PNG files, directories or glob patterns to process (repeatable)")]
+	pub(crate) inputs: Vec<PathBuf>,
 
-	#[arg(value_parser = ChunkType::from_str)]
-	pub(crate) chunk_type: ChunkType,
+	#[arg(long, help = "Descend into subdirectories of input directories")]
+	pub(crate) recursive: bool,
+
+	#[arg(value_parser = ChunkType::from_str, add = ArgValueCompleter::new(completions::chunk_types), help = "Chunk type (4 ASCII letters), required by the chunk method")]
+	pub(crate) chunk_type: Option<ChunkType>,
+
+	#[arg(long, value_name = "PRIVKEY", value_parser = Identity::from_str, conflicts_with = "identity_file", help = "Private key (64 hex digits) to decrypt the message with")]
+	pub(crate) identity: Option<Identity>,
+
+	#[arg(long, value_name = "FILE", help = "Identity file holding the private key to decrypt the message with")]
+	pub(crate) identity_file: Option<PathBuf>,
+
+	#[arg(short, long, value_enum, default_value_t = Method::Chunk, help = "Where the message is hidden")]
+	pub(crate) method: Method,
+
+	#[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..=4), help = "Bits per colour sample used by the lsb and apng methods")]
+	pub(crate) bits: u8,
+
+	#[arg(long, help = "The message also uses the lowest alpha bit of visible pixels (alpha method)")]
+	pub(crate) alpha_lsb: bool,
+
+	#[arg(short, long, value_name = "PASSPHRASE", help = "Passphrase the message was scattered with")]
+	pub(crate) key: Option<String>,
 }
 
 #[derive(Args)]
 pub struct RemoveArgs {
-	#[arg(short, long, help = "Path to the PNG file to process")]
-	pub(crate) input: PathBuf,
+	#[arg(short, long = "input", required = true, help = "PNG files, directories or glob patterns to process (repeatable)")]
+	pub(crate) inputs: Vec<PathBuf>,
+
+	#[arg(long, help = "Descend into subdirectories of input directories")]
+	pub(crate) recursive: bool,
+
+	#[arg(long, help = "Print the chunk-level changes instead of writing the file")]
+	pub(crate) dry_run: bool,
 
 	/// 4-character chunk type
-	#[arg(value_parser = ChunkType::from_str)]
+	#[arg(value_parser = ChunkType::from_str, add = ArgValueCompleter::new(completions::chunk_types))]
 	pub(crate) chunk_type: ChunkType,
 }
 
 #[derive(Args)]
 pub struct PrintArgs {
+	#[arg(short, long = "input", required = true, help = "PNG files, directories or glob patterns to process (repeatable)")]
+	pub(crate) inputs: Vec<PathBuf>,
+
+	#[arg(long, help = "Descend into subdirectories of input directories")]
+	pub(crate) recursive: bool,
+}
+
+#[derive(Args)]
+pub struct VerifyArgs {
+	#[arg(short, long = "input", required = true, help = "PNG files, directories or glob patterns to process (repeatable)")]
+	pub(crate) inputs: Vec<PathBuf>,
+
+	#[arg(long, help = "Descend into subdirectories of input directories")]
+	pub(crate) recursive: bool,
+}
+
+#[derive(Args)]
+pub struct CapacityArgs {
+	#[arg(short, long, help = "Path to the PNG file to process")]
+	pub(crate) input: PathBuf,
+}
+
+#[derive(Args)]
+pub struct KeygenArgs {
+	#[arg(short, long, value_name = "FILE", help = "Write the identity to this file instead of stdout")]
+	pub(crate) output: Option<PathBuf>,
+}
+
+/// What happens to an existing file that a command writes over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputPolicy {
+	/// Replace it
+	Overwrite,
+	/// Copy it to FILE.bak first
+	Backup,
+	/// Fail; pass -o with a new file instead
+	Refuse,
+}
+
+/// How reports are written to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+	Text,
+	Json,
+}
+
+#[derive(Args)]
+pub struct DetectArgs {
 	#[arg(short, long, help = "Path to the PNG file to process")]
 	pub(crate) input: PathBuf,
-}
\ No newline at end of file
+
+	#[arg(short, long, value_enum, default_value_t = OutputFormat::Text, help = "Report format")]
+	pub(crate) format: OutputFormat,
+}
+
+#[derive(Args)]
+pub struct DiffArgs {
+	#[arg(help = "Original PNG file")]
+	pub(crate) before: PathBuf,
+
+	#[arg(help = "Changed PNG file")]
+	pub(crate) after: PathBuf,
+
+	#[arg(short, long, value_enum, default_value_t = OutputFormat::Text, help = "Report format")]
+	pub(crate) format: OutputFormat,
+}
+
+#[derive(Args)]
+pub struct ChunkTypeArgs {
+	#[command(subcommand)]
+	pub(crate) command: ChunkTypeCommand,
+}
+
+#[derive(Subcommand)]
+pub enum ChunkTypeCommand {
+	/// Explain what each letter of a chunk type means and whether it suits a message
+	Check(ChunkTypeCheckArgs),
+
+	/// Generate a random private, ancillary, safe-to-copy chunk type
+	Suggest(ChunkTypeSuggestArgs),
+}
+
+#[derive(Args)]
+pub struct ChunkTypeCheckArgs {
+	#[arg(help = "Chunk type (4 ASCII letters)")]
+	pub(crate) chunk_type: String,
+}
+
+#[derive(Args)]
+pub struct ChunkTypeSuggestArgs {
+	#[arg(short, long, help = "PNG file whose chunk types the suggestion must differ from")]
+	pub(crate) input: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct TuiArgs {
+	#[arg(short, long, help = "Path to the PNG file to process")]
+	pub(crate) input: PathBuf,
+}
+
+/// Shells that completions can be generated for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+	Bash,
+	Elvish,
+	Fish,
+	Powershell,
+	Zsh,
+}
+
+#[derive(Args)]
+pub struct CompletionsArgs {
+	#[arg(value_enum, help = "Shell to generate the completion script for")]
+	pub(crate) shell: Shell,
+}
+
+#[derive(Args)]
+pub struct ManArgs {
+	#[arg(long, value_name = "DIR", help = "Write a page for every command into DIR instead of printing the main page")]
+	pub(crate) dir: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ScriptArgs {
+	#[arg(short, long, help = "Path to the PNG file to process")]
+	pub(crate) input: PathBuf,
+
+	#[arg(short, long, value_name = "FILE", help = "Output file path (defaults to input file if not specified)")]
+	pub(crate) output: Option<PathBuf>,
+
+	#[arg(long, help = "Print the chunk-level changes instead of writing the file")]
+	pub(crate) dry_run: bool,
+
+	#[arg(help = "File of steps, one per line or separated by semicolons (standard input if omitted or -)")]
+	pub(crate) script: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct ExifArgs {
+	#[command(subcommand)]
+	pub(crate) command: ExifCommand,
+}
+
+#[derive(Subcommand)]
+pub enum ExifCommand {
+	/// List all Exif tags, or only the named ones
+	Get(ExifGetArgs),
+
+	/// Set a tag, creating the eXIf chunk if needed
+	Set(ExifSetArgs),
+
+	/// Delete tags
+	Delete(ExifDeleteArgs),
+
+	/// Remove all GPS location tags
+	StripGps(ExifStripGpsArgs),
+}
+
+#[derive(Args)]
+pub struct ExifGetArgs {
+	#[arg(short, long, help = "Path to the PNG file to process")]
+	pub(crate) input: PathBuf,
+
+	#[arg(help = "Tag names, e.g. Make or GPSLatitude")]
+	pub(crate) tags: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct ExifSetArgs {
+	#[arg(short, long, help = "Path to the PNG file to process")]
+	pub(crate) input: PathBuf,
+
+	#[arg(short, long, value_name = "FILE", help = "Output file path (defaults to input file if not specified)")]
+	pub(crate) output: Option<PathBuf>,
+
+	#[arg(long, help = "Print the chunk-level changes instead of writing the file")]
+	pub(crate) dry_run: bool,
+
+	#[arg(help = "Tag name, e.g. Artist")]
+	pub(crate) tag: String,
+
+	#[arg(help = "Text, or comma-separated numbers for numeric tags (rationals may be decimals or fractions)")]
+	pub(crate) value: String,
+}
+
+#[derive(Args)]
+pub struct ExifDeleteArgs {
+	#[arg(short, long, help = "Path to the PNG file to process")]
+	pub(crate) input: PathBuf,
+
+	#[arg(short, long, value_name = "FILE", help = "Output file path (defaults to input file if not specified)")]
+	pub(crate) output: Option<PathBuf>,
+
+	#[arg(long, help = "Print the chunk-level changes instead of writing the file")]
+	pub(crate) dry_run: bool,
+
+	#[arg(required = true, help = "Tag names")]
+	pub(crate) tags: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct ExifStripGpsArgs {
+	#[arg(short, long, help = "Path to the PNG file to process")]
+	pub(crate) input: PathBuf,
+
+	#[arg(short, long, value_name = "FILE", help = "Output file path (defaults to input file if not specified)")]
+	pub(crate) output: Option<PathBuf>,
+
+	#[arg(long, help = "Print the chunk-level changes instead of writing the file")]
+	pub(crate) dry_run: bool,
+}
+
+#[derive(Args)]
+pub struct IccArgs {
+	#[command(subcommand)]
+	pub(crate) command: IccCommand,
+}
+
+#[derive(Subcommand)]
+pub enum IccCommand {
+	/// Write the embedded ICC profile to a file
+	Extract(IccExtractArgs),
+
+	/// Embed an ICC profile, replacing any iCCP or sRGB chunk
+	Embed(IccEmbedArgs),
+}
+
+#[derive(Args)]
+pub struct IccExtractArgs {
+	#[arg(short, long, help = "Path to the PNG file to process")]
+	pub(crate) input: PathBuf,
+
+	#[arg(short, long, value_name = "FILE", help = "Where to write the profile")]
+	pub(crate) output: PathBuf,
+}
+
+#[derive(Args)]
+pub struct IccEmbedArgs {
+	#[arg(short, long, help = "Path to the PNG file to process")]
+	pub(crate) input: PathBuf,
+
+	#[arg(short, long, value_name = "FILE", help = "Output file path (defaults to input file if not specified)")]
+	pub(crate) output: Option<PathBuf>,
+
+	#[arg(long, help = "Print the chunk-level changes instead of writing the file")]
+	pub(crate) dry_run: bool,
+
+	#[arg(help = "ICC profile to embed")]
+	pub(crate) profile: PathBuf,
+
+	#[arg(short, long, help = "Profile name stored in the iCCP chunk (defaults to the profile's file name)")]
+	pub(crate) name: Option<String>,
+}
+
+#[derive(Args)]
+pub struct StripArgs {
+	#[arg(short, long = "input", required = true, help = "PNG files, directories or glob patterns to process (repeatable)")]
+	pub(crate) inputs: Vec<PathBuf>,
+
+	#[arg(long, help = "Descend into subdirectories of input directories")]
+	pub(crate) recursive: bool,
+
+	#[arg(short, long, value_name = "FILE", help = "Output file path (defaults to input file if not specified)")]
+	pub(crate) output: Option<PathBuf>,
+
+	#[arg(long, help = "Print the chunk-level changes instead of writing the file")]
+	pub(crate) dry_run: bool,
+
+	#[command(flatten)]
+	pub(crate) strip: StripOptions,
+}
+
+// Which chunks to strip, shared by `strip` and the strip step of scripts.
+#[derive(Args)]
+#[command(group = ArgGroup::new("profile").required(true).args(["all_ancillary", "private_only"]))]
+pub struct StripOptions {
+	#[arg(long, help = "Remove every ancillary chunk except tRNS")]
+	pub(crate) all_ancillary: bool,
+
+	#[arg(long, help = "Remove only private ancillary chunks")]
+	pub(crate) private_only: bool,
+
+	#[arg(long, help = "Keep colour chunks (gAMA, cHRM, sRGB, iCCP, sBIT, cICP, mDCv, cLLi)")]
+	pub(crate) keep_color: bool,
+
+	#[arg(long, value_name = "KEYWORDS", value_delimiter = ',', help = "Keep text chunks with these keywords (comma-separated)")]
+	pub(crate) keep_text: Vec<String>,
+}
+
+impl StripOptions {
+	pub(crate) fn policy(self) -> StripPolicy {
+		let scope = match (self.all_ancillary, self.private_only) {
+			(true, false) => StripScope::AllAncillary,
+			(false, true) => StripScope::PrivateOnly,
+			_ => unreachable!("the profile group requires exactly one of the flags"),
+		};
+		StripPolicy { scope, keep_color: self.keep_color, keep_text: self.keep_text }
+	}
+}
+
+#[derive(Args)]
+pub struct TransplantArgs {
+	#[arg(long, value_name = "FILE", help = "PNG file to copy chunks from")]
+	pub(crate) from: PathBuf,
+
+	#[arg(long, value_name = "FILE", help = "PNG file to copy chunks into")]
+	pub(crate) to: PathBuf,
+
+	#[arg(short, long, value_name = "FILE", help = "Output file path (defaults to the --to file if not specified)")]
+	pub(crate) output: Option<PathBuf>,
+
+	#[arg(long, help = "Print the chunk-level changes instead of writing the file")]
+	pub(crate) dry_run: bool,
+
+	#[arg(short, long, value_name = "TYPES", value_delimiter = ',', value_parser = ChunkType::from_str, help = "Only copy these chunk types (comma-separated; defaults to all ancillary chunks)")]
+	pub(crate) types: Vec<ChunkType>,
+
+	#[arg(long, help = "Copy unsafe-to-copy chunks even though the images differ")]
+	pub(crate) force: bool,
+}
+
+#[derive(Args)]
+pub struct XmpArgs {
+	#[command(subcommand)]
+	pub(crate) command: XmpCommand,
+}
+
+#[derive(Subcommand)]
+pub enum XmpCommand {
+	/// List all XMP properties, or only the named ones
+	Get(XmpGetArgs),
+
+	/// Set a property, creating the XMP packet if needed
+	Set(XmpSetArgs),
+}
+
+#[derive(Args)]
+pub struct XmpGetArgs {
+	#[arg(short, long, help = "Path to the PNG file to process")]
+	pub(crate) input: PathBuf,
+
+	#[arg(short, long = "namespace", value_name = "PREFIX=URI", value_parser = parse_namespace, help = "Declare a custom namespace prefix (repeatable)")]
+	pub(crate) namespaces: Vec<(String, String)>,
+
+	#[arg(help = "Properties as prefix:name, or title, creator, rights, description, subject")]
+	pub(crate) properties: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct XmpSetArgs {
+	#[arg(short, long, help = "Path to the PNG file to process")]
+	pub(crate) input: PathBuf,
+
+	#[arg(short, long, value_name = "FILE", help = "Output file path (defaults to input file if not specified)")]
+	pub(crate) output: Option<PathBuf>,
+
+	#[arg(long, help = "Print the chunk-level changes instead of writing the file")]
+	pub(crate) dry_run: bool,
+
+	#[arg(short, long = "namespace", value_name = "PREFIX=URI", value_parser = parse_namespace, help = "Declare a custom namespace prefix (repeatable)")]
+	pub(crate) namespaces: Vec<(String, String)>,
+
+	#[arg(help = "Property as prefix:name, or title, creator, rights, description, subject")]
+	pub(crate) property: String,
+
+	#[arg(required = true, help = "Value; creator and subject take several")]
+	pub(crate) values: Vec<String>,
+}
+
+pub(crate) fn parse_namespace(value: &str) -> Result<(String, String), String> {
+	value
+		.split_once('=')
+		.map(|(prefix, uri)| (prefix.to_string(), uri.to_string()))
+		.ok_or_else(|| String::from("expected PREFIX=URI"))
+}
@@ -0,0 +1,181 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+
+#[derive(thiserror::Error, Debug)]
+pub enum BatchError {
+	#[error(transparent)]
+	Pattern(#[from] glob::PatternError),
+
+	#[error("no files match \"{0}\"")]
+	NoMatch(String),
+
+	#[error("no PNG files found")]
+	Empty,
+
+	#[error("--output can only be used with a single input file")]
+	Output,
+
+	#[error("{failed} of {total} files failed")]
+	Failed {
+		failed: usize,
+		total: usize,
+	},
+
+	#[error("failed to read {path}: {source}")]
+	Io {
+		path: PathBuf,
+		source: std::io::Error,
+	},
+}
+
+fn is_png(path: &Path) -> bool {
+	path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+}
+
+fn is_pattern(path: &Path) -> bool {
+	path.to_string_lossy().contains(['*', '?', '['])
+}
+
+// PNG files in `directory`, descending into subdirectories if `recursive`.
+fn directory_files(directory: &Path, recursive: bool, files: &mut Vec<PathBuf>) -> Result<(), BatchError> {
+	let io_error = |source| BatchError::Io { path: directory.to_path_buf(), source };
+	let mut entries: Vec<PathBuf> = fs::read_dir(directory)
+		.map_err(io_error)?
+		.map(|entry| entry.map(|entry| entry.path()))
+		.collect::<Result<_, _>>()
+		.map_err(io_error)?;
+	entries.sort();
+	for path in entries {
+		if path.is_dir() {
+			if recursive {
+				directory_files(&path, recursive, files)?;
+			}
+		} else if is_png(&path) {
+			files.push(path);
+		}
+	}
+	Ok(())
+}
+
+/// Expands the given inputs into files: plain paths are kept, directories
+/// contribute their PNG files and glob patterns the files they match.
+pub fn expand(inputs: &[PathBuf], recursive: bool) -> Result<Vec<PathBuf>, BatchError> {
+	let mut files = Vec::new();
+	for input in inputs {
+		if input.is_dir() {
+			directory_files(input, recursive, &mut files)?;
+		} else if !input.exists() && is_pattern(input) {
+			let pattern = input.to_string_lossy();
+			let matches: Vec<PathBuf> = glob::glob(&pattern)?.filter_map(Result::ok).filter(|path| path.is_file()).collect();
+			if matches.is_empty() {
+				return Err(BatchError::NoMatch(pattern.into_owned()));
+			}
+			files.extend(matches);
+		} else {
+			files.push(input.clone());
+		}
+	}
+	// overlapping inputs must not queue a file twice, or two jobs would write
+	// it at the same time
+	let mut seen = HashSet::new();
+	files.retain(|file| seen.insert(fs::canonicalize(file).unwrap_or_else(|_| file.clone())));
+	if files.is_empty() {
+		return Err(BatchError::Empty);
+	}
+	Ok(files)
+}
+
+/// Runs `job` on every file of `files`. A single file behaves as if there
+/// were no batch: its output is printed and its error returned. Several
+/// files are processed in parallel, each file's output is printed under its
+/// name, followed by a summary table.
+pub fn run<F>(files: &[PathBuf], job: F) -> crate::Result<()>
+where
+	F: Fn(&Path) -> crate::Result<String> + Sync,
+{
+	if let [file] = files {
+		print!("{}", job(file)?);
+		return Ok(());
+	}
+
+	let results: Vec<Result<String, String>> = files.par_iter().map(|file| job(file).map_err(|e| e.to_string())).collect();
+	for (file, result) in files.iter().zip(&results) {
+		if let Ok(output) = result {
+			if !output.is_empty() {
+				println!("==> {} <==", file.display());
+				print!("{output}");
+			}
+		}
+	}
+
+	let width = files.iter().map(|file| file.display().to_string().len()).max().unwrap_or(0).max(4);
+	println!("{:<width$}  Status", "File");
+	for (file, result) in files.iter().zip(&results) {
+		match result {
+			Ok(_) => println!("{:<width$}  ok", file.display().to_string()),
+			Err(e) => println!("{:<width$}  failed: {e}", file.display().to_string()),
+		}
+	}
+	let failed = results.iter().filter(|result| result.is_err()).count();
+	println!("{} files, {} succeeded, {failed} failed", files.len(), files.len() - failed);
+	if failed > 0 {
+		return Err(BatchError::Failed { failed, total: files.len() }.into());
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+
+	fn testing_tree(name: &str) -> PathBuf {
+		let root = std::env::temp_dir().join(format!("pngme-batch-{name}-{}", std::process::id()));
+		fs::create_dir_all(root.join("nested")).unwrap();
+		for file in ["a.png", "b.PNG", "notes.txt", "nested/c.png"] {
+			fs::write(root.join(file), b"").unwrap();
+		}
+		root
+	}
+
+	#[test]
+	fn test_expand_directories() {
+		let root = testing_tree("dirs");
+		let names = |files: Vec<PathBuf>| -> Vec<String> {
+			files.iter().map(|file| file.strip_prefix(&root).unwrap().to_string_lossy().into_owned()).collect()
+		};
+		assert_eq!(names(expand(std::slice::from_ref(&root), false).unwrap()), ["a.png", "b.PNG"]);
+		assert_eq!(names(expand(std::slice::from_ref(&root), true).unwrap()), ["a.png", "b.PNG", "nested/c.png"]);
+		fs::remove_dir_all(root).unwrap();
+	}
+
+	#[test]
+	fn test_expand_globs() {
+		let root = testing_tree("globs");
+		let files = expand(&[root.join("*.png"), root.join("nested/*.png")], false).unwrap();
+		assert_eq!(files, [root.join("a.png"), root.join("nested/c.png")]);
+		assert!(matches!(expand(&[root.join("*.gif")], false), Err(BatchError::NoMatch(_))));
+		fs::remove_dir_all(root).unwrap();
+	}
+
+	#[test]
+	fn test_expand_overlapping_inputs() {
+		let root = testing_tree("overlap");
+		let files = expand(&[root.join("a.png"), root.clone(), root.join("nested/../a.png")], false).unwrap();
+		assert_eq!(files, [root.join("a.png"), root.join("b.PNG")]);
+		fs::remove_dir_all(root).unwrap();
+	}
+
+	#[test]
+	fn test_run_reports_failures() {
+		let inputs = [PathBuf::from("good.png"), PathBuf::from("bad.png")];
+		let result = run(&inputs, |path| match path.to_str() {
+			Some("good.png") => Ok(String::new()),
+			_ => Err("broken".into()),
+		});
+		let error = result.unwrap_err().downcast::<BatchError>().unwrap();
+		assert!(matches!(*error, BatchError::Failed { failed: 1, total: 2 }));
+	}
+}
@@ -11,6 +11,8 @@ pub struct Chunk {
 
 impl Chunk {
 	pub const OVERHEAD_BYTES: usize = 12;
+	/// Largest data length allowed by the PNG specification (2^31 - 1).
+	pub const MAX_LENGTH: u32 = i32::MAX as u32;
 	
 	pub fn new(chunk_type: ChunkType, chunk_data: Vec<u8>) -> Chunk {
 		Chunk {
@@ -94,7 +96,7 @@ pub enum ValidationError {
 	#[error(transparent)]
 	ChunkType(#[from] chunk_type::ChunkTypeError),
 
-	#[error("crc failed (expected '{expected}', got '{actual}'")]
+	#[error("CRC mismatch (computed {expected:#010x}, stored {actual:#010x})")]
 	CRC32Mismatch {
 		expected: u32,
 		actual: u32,
@@ -154,6 +156,20 @@ impl TryFrom<&[u8]> for Chunk {
 	}
 }
 
+impl Chunk {
+	/// Parses a chunk like `try_from` but keeps it when only the CRC is wrong,
+	/// returning whether the CRC matched. Used when reading payloads that carry
+	/// their own error correction.
+	pub fn try_from_damaged(value: &[u8]) -> Result<(Chunk, bool), ChunkError> {
+		let (length, type_code, data, crc) = Chunk::read(value)?;
+		match Chunk::validate(length, type_code, data.clone(), crc) {
+			Ok(chunk) => Ok((chunk, true)),
+			Err(ValidationError::CRC32Mismatch { .. }) => Ok((Chunk::new(ChunkType::try_from(type_code).map_err(ValidationError::from)?, data), false)),
+			Err(error) => Err(error.into()),
+		}
+	}
+}
+
 impl std::fmt::Display for Chunk {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		writeln!(f, "Chunk {{",)?;
@@ -1,9 +1,37 @@
+use rand_core::RngCore;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ChunkType {
 	type_code: [u8; 4],
 }
 
 impl ChunkType {
+	pub const IHDR: ChunkType = ChunkType { type_code: *b"IHDR" };
+	pub const PLTE: ChunkType = ChunkType { type_code: *b"PLTE" };
+	pub const IDAT: ChunkType = ChunkType { type_code: *b"IDAT" };
+	pub const IEND: ChunkType = ChunkType { type_code: *b"IEND" };
+	pub const ACTL: ChunkType = ChunkType { type_code: *b"acTL" };
+	pub const FCTL: ChunkType = ChunkType { type_code: *b"fcTL" };
+	pub const FDAT: ChunkType = ChunkType { type_code: *b"fdAT" };
+	pub const TRNS: ChunkType = ChunkType { type_code: *b"tRNS" };
+	pub const GAMA: ChunkType = ChunkType { type_code: *b"gAMA" };
+	pub const CHRM: ChunkType = ChunkType { type_code: *b"cHRM" };
+	pub const SRGB: ChunkType = ChunkType { type_code: *b"sRGB" };
+	pub const ICCP: ChunkType = ChunkType { type_code: *b"iCCP" };
+	pub const SBIT: ChunkType = ChunkType { type_code: *b"sBIT" };
+	pub const BKGD: ChunkType = ChunkType { type_code: *b"bKGD" };
+	pub const HIST: ChunkType = ChunkType { type_code: *b"hIST" };
+	pub const PHYS: ChunkType = ChunkType { type_code: *b"pHYs" };
+	pub const SPLT: ChunkType = ChunkType { type_code: *b"sPLT" };
+	pub const TIME: ChunkType = ChunkType { type_code: *b"tIME" };
+	pub const EXIF: ChunkType = ChunkType { type_code: *b"eXIf" };
+	pub const CICP: ChunkType = ChunkType { type_code: *b"cICP" };
+	pub const MDCV: ChunkType = ChunkType { type_code: *b"mDCv" };
+	pub const CLLI: ChunkType = ChunkType { type_code: *b"cLLi" };
+	pub const TEXT: ChunkType = ChunkType { type_code: *b"tEXt" };
+	pub const ZTXT: ChunkType = ChunkType { type_code: *b"zTXt" };
+	pub const ITXT: ChunkType = ChunkType { type_code: *b"iTXt" };
+
 	pub fn bytes(&self) -> [u8; 4] {
 		self.type_code
 	}
@@ -24,10 +52,113 @@ impl ChunkType {
 		self.type_code[3].is_ascii_lowercase()
 	}
 
+	#[allow(dead_code)]
 	pub fn is_valid(&self) -> bool {
 		self.type_code.iter().all(|b| b.is_ascii_alphabetic())
 			&& self.is_reserved_bit_valid()
 	}
+
+	/// Name of the type if it is defined by the PNG specification or one of
+	/// its registered extensions.
+	pub fn registered_name(&self) -> Option<&'static str> {
+		REGISTERED.iter().find(|(chunk_type, _)| chunk_type == self).map(|(_, name)| *name)
+	}
+
+	/// Why the type is a poor choice for application data, if it is.
+	pub fn payload_warning(&self) -> Option<String> {
+		if let Some(name) = self.registered_name() {
+			Some(format!("it is the standard {name} chunk"))
+		} else if self.is_critical() {
+			Some(String::from("it is critical, so decoders that don't know it will reject the image"))
+		} else if self.is_public() {
+			Some(String::from("it is public, so its meaning is reserved for the PNG specification"))
+		} else {
+			None
+		}
+	}
+
+	/// A random ancillary, private, safe-to-copy type that is not in `taken`.
+	pub fn suggest(rng: &mut impl RngCore, taken: &[ChunkType]) -> ChunkType {
+		loop {
+			let mut type_code = [0u8; 4];
+			for (byte, random) in type_code.iter_mut().zip(rng.next_u32().to_le_bytes()) {
+				*byte = b'a' + random % 26;
+			}
+			type_code[2] = type_code[2].to_ascii_uppercase();
+			let chunk_type = ChunkType { type_code };
+			if !taken.contains(&chunk_type) {
+				return chunk_type;
+			}
+		}
+	}
+}
+
+/// APNG chunks, which only make sense together with the frames they describe.
+pub const ANIMATION: [ChunkType; 3] = [ChunkType::ACTL, ChunkType::FCTL, ChunkType::FDAT];
+
+/// Chunk types defined by the PNG specification and its registered
+/// extensions, with what they hold.
+pub const REGISTERED: [(ChunkType, &str); 34] = [
+	(ChunkType::IHDR, "image header"),
+	(ChunkType::PLTE, "palette"),
+	(ChunkType::IDAT, "image data"),
+	(ChunkType::IEND, "image trailer"),
+	(ChunkType::TRNS, "transparency"),
+	(ChunkType::CHRM, "chromaticities"),
+	(ChunkType::GAMA, "gamma"),
+	(ChunkType::ICCP, "ICC profile"),
+	(ChunkType::SBIT, "significant bits"),
+	(ChunkType::SRGB, "sRGB colour space"),
+	(ChunkType::CICP, "coding-independent code points"),
+	(ChunkType::MDCV, "mastering display colour volume"),
+	(ChunkType::CLLI, "content light level"),
+	(ChunkType::TEXT, "text"),
+	(ChunkType::ZTXT, "compressed text"),
+	(ChunkType::ITXT, "international text"),
+	(ChunkType::BKGD, "background colour"),
+	(ChunkType::HIST, "palette histogram"),
+	(ChunkType::PHYS, "physical pixel dimensions"),
+	(ChunkType::SPLT, "suggested palette"),
+	(ChunkType::EXIF, "Exif"),
+	(ChunkType::TIME, "modification time"),
+	(ChunkType::ACTL, "animation control"),
+	(ChunkType::FCTL, "frame control"),
+	(ChunkType::FDAT, "frame data"),
+	(ChunkType { type_code: *b"oFFs" }, "image offset"),
+	(ChunkType { type_code: *b"pCAL" }, "pixel calibration"),
+	(ChunkType { type_code: *b"sCAL" }, "physical scale"),
+	(ChunkType { type_code: *b"gIFg" }, "GIF graphic control extension"),
+	(ChunkType { type_code: *b"gIFx" }, "GIF application extension"),
+	(ChunkType { type_code: *b"gIFt" }, "GIF plain text"),
+	(ChunkType { type_code: *b"sTER" }, "stereo image"),
+	(ChunkType { type_code: *b"dSIG" }, "digital signature"),
+	(ChunkType { type_code: *b"fRAc" }, "fractal image parameters"),
+];
+
+// What each letter's case means: (property, meaning) for lowercase, then for
+// uppercase.
+const PROPERTY_BITS: [[(&str, &str); 2]; 4] = [
+	[("ancillary", "decoders may ignore it"), ("critical", "decoders that don't know it must reject the image")],
+	[("private", "free for applications to define"), ("public", "reserved for types defined by the PNG specification")],
+	[("reserved bit set", "invalid, the third letter must be uppercase"), ("reserved bit clear", "as required")],
+	[("safe to copy", "editors may keep it after changing the image"), ("unsafe to copy", "editors must drop it when the image data changes")],
+];
+
+/// Explains the property bit of each letter of `s`, which only needs to be
+/// four ASCII letters, so that invalid types can be explained too.
+pub fn explain(s: &str) -> Result<Vec<String>, ChunkTypeError> {
+	let type_code = ChunkType::parse(s)?;
+	if !type_code.iter().all(|b| b.is_ascii_alphabetic()) {
+		return Err(ValidationError::NonAscii.into());
+	}
+	Ok(type_code
+		.iter()
+		.zip(PROPERTY_BITS)
+		.map(|(&letter, bit)| {
+			let (property, meaning) = bit[letter.is_ascii_uppercase() as usize];
+			format!("{}  {property:<19} {meaning}", letter as char)
+		})
+		.collect())
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -91,6 +222,7 @@ impl TryFrom<[u8; 4]> for ChunkType {
 impl std::str::FromStr for ChunkType {
 	type Err = ChunkTypeError;
 
+	#[allow(clippy::needless_question_mark)]
 	fn from_str(s: &str) -> Result<Self, Self::Err> {
 		let type_code = Self::parse(s)?;
 		Ok(ChunkType::try_from(type_code)?)
@@ -104,6 +236,12 @@ impl std::fmt::Display for ChunkType {
 	}
 }
 
+impl serde::Serialize for ChunkType {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -195,6 +333,46 @@ mod tests {
 		assert_eq!(&chunk.to_string(), "RuSt");
 	}
 
+	#[test]
+	pub fn test_explain() {
+		let lines = explain("Rust").unwrap();
+		assert_eq!(lines[0], "R  critical            decoders that don't know it must reject the image");
+		assert!(lines[2].contains("invalid"));
+		assert!(explain("Ru1t").is_err());
+		assert!(explain("Rusty").is_err());
+	}
+
+	#[test]
+	pub fn test_payload_warning() {
+		assert!(ChunkType::from_str("ruSt").unwrap().payload_warning().is_none());
+		assert!(ChunkType::from_str("RuSt").unwrap().payload_warning().is_some());
+		assert!(ChunkType::from_str("rUSt").unwrap().payload_warning().is_some());
+		assert_eq!(ChunkType::from_str("sCAL").unwrap().payload_warning().unwrap(), "it is the standard physical scale chunk");
+	}
+
+	#[test]
+	pub fn test_registered_types() {
+		// APNG's types predate its registration, so they look private
+		for (chunk_type, _) in REGISTERED {
+			assert!(chunk_type.is_valid() && (chunk_type.is_public() || ANIMATION.contains(&chunk_type)), "{chunk_type}");
+		}
+		assert_eq!(ChunkType::IDAT.registered_name(), Some("image data"));
+		assert_eq!(ChunkType::from_str("ruSt").unwrap().registered_name(), None);
+	}
+
+	#[test]
+	pub fn test_suggest() {
+		use rand_chacha::ChaCha20Rng;
+		use rand_core::SeedableRng;
+
+		let mut rng = ChaCha20Rng::seed_from_u64(3);
+		let first = ChunkType::suggest(&mut rng, &[]);
+		assert!(first.is_valid() && first.is_safe_to_copy() && first.payload_warning().is_none());
+
+		let mut rng = ChaCha20Rng::seed_from_u64(3);
+		assert_ne!(ChunkType::suggest(&mut rng, std::slice::from_ref(&first)), first);
+	}
+
 	#[test]
 	pub fn test_chunk_type_trait_impls() {
 		let chunk_type_1: ChunkType = TryFrom::try_from([82, 117, 83, 116]).unwrap();
@@ -1,13 +1,36 @@
+use std::fmt::Write;
 use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 use clap::Subcommand;
-use crate::args::{EncodeArgs, DecodeArgs, RemoveArgs, PrintArgs};
+use flate2::Compression;
+use rand_core::OsRng;
+use crate::args::{OutputPolicy, EncodeArgs, DecodeArgs, RemoveArgs, PrintArgs, VerifyArgs, CapacityArgs, KeygenArgs, DetectArgs, DiffArgs, OutputFormat, ChunkTypeArgs, ChunkTypeCommand, TuiArgs, ScriptArgs, CompletionsArgs, ManArgs, ExifArgs, ExifCommand, IccArgs, IccCommand, StripArgs, TransplantArgs, XmpArgs, XmpCommand};
 use crate::png::Png;
+use crate::batch::{self, BatchError};
 use crate::chunk::Chunk;
+use crate::chunk_type::{self, ChunkType};
+use crate::completions;
+use crate::config::Config;
+use crate::crypto::{self, Identity, PublicKey};
+use crate::detect;
+use crate::diff;
+use crate::embed::{self, ScatterKey, Target};
+use crate::envelope::Envelope;
+use crate::exif::{Exif, ExifError, TagInfo};
+use crate::icc::IccProfile;
+use crate::image::{ImageHeader, Raster};
+use crate::standard_chunk::{self, StandardChunk};
+use crate::script;
+use crate::strip;
+use crate::transplant::{self, Action};
+use crate::tui;
+use crate::xmp::{self, Property, Xmp};
 
 #[derive(Subcommand)]
 #[command(about = "PNG file manipulation commands")]
 pub enum Commands {
-	#[command(visible_alias = "e")]
+	#[command(visible_alias = "e", allow_missing_positional = true)]
 	Encode(EncodeArgs),
 
 	#[command(visible_alias = "d")]
@@ -18,49 +41,697 @@ pub enum Commands {
 
 	#[command(visible_alias = "p")]
 	Print(PrintArgs),
+
+	/// Check files for CRC errors, misplaced chunks and undecodable image data
+	Verify(VerifyArgs),
+
+	/// Report how many bytes each embedding method can hide in an image
+	Capacity(CapacityArgs),
+
+	/// Generate an X25519 identity for encrypted messages
+	Keygen(KeygenArgs),
+
+	/// Scan an image for traces of hidden data
+	Detect(DetectArgs),
+
+	/// Show the chunks that differ between two PNG files
+	Diff(DiffArgs),
+
+	/// Check chunk types or suggest one for hidden messages
+	ChunkType(ChunkTypeArgs),
+
+	/// Read and edit Exif metadata in the eXIf chunk
+	Exif(ExifArgs),
+
+	/// Extract or embed the ICC colour profile
+	Icc(IccArgs),
+
+	/// Remove metadata chunks while keeping the image renderable
+	Strip(StripArgs),
+
+	/// Copy ancillary chunks from one image to another
+	Transplant(TransplantArgs),
+
+	/// Read and edit XMP metadata in the iTXt chunk
+	Xmp(XmpArgs),
+
+	/// Browse and edit the chunks of a file in the terminal
+	Tui(TuiArgs),
+
+	/// Apply a sequence of edits to a file, writing it once
+	#[command(after_help = "Steps take the arguments of the command of the same name:\n  \
+		remove TYPE\n  \
+		encode [TYPE] MESSAGE [--method ...] [--force] ...\n  \
+		text set KEYWORD TEXT | text delete KEYWORD\n  \
+		strip --all-ancillary | --private-only [--keep-color] [--keep-text ...]\n  \
+		exif set TAG VALUE | exif delete TAG... | exif strip-gps\n  \
+		xmp set PROPERTY VALUE...")]
+	Script(ScriptArgs),
+
+	/// Print a shell completion script, completing chunk types from the input file
+	Completions(CompletionsArgs),
+
+	/// Generate man pages
+	Man(ManArgs),
 }
 
-pub fn encode(args: EncodeArgs) -> crate::Result<()> {
-	let input_path = args.input.as_path();
-	let output_path = args.output.as_deref().unwrap_or(input_path);
+#[derive(thiserror::Error, Debug)]
+pub enum CommandError {
+	#[error("cannot read {}: {source}", path.display())]
+	Read {
+		path: PathBuf,
+		source: std::io::Error,
+	},
 
-	let file_content = fs::read(input_path)?;
-	let mut png = Png::try_from(file_content.as_slice())?;
-	let chunk = Chunk::new(args.chunk_type, args.message.as_bytes().to_vec());
-	png.append_chunk(chunk);
-	fs::write(output_path, png.as_bytes())?;
-	Ok(())
+	#[error("cannot write {}: {source}", path.display())]
+	Write {
+		path: PathBuf,
+		source: std::io::Error,
+	},
+
+	#[error("no {0} chunk found")]
+	NoChunk(ChunkType),
+
+	#[error("no message found")]
+	NoMessage,
+
+	#[error("refusing to overwrite {}", .0.display())]
+	Exists(PathBuf),
+
+	#[error("refusing to embed a message as {chunk_type}: {reason}")]
+	ReservedChunkType {
+		chunk_type: ChunkType,
+		reason: String,
+	},
 }
 
-pub fn decode(args: DecodeArgs) -> crate::Result<()> {
-	let file_content = fs::read(&args.input.as_path())?;
-	let mut png = Png::try_from(file_content.as_slice())?;
-	let chunk = png.remove_first_chunk(&args.chunk_type);
-	if let Some(chunk) = chunk {
-		println!("{}", chunk.data_as_str()?);
+/// Refuses critical and standard chunk types for messages, as they break or
+/// mislead every decoder, unless `force` is set. Other poor choices only
+/// produce a warning.
+pub(crate) fn check_message_type(chunk_type: &ChunkType, force: bool) -> Result<Option<String>, CommandError> {
+	let Some(reason) = chunk_type.payload_warning() else {
+		return Ok(None);
+	};
+	if !force && (chunk_type.is_critical() || chunk_type.registered_name().is_some()) {
+		return Err(CommandError::ReservedChunkType { chunk_type: chunk_type.clone(), reason });
+	}
+	Ok(Some(format!("{chunk_type} is a poor chunk type for a message: {reason}.")))
+}
+
+pub(crate) fn read_file(path: &Path) -> Result<Vec<u8>, CommandError> {
+	fs::read(path).map_err(|source| CommandError::Read { path: path.to_path_buf(), source })
+}
+
+pub(crate) fn write_file(path: &Path, contents: impl AsRef<[u8]>) -> Result<(), CommandError> {
+	fs::write(path, contents).map_err(|source| CommandError::Write { path: path.to_path_buf(), source })
+}
+
+// Writes `contents` to `path`, handling an existing file as `policy` says.
+// Returns a note about the backup, if one was made.
+pub(crate) fn write_output(path: &Path, contents: impl AsRef<[u8]>, policy: OutputPolicy) -> crate::Result<String> {
+	let mut output = String::new();
+	if path.exists() {
+		match policy {
+			OutputPolicy::Overwrite => {}
+			OutputPolicy::Backup => {
+				let mut backup = path.as_os_str().to_owned();
+				backup.push(".bak");
+				fs::copy(path, &backup).map_err(|source| CommandError::Write { path: backup.clone().into(), source })?;
+				writeln!(output, "Backed up {} to {}.", path.display(), Path::new(&backup).display())?;
+			}
+			OutputPolicy::Refuse => return Err(CommandError::Exists(path.to_path_buf()).into()),
+		}
+	}
+	write_file(path, contents)?;
+	Ok(output)
+}
+
+// Writes `png` to `path` as `policy` allows, or on a dry run describes how it
+// differs from `original` instead.
+pub(crate) fn save(original: &Png, png: &Png, path: &Path, dry_run: bool, policy: OutputPolicy) -> crate::Result<String> {
+	if !dry_run {
+		return write_output(path, png.as_bytes(), policy);
+	}
+	let changes = diff::diff(original, png);
+	if changes.is_empty() {
+		return Ok(format!("Dry run: {} would not change.\n", path.display()));
+	}
+	let mut output = format!(
+		"Dry run: {} would change from {} to {} bytes.\n",
+		path.display(),
+		original.as_bytes().len(),
+		png.as_bytes().len(),
+	);
+	for change in changes {
+		writeln!(output, "  {change}")?;
+	}
+	Ok(output)
+}
+
+// Wraps a message for embedding, encrypting it to `recipients` if there are
+// any and adding error correction.
+pub(crate) fn payload(message: &str, recipients: &[PublicKey], ecc: Option<u8>) -> crate::Result<Vec<u8>> {
+	let mut data = if recipients.is_empty() {
+		Envelope::frame_plain(message.as_bytes())
 	} else {
-		println!("No message found.")
+		crypto::seal(message.as_bytes(), recipients)?.as_bytes()
+	};
+	if let Some(parity) = ecc {
+		data = Envelope::protected(&data, parity).as_bytes();
+	}
+	Ok(data)
+}
+
+pub fn encode(args: EncodeArgs, policy: OutputPolicy) -> crate::Result<()> {
+	let files = batch::expand(&args.inputs, args.recursive)?;
+	if args.output.is_some() && files.len() > 1 {
+		return Err(BatchError::Output.into());
+	}
+
+	if let Some(chunk_type) = &args.embed.chunk_type {
+		if let Some(warning) = check_message_type(chunk_type, args.embed.force)? {
+			eprintln!("Warning: {warning}");
+		}
+	}
+	let key = args.embed.key.as_deref().map(ScatterKey::from_passphrase);
+	let target = Target::new(args.embed.method, args.embed.bits, args.embed.alpha_lsb, args.embed.chunk_type, key)?;
+	batch::run(&files, |input_path| {
+		let output_path = args.output.as_deref().unwrap_or(input_path);
+		let file_content = read_file(input_path)?;
+		let original = Png::try_from(file_content.as_slice())?;
+		let mut png = original.clone();
+		let recipients = if args.embed.plain { &[][..] } else { &args.embed.recipients };
+		embed::embed(&mut png, &target, &payload(&args.embed.message, recipients, args.embed.ecc)?, Compression::new(args.embed.compression))?;
+		save(&original, &png, output_path, args.dry_run, policy)
+	})
+}
+
+// Where the private key to decrypt with comes from. Identity files are only
+// read once a message turns out to be encrypted.
+#[derive(Clone, Copy)]
+enum IdentitySource<'a> {
+	Key(&'a Identity),
+	File(&'a Path),
+}
+
+// Unwraps an extracted payload layer by layer, correcting protected envelopes
+// and decrypting encrypted ones.
+fn open_payload(data: Vec<u8>, identity: Option<IdentitySource>) -> crate::Result<Vec<u8>> {
+	let Some(mut envelope) = Envelope::parse_stored(&data) else {
+		return Ok(data);
+	};
+	loop {
+		let data = match (envelope.protection(), envelope.encryption(), identity) {
+			(Some(_), _, _) => {
+				let (payload, corrected) = envelope.recover()?;
+				if corrected > 0 {
+					eprintln!("Error correction: {corrected} bytes corrected.");
+				}
+				payload
+			}
+			(None, None, _) => return Ok(envelope.body().to_vec()),
+			(None, Some(_), Some(IdentitySource::Key(identity))) => return Ok(crypto::open(&envelope, identity)?),
+			(None, Some(_), Some(IdentitySource::File(path))) => {
+				let identity: Identity = String::from_utf8(read_file(path)?)?.parse()?;
+				return Ok(crypto::open(&envelope, &identity)?);
+			}
+			(None, Some(_), None) => return Err("message is encrypted; pass --identity or --identity-file to decrypt it".into()),
+		};
+		if !Envelope::is_envelope(&data) {
+			return Ok(data);
+		}
+		envelope = Envelope::try_from(data.as_slice())?;
+	}
+}
+
+pub fn decode(args: DecodeArgs) -> crate::Result<()> {
+	let key = args.key.as_deref().map(ScatterKey::from_passphrase);
+	let target = Target::new(args.method, args.bits, args.alpha_lsb, args.chunk_type, key)?;
+	batch::run(&batch::expand(&args.inputs, args.recursive)?, |input| {
+		let file_content = read_file(input)?;
+		// chunks with bad CRCs are kept so that error correction gets a chance
+		let (png, damaged) = Png::try_from_damaged(file_content.as_slice())?;
+		for index in damaged {
+			eprintln!("Warning: chunk {index} ({}) fails its CRC check.", png.chunks()[index].chunk_type());
+		}
+		if let Some(data) = embed::extract(&png, &target)? {
+			let identity = args.identity.as_ref().map(IdentitySource::Key).or(args.identity_file.as_deref().map(IdentitySource::File));
+			let message = open_payload(data, identity)?;
+			Ok(format!("{}\n", String::from_utf8(message)?))
+		} else {
+			Err(CommandError::NoMessage.into())
+		}
+	})
+}
+
+pub fn remove(args: RemoveArgs, policy: OutputPolicy) -> crate::Result<()> {
+	batch::run(&batch::expand(&args.inputs, args.recursive)?, |input| {
+		let file_content = read_file(input)?;
+		let original = Png::try_from(file_content.as_slice())?;
+		let mut png = original.clone();
+		if let Some(chunk) = png.remove_first_chunk(&args.chunk_type) {
+			let output = save(&original, &png, input, args.dry_run, policy)?;
+			let removed = if args.dry_run { "would be removed" } else { "removed" };
+			if Envelope::parse_stored(chunk.data()).is_some() {
+				Ok(format!("{output}Chunk with {} byte payload {removed}.\n", chunk.length()))
+			} else {
+				Ok(format!("{output}Chunk with content \"{}\" {removed}.\n", chunk.data_as_str()?))
+			}
+		} else {
+			Err(CommandError::NoChunk(args.chunk_type.clone()).into())
+		}
+	})
+}
+
+pub fn print(args: PrintArgs) -> crate::Result<()> {
+	batch::run(&batch::expand(&args.inputs, args.recursive)?, |input| {
+		let input_bytes = read_file(input)?;
+		let png = Png::try_from(input_bytes.as_slice())?;
+		let header = ImageHeader::from_png(&png).ok();
+		let mut output = String::new();
+		for chunk in png.chunks() {
+			writeln!(output, "{}", chunk)?;
+			match header.as_ref().map(|header| StandardChunk::parse(chunk, header)) {
+				Some(Ok(Some(standard))) => writeln!(output, "  {standard}")?,
+				Some(Err(e)) => writeln!(output, "  invalid: {e}")?,
+				_ => {}
+			}
+		}
+		Ok(output)
+	})
+}
+
+pub fn verify(args: VerifyArgs) -> crate::Result<()> {
+	batch::run(&batch::expand(&args.inputs, args.recursive)?, |input| {
+		let (png, damaged) = Png::try_from_damaged(read_file(input)?.as_slice())?;
+		let chunks = png.chunks();
+		let mut problems: Vec<String> = damaged
+			.into_iter()
+			.map(|index| format!("chunk {index} ({}) fails its CRC check", chunks[index].chunk_type()))
+			.collect();
+		if chunks.first().map(Chunk::chunk_type) != Some(&ChunkType::IHDR) {
+			problems.push(String::from("IHDR is not the first chunk"));
+		}
+		// the chunk method appends after IEND, which decoders skip, so chunks
+		// there only earn a warning
+		let mut warning = String::new();
+		match chunks.iter().position(|chunk| chunk.chunk_type() == &ChunkType::IEND) {
+			None => problems.push(String::from("IEND is missing")),
+			Some(end) if end + 1 < chunks.len() => {
+				let after: Vec<String> = chunks[end + 1..].iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+				warning = format!(" (warning: {} after IEND)", after.join(", "));
+			}
+			Some(_) => {}
+		}
+		match ImageHeader::from_png(&png) {
+			Ok(header) => {
+				problems.extend(chunks.iter().filter_map(|chunk| StandardChunk::parse(chunk, &header).err()).map(|e| e.to_string()));
+				if let Err(e) = Raster::from_png(&png) {
+					problems.push(e.to_string());
+				}
+				if problems.is_empty() {
+					return Ok(format!(
+						"OK: {} chunks, {}x{} {}-bit {}{warning}\n",
+						chunks.len(),
+						header.width,
+						header.height,
+						header.bit_depth,
+						header.color_type,
+					));
+				}
+			}
+			Err(e) => problems.push(e.to_string()),
+		}
+		Err(problems.join("; ").into())
+	})
+}
+
+pub fn capacity(args: CapacityArgs) -> crate::Result<()> {
+	let input_bytes = read_file(&args.input)?;
+	let png = Png::try_from(input_bytes.as_slice())?;
+	let header = ImageHeader::from_png(&png)?;
+	println!(
+		"{}x{} {}-bit {}{}",
+		header.width,
+		header.height,
+		header.bit_depth,
+		header.color_type,
+		if header.interlaced { ", interlaced" } else { "" },
+	);
+
+	println!("  {:<15} {} bytes", "chunk", Chunk::MAX_LENGTH);
+	let mut targets: Vec<(String, Target)> = (1..=4).map(|bits| (format!("lsb (bits={bits})"), Target::Lsb { bits, key: None })).collect();
+	targets.push((String::from("alpha"), Target::Alpha { alpha_lsb: false, key: None }));
+	targets.push((String::from("alpha (+lsb)"), Target::Alpha { alpha_lsb: true, key: None }));
+	targets.extend((1..=4).map(|bits| (format!("apng (bits={bits})"), Target::Apng { bits, key: None })));
+	targets.push((String::from("trailing"), Target::Trailing));
+	targets.push((String::from("palette"), Target::Palette { key: None }));
+	targets.push((String::from("palette-order"), Target::PaletteOrder { key: None }));
+	for (label, target) in targets {
+		match embed::capacity(&png, &target) {
+			Ok(bytes) => println!("  {label:<15} {bytes} bytes"),
+			Err(error) => println!("  {label:<15} unavailable: {error}"),
+		}
 	}
 	Ok(())
 }
 
-pub fn remove(args: RemoveArgs) -> crate::Result<()> {
-	let file_content = fs::read(&args.input.as_path())?;
-	let mut png = Png::try_from(file_content.as_slice())?;
-	if let Some(chunk) = png.remove_first_chunk(&args.chunk_type) {
-		fs::write(&args.input, png.as_bytes())?;
-		println!("Chunk with content \"{}\" removed.", chunk.data_as_str()?);
+pub fn keygen(args: KeygenArgs) -> crate::Result<()> {
+	let identity = Identity::generate();
+	if let Some(path) = args.output {
+		write_file(&path, identity.to_file_contents())?;
+		println!("Public key: {}", identity.public_key());
 	} else {
-		println!("No chunk found.");
+		print!("{}", identity.to_file_contents());
 	}
 	Ok(())
 }
 
-pub fn print(args: PrintArgs) -> crate::Result<()> {
-	let input_bytes = fs::read(&args.input.as_path())?;
-	let png = Png::try_from(input_bytes.as_slice())?;
-	for chunk in png.chunks() {
-        println!("{}", chunk);
-    }
+pub fn detect(args: DetectArgs) -> crate::Result<()> {
+	let input_bytes = read_file(&args.input)?;
+	let report = detect::analyze(&input_bytes)?;
+	match args.format {
+		OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+		OutputFormat::Text => {
+			println!("Suspicion score: {}/100 ({})", report.score, report.verdict);
+			for finding in &report.findings {
+				println!("  [{:>3}] {:<18} {}", finding.score, finding.check, finding.detail);
+			}
+			match report.statistics {
+				Some(statistics) => {
+					println!("LSB statistics:");
+					println!("  chi-square embedding probability  {:.3}", statistics.chi_square);
+					println!("  RS estimated payload              {:.1}% of colour LSBs", statistics.rs_estimate * 100.0);
+				}
+				None => println!("LSB statistics: not available for this image type"),
+			}
+		}
+	}
+	Ok(())
+}
+
+pub fn diff(args: DiffArgs) -> crate::Result<()> {
+	let before = Png::try_from(read_file(&args.before)?.as_slice())?;
+	let after = Png::try_from(read_file(&args.after)?.as_slice())?;
+	let report = diff::compare(&before, &after);
+	match args.format {
+		OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+		OutputFormat::Text => {
+			if report.changes.is_empty() {
+				println!("No chunks differ.");
+			}
+			for change in &report.changes {
+				println!("  {change}");
+			}
+			println!("Image data: {}", report.image_data);
+		}
+	}
+	Ok(())
+}
+
+pub fn chunk_type(args: ChunkTypeArgs) -> crate::Result<()> {
+	match args.command {
+		ChunkTypeCommand::Check(args) => {
+			for line in chunk_type::explain(&args.chunk_type)? {
+				println!("  {line}");
+			}
+			let chunk_type: ChunkType = args.chunk_type.parse()?;
+			match chunk_type.payload_warning() {
+				Some(warning) => println!("{chunk_type} is valid, but a poor type for a message: {warning}."),
+				None => println!("{chunk_type} is valid and suits a message."),
+			}
+		}
+		ChunkTypeCommand::Suggest(args) => {
+			let taken = match &args.input {
+				Some(path) => {
+					let png = Png::try_from(read_file(path)?.as_slice())?;
+					png.chunks().iter().map(|chunk| chunk.chunk_type().clone()).collect()
+				}
+				None => Vec::new(),
+			};
+			println!("{}", ChunkType::suggest(&mut OsRng, &taken));
+		}
+	}
+	Ok(())
+}
+
+pub(crate) fn tag_info(name: &str) -> Result<&'static TagInfo, ExifError> {
+	TagInfo::find(name).ok_or_else(|| ExifError::UnknownTag(name.to_string()))
+}
+
+pub(crate) fn read_exif(png: &Png) -> crate::Result<Exif> {
+	Ok(match png.chunk_by_type(&ChunkType::EXIF) {
+		Some(chunk) => Exif::try_from(chunk.data())?,
+		None => Exif::new(),
+	})
+}
+
+/// Replaces the eXIf chunk, dropping it altogether once no tags are left.
+pub(crate) fn replace_exif(png: &mut Png, exif: &Exif) {
+	while png.remove_first_chunk(&ChunkType::EXIF).is_some() {}
+	if !exif.is_empty() {
+		png.insert_before_image_data(Chunk::new(ChunkType::EXIF, exif.as_bytes()));
+	}
+}
+
+fn write_exif(original: &Png, exif: &Exif, output_path: &Path, dry_run: bool, policy: OutputPolicy) -> crate::Result<()> {
+	let mut png = original.clone();
+	replace_exif(&mut png, exif);
+	print!("{}", save(original, &png, output_path, dry_run, policy)?);
+	Ok(())
+}
+
+pub fn exif(args: ExifArgs, policy: OutputPolicy) -> crate::Result<()> {
+	match args.command {
+		ExifCommand::Get(args) => {
+			let png = Png::try_from(read_file(&args.input)?.as_slice())?;
+			let exif = read_exif(&png)?;
+			if args.tags.is_empty() {
+				if exif.is_empty() {
+					println!("No Exif data found.");
+				}
+				for (ifd, entry) in exif.entries() {
+					println!("{:<8} {:<20} {}", ifd, entry.name(ifd), entry.display(exif.order()));
+				}
+			}
+			for name in &args.tags {
+				let info = tag_info(name)?;
+				match exif.get(info) {
+					Some(entry) => println!("{:<20} {}", info.name, entry.display(exif.order())),
+					None => println!("{:<20} (not set)", info.name),
+				}
+			}
+		}
+		ExifCommand::Set(args) => {
+			let png = Png::try_from(read_file(&args.input)?.as_slice())?;
+			let mut exif = read_exif(&png)?;
+			exif.set(tag_info(&args.tag)?, &args.value)?;
+			write_exif(&png, &exif, args.output.as_deref().unwrap_or(&args.input), args.dry_run, policy)?;
+		}
+		ExifCommand::Delete(args) => {
+			let png = Png::try_from(read_file(&args.input)?.as_slice())?;
+			let mut exif = read_exif(&png)?;
+			for name in &args.tags {
+				let info = tag_info(name)?;
+				if !exif.delete(info) {
+					println!("{} is not set.", info.name);
+				}
+			}
+			write_exif(&png, &exif, args.output.as_deref().unwrap_or(&args.input), args.dry_run, policy)?;
+		}
+		ExifCommand::StripGps(args) => {
+			let png = Png::try_from(read_file(&args.input)?.as_slice())?;
+			let mut exif = read_exif(&png)?;
+			let removed = exif.strip_gps();
+			write_exif(&png, &exif, args.output.as_deref().unwrap_or(&args.input), args.dry_run, policy)?;
+			println!("{removed} GPS tags {}.", if args.dry_run { "would be removed" } else { "removed" });
+		}
+	}
+	Ok(())
+}
+
+pub fn icc(args: IccArgs, policy: OutputPolicy) -> crate::Result<()> {
+	match args.command {
+		IccCommand::Extract(args) => {
+			let png = Png::try_from(read_file(&args.input)?.as_slice())?;
+			let header = ImageHeader::from_png(&png)?;
+			let Some(chunk) = png.chunk_by_type(&ChunkType::ICCP) else {
+				println!("No ICC profile found.");
+				return Ok(());
+			};
+			let Some(StandardChunk::IccProfile { name, profile }) = StandardChunk::parse(chunk, &header)? else {
+				unreachable!("iCCP chunks parse as ICC profiles");
+			};
+			let data = IccProfile::inflate(&profile)?;
+			match IccProfile::try_from(data.as_slice()) {
+				Ok(profile) => println!("\"{name}\": {profile}"),
+				Err(e) => eprintln!("Warning: \"{name}\" is not a valid ICC profile: {e}"),
+			}
+			write_file(&args.output, data)?;
+		}
+		IccCommand::Embed(args) => {
+			let original = Png::try_from(read_file(&args.input)?.as_slice())?;
+			let mut png = original.clone();
+			let header = ImageHeader::from_png(&png)?;
+			let profile = IccProfile::try_from(read_file(&args.profile)?.as_slice())?;
+			profile.check_color_type(header.color_type)?;
+
+			let name = match args.name {
+				Some(name) => name,
+				None => args.profile.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default(),
+			};
+			let latin1: Vec<u8> = name.chars().map(|c| u8::try_from(c).unwrap_or(0)).collect();
+			let name = standard_chunk::keyword(&ChunkType::ICCP, &latin1)?;
+
+			while png.remove_first_chunk(&ChunkType::ICCP).is_some() {}
+			// an ICC profile overrides sRGB, so keeping both would be contradictory
+			if png.remove_first_chunk(&ChunkType::SRGB).is_some() {
+				println!("{} conflicting sRGB chunk.", if args.dry_run { "Would remove" } else { "Removed" });
+			}
+			let chunk = StandardChunk::IccProfile { name: name.clone(), profile: profile.compress() }.to_chunk();
+			png.insert_before_image_data(chunk);
+			print!("{}", save(&original, &png, args.output.as_deref().unwrap_or(&args.input), args.dry_run, policy)?);
+			println!("{} \"{name}\": {profile}", if args.dry_run { "Would embed" } else { "Embedded" });
+		}
+	}
+	Ok(())
+}
+
+pub fn strip(args: StripArgs, policy: OutputPolicy) -> crate::Result<()> {
+	let files = batch::expand(&args.inputs, args.recursive)?;
+	if args.output.is_some() && files.len() > 1 {
+		return Err(BatchError::Output.into());
+	}
+
+	let strip_policy = args.strip.policy();
+	batch::run(&files, |input| {
+		let original = Png::try_from(read_file(input)?.as_slice())?;
+		let mut png = original.clone();
+		let removed = strip_policy.apply(&mut png);
+		if strip::removes_animation(&removed) {
+			eprintln!("Warning: {} is no longer animated; only its default image is left.", input.display());
+		}
+		let mut output = String::new();
+		let (chunk_verb, summary_verb, saved) = match args.dry_run {
+			true => ("would remove", "Would remove", "saving"),
+			false => ("removed", "Removed", "saved"),
+		};
+		for chunk in &removed {
+			let keyword = strip::text_keyword(chunk).map(|keyword| format!(" ({keyword})")).unwrap_or_default();
+			writeln!(output, "  {chunk_verb} {}{keyword}, {} bytes", chunk.chunk_type(), chunk.as_bytes().len())?;
+		}
+		let bytes: usize = removed.iter().map(|chunk| chunk.as_bytes().len()).sum();
+		writeln!(output, "{summary_verb} {} chunks, {saved} {bytes} bytes.", removed.len())?;
+		output.push_str(&save(&original, &png, args.output.as_deref().unwrap_or(input), args.dry_run, policy)?);
+		Ok(output)
+	})
+}
+
+pub fn transplant(args: TransplantArgs, policy: OutputPolicy) -> crate::Result<()> {
+	let from = Png::try_from(read_file(&args.from)?.as_slice())?;
+	let original = Png::try_from(read_file(&args.to)?.as_slice())?;
+	let mut to = original.clone();
+	let report = transplant::transplant(&from, &mut to, &args.types, args.force)?;
+	let mut copied = 0;
+	for transplanted in &report {
+		match &transplanted.action {
+			Action::Copied => println!("  copied {}", transplanted.chunk_type),
+			Action::Replaced => println!("  replaced {}", transplanted.chunk_type),
+			Action::Skipped(reason) => println!("  skipped {}: {reason}", transplanted.chunk_type),
+		}
+		copied += (transplanted.action == Action::Copied || transplanted.action == Action::Replaced) as usize;
+	}
+	println!("Copied {copied} of {} chunks.", report.len());
+	print!("{}", save(&original, &to, args.output.as_deref().unwrap_or(&args.to), args.dry_run, policy)?);
 	Ok(())
-}
\ No newline at end of file
+}
+
+// Index of the iTXt chunk holding the XMP packet.
+fn xmp_chunk(png: &Png) -> Option<usize> {
+	png.chunks().iter().position(|chunk| {
+		chunk.chunk_type() == &ChunkType::ITXT && strip::text_keyword(chunk).as_deref() == Some(xmp::KEYWORD)
+	})
+}
+
+pub(crate) fn read_xmp(png: &Png) -> crate::Result<Xmp> {
+	Ok(match xmp_chunk(png) {
+		Some(index) => Xmp::from_chunk(&png.chunks()[index])?,
+		None => Xmp::new(),
+	})
+}
+
+/// Replaces the XMP packet. It keeps its place; new ones go before the image
+/// data.
+pub(crate) fn replace_xmp(png: &mut Png, xmp: &Xmp) {
+	match xmp_chunk(png) {
+		Some(index) => {
+			let mut chunks = png.chunks().to_vec();
+			chunks[index] = xmp.to_chunk();
+			*png = Png::from_chunks(chunks);
+		}
+		None => png.insert_before_image_data(xmp.to_chunk()),
+	}
+}
+
+pub fn xmp(args: XmpArgs, policy: OutputPolicy) -> crate::Result<()> {
+	match args.command {
+		XmpCommand::Get(args) => {
+			let png = Png::try_from(read_file(&args.input)?.as_slice())?;
+			let Some(index) = xmp_chunk(&png) else {
+				println!("No XMP packet found.");
+				return Ok(());
+			};
+			let xmp = Xmp::from_chunk(&png.chunks()[index])?;
+			if args.properties.is_empty() {
+				for (name, value) in xmp.properties() {
+					println!("{name:<24} {value}");
+				}
+			}
+			for text in &args.properties {
+				let property = Property::parse(text, &args.namespaces)?;
+				let value = xmp.get(&property).unwrap_or_else(|| String::from("(not set)"));
+				println!("{:<24} {value}", format!("{}:{}", property.prefix, property.name));
+			}
+		}
+		XmpCommand::Set(args) => {
+			let original = Png::try_from(read_file(&args.input)?.as_slice())?;
+			let property = Property::parse(&args.property, &args.namespaces)?;
+			let mut xmp = read_xmp(&original)?;
+			xmp.set(&property, &args.values);
+			let mut png = original.clone();
+			replace_xmp(&mut png, &xmp);
+			print!("{}", save(&original, &png, args.output.as_deref().unwrap_or(&args.input), args.dry_run, policy)?);
+		}
+	}
+	Ok(())
+}
+
+pub fn tui(args: TuiArgs, policy: OutputPolicy) -> crate::Result<()> {
+	tui::run(&args.input, policy)
+}
+
+pub fn script(args: ScriptArgs, config: &Config, policy: OutputPolicy) -> crate::Result<()> {
+	let text = match &args.script {
+		Some(path) if path != Path::new("-") => String::from_utf8(read_file(path)?)?,
+		_ => io::read_to_string(io::stdin()).map_err(|source| CommandError::Read { path: PathBuf::from("standard input"), source })?,
+	};
+	let steps = script::parse(&text, config)?;
+	let original = Png::try_from(read_file(&args.input)?.as_slice())?;
+	let mut png = original.clone();
+	print!("{}", script::run(&mut png, steps)?);
+	print!("{}", save(&original, &png, args.output.as_deref().unwrap_or(&args.input), args.dry_run, policy)?);
+	Ok(())
+}
+
+pub fn completions(args: CompletionsArgs) -> crate::Result<()> {
+	completions::registration(args.shell, &mut io::stdout())?;
+	Ok(())
+}
+
+pub fn man(args: ManArgs) -> crate::Result<()> {
+	completions::man(args.dir.as_deref(), &mut io::stdout())?;
+	if let Some(dir) = &args.dir {
+		println!("Man pages written to {}.", dir.display());
+	}
+	Ok(())
+}
@@ -0,0 +1,100 @@
+use std::ffi::{OsStr, OsString};
+use std::io::Write;
+use std::path::PathBuf;
+use clap::CommandFactory;
+use clap_complete::engine::CompletionCandidate;
+use clap_complete::env::{CompleteEnv, Shells};
+use crate::args::Shell;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::Cli;
+
+/// Environment variable through which the shell asks for completions.
+const VAR: &str = "COMPLETE";
+
+/// Answers the shell and exits if this process was started to complete a
+/// command line; returns otherwise.
+pub fn handle() {
+	CompleteEnv::with_factory(Cli::command).var(VAR).complete();
+}
+
+/// Writes the script that makes `shell` ask pngme for completions.
+pub fn registration(shell: Shell, buf: &mut dyn Write) -> std::io::Result<()> {
+	let name = match shell {
+		Shell::Bash => "bash",
+		Shell::Elvish => "elvish",
+		Shell::Fish => "fish",
+		Shell::Powershell => "powershell",
+		Shell::Zsh => "zsh",
+	};
+	let shells = Shells::builtins();
+	let completer = shells.completer(name).expect("clap_complete supports all shells in Shell");
+	completer.write_registration(VAR, "pngme", "pngme", "pngme", buf)
+}
+
+// Files given with -i in the command line being completed, which the shell
+// passes after `--`.
+fn inputs(args: impl IntoIterator<Item = OsString>) -> Vec<PathBuf> {
+	let mut args = args.into_iter().skip_while(|arg| arg != "--");
+	let mut inputs = Vec::new();
+	while let Some(arg) = args.next() {
+		if arg == "-i" || arg == "--input" {
+			inputs.extend(args.next().map(PathBuf::from));
+		} else if let Some(arg) = arg.to_str() {
+			let path = arg.strip_prefix("--input=").or_else(|| arg.strip_prefix("-i").filter(|_| !arg.starts_with("--")));
+			inputs.extend(path.filter(|path| !path.is_empty()).map(PathBuf::from));
+		}
+	}
+	inputs
+}
+
+/// Completes a chunk type with the types found in the input files.
+pub fn chunk_types(current: &OsStr) -> Vec<CompletionCandidate> {
+	let Some(current) = current.to_str() else {
+		return Vec::new();
+	};
+	let mut found: Vec<ChunkType> = Vec::new();
+	for path in inputs(std::env::args_os()).into_iter().filter(|path| path.is_file()) {
+		let Ok(bytes) = std::fs::read(&path) else {
+			continue;
+		};
+		let Ok((png, _)) = Png::try_from_damaged(&bytes) else {
+			continue;
+		};
+		for chunk in png.chunks() {
+			if !found.contains(chunk.chunk_type()) {
+				found.push(chunk.chunk_type().clone());
+			}
+		}
+	}
+	found
+		.into_iter()
+		.filter(|chunk_type| chunk_type.to_string().starts_with(current))
+		.map(|chunk_type| {
+			let help = chunk_type.registered_name().unwrap_or(if chunk_type.is_public() { "unregistered" } else { "private" });
+			CompletionCandidate::new(chunk_type.to_string()).help(Some(help.into()))
+		})
+		.collect()
+}
+
+/// Writes the man page of pngme, or with `dir` one page per command into
+/// that directory.
+pub fn man(dir: Option<&std::path::Path>, buf: &mut dyn Write) -> std::io::Result<()> {
+	match dir {
+		Some(dir) => clap_mangen::generate_to(Cli::command(), dir),
+		None => clap_mangen::Man::new(Cli::command()).render(buf),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+
+	#[test]
+	fn test_inputs() {
+		let args = ["pngme", "--", "pngme", "decode", "-i", "a.png", "--input=b.png", "-ic.png", "--identity", "key", "-i"];
+		let inputs = inputs(args.map(OsString::from));
+		assert_eq!(inputs, [PathBuf::from("a.png"), PathBuf::from("b.png"), PathBuf::from("c.png")]);
+	}
+}
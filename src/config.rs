@@ -0,0 +1,231 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use clap::{Command, ValueEnum};
+use serde::Deserialize;
+use crate::args::OutputPolicy;
+use crate::chunk_type::ChunkType;
+use crate::crypto::PublicKey;
+use crate::embed::Method;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+	#[error("cannot read {}: {source}", path.display())]
+	Read {
+		path: PathBuf,
+		source: std::io::Error,
+	},
+
+	#[error("invalid config file {}: {source}", path.display())]
+	Parse {
+		path: PathBuf,
+		source: toml::de::Error,
+	},
+
+	#[error("invalid {key} in {}: {message}", path.display())]
+	Invalid {
+		path: PathBuf,
+		key: &'static str,
+		message: String,
+	},
+}
+
+/// Defaults read from a TOML file. Each one becomes the default value of the
+/// argument with the same name in the commands listed in `CONFIGURED`, so the
+/// command line still overrides it.
+#[derive(Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+	/// Chunk type for encode, decode and remove.
+	pub chunk_type: Option<String>,
+	pub method: Option<String>,
+	/// zlib level for image data that pixel-domain methods re-encode.
+	pub compression: Option<u32>,
+	/// Identity file to decrypt messages with, relative to the config file.
+	/// It is the default of `--identity-file`, as keys don't belong in it.
+	pub identity: Option<PathBuf>,
+	/// Public keys to encrypt messages to.
+	pub recipients: Vec<String>,
+	pub output_policy: Option<String>,
+}
+
+/// `$XDG_CONFIG_HOME/pngme/config.toml`, with `~/.config` if the variable is
+/// not set.
+pub fn default_path() -> Option<PathBuf> {
+	let base = match std::env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+		Some(dir) => PathBuf::from(dir),
+		None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+	};
+	Some(base.join("pngme").join("config.toml"))
+}
+
+/// Value of `--config` in `args`. The config has to be read before the command
+/// line is parsed, as it sets the defaults of its arguments.
+pub fn path_from_args(args: impl IntoIterator<Item = OsString>) -> Option<PathBuf> {
+	let mut args = args.into_iter().take_while(|arg| arg != "--");
+	while let Some(arg) = args.next() {
+		if arg == "--config" {
+			return args.next().map(PathBuf::from);
+		}
+		if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+			return Some(PathBuf::from(path));
+		}
+	}
+	None
+}
+
+/// Commands that take their defaults from the config, both on the command line
+/// and as script steps. Others may have arguments with the same names, like
+/// `chunk-type check`, but a default makes no sense for them.
+const CONFIGURED: [&str; 3] = ["encode", "decode", "remove"];
+
+fn check<T, E: std::fmt::Display>(path: &Path, key: &'static str, result: Result<T, E>) -> Result<(), ConfigError> {
+	result.map(|_| ()).map_err(|e| ConfigError::Invalid { path: path.to_path_buf(), key, message: e.to_string() })
+}
+
+// Parses `value` as clap would, listing the valid values if it isn't one.
+fn value_enum<T: ValueEnum>(value: &str) -> Result<T, String> {
+	T::from_str(value, false).map_err(|_| {
+		let names: Vec<String> = T::value_variants().iter().filter_map(T::to_possible_value).map(|v| v.get_name().to_string()).collect();
+		format!("{value:?} is not one of {}", names.join(", "))
+	})
+}
+
+impl Config {
+	/// Reads `path`, or the default path if there is a file there.
+	pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+		let path = match path.map(Path::to_path_buf).or_else(default_path) {
+			Some(path) => path,
+			None => return Ok(Config::default()),
+		};
+		match std::fs::read_to_string(&path) {
+			Ok(text) => Config::parse(&text, &path),
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound && Some(&path) == default_path().as_ref() => Ok(Config::default()),
+			Err(source) => Err(ConfigError::Read { path, source }),
+		}
+	}
+
+	/// Parses and checks the contents of the config file at `path`.
+	pub fn parse(text: &str, path: &Path) -> Result<Config, ConfigError> {
+		let mut config: Config = toml::from_str(text).map_err(|source| ConfigError::Parse { path: path.to_path_buf(), source })?;
+		if let Some(chunk_type) = &config.chunk_type {
+			check(path, "chunk-type", ChunkType::from_str(chunk_type))?;
+		}
+		if let Some(method) = &config.method {
+			check(path, "method", value_enum::<Method>(method))?;
+		}
+		if let Some(level) = config.compression {
+			check(path, "compression", if level <= 9 { Ok(()) } else { Err("the level must be 0-9") })?;
+		}
+		for recipient in &config.recipients {
+			check(path, "recipients", PublicKey::from_str(recipient))?;
+		}
+		if let Some(policy) = &config.output_policy {
+			check(path, "output-policy", value_enum::<OutputPolicy>(policy))?;
+		}
+		config.identity = config.identity.map(|identity| match identity.strip_prefix("~") {
+			Ok(home) => std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default().join(home),
+			Err(_) => path.parent().unwrap_or(Path::new("")).join(identity),
+		});
+		Ok(config)
+	}
+
+	// Default values by argument id.
+	fn defaults(&self) -> Vec<(&'static str, Vec<OsString>)> {
+		let mut defaults = Vec::new();
+		let mut add = |id, value: Option<OsString>| defaults.extend(value.map(|value| (id, vec![value])));
+		add("chunk_type", self.chunk_type.clone().map(OsString::from));
+		add("method", self.method.clone().map(OsString::from));
+		add("compression", self.compression.map(|level| level.to_string().into()));
+		add("identity_file", self.identity.clone().map(OsString::from));
+		add("output_policy", self.output_policy.clone().map(OsString::from));
+		if !self.recipients.is_empty() {
+			defaults.push(("recipients", self.recipients.iter().map(OsString::from).collect()));
+		}
+		defaults
+	}
+
+	/// Sets the defaults of `command`, whose own arguments are the global ones,
+	/// and of its subcommands in `CONFIGURED`.
+	pub fn apply(&self, command: Command) -> Command {
+		let defaults = self.defaults();
+		let apply_defaults = |command: Command| {
+			command.mut_args(|arg| match defaults.iter().find(|(id, _)| arg.get_id() == id) {
+				Some((_, values)) => arg.default_values(values.clone()).required(false),
+				None => arg,
+			})
+		};
+		apply_defaults(command).mut_subcommands(|subcommand| match CONFIGURED.contains(&subcommand.get_name()) {
+			true => apply_defaults(subcommand),
+			false => subcommand,
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+	use clap::{CommandFactory, FromArgMatches};
+	use crate::commands::Commands;
+	use crate::Cli;
+
+	const PUBLIC_KEY: &str = "8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a";
+
+	fn parse(args: &[&str], config: &Config) -> Cli {
+		let matches = config.apply(Cli::command()).try_get_matches_from(args).unwrap();
+		Cli::from_arg_matches(&matches).unwrap()
+	}
+
+	#[test]
+	fn test_parse() {
+		let text = format!("chunk-type = \"ruSt\"\nmethod = \"lsb\"\nidentity = \"keys/me.txt\"\nrecipients = [\"{PUBLIC_KEY}\"]\n");
+		let config = Config::parse(&text, Path::new("/etc/pngme/config.toml")).unwrap();
+		assert_eq!(config.chunk_type.as_deref(), Some("ruSt"));
+		assert_eq!(config.identity, Some(PathBuf::from("/etc/pngme/keys/me.txt")));
+
+		let error = Config::parse("method = \"smoke-signals\"", Path::new("config.toml")).unwrap_err();
+		assert!(error.to_string().starts_with("invalid method in config.toml: \"smoke-signals\" is not one of chunk, lsb, "));
+		assert!(matches!(Config::parse("colour = \"red\"", Path::new("config.toml")), Err(ConfigError::Parse { .. })));
+	}
+
+	#[test]
+	fn test_command_line_overrides_config() {
+		let mut config = Config {
+			chunk_type: Some(String::from("ruSt")),
+			compression: Some(1),
+			recipients: vec![String::from(PUBLIC_KEY)],
+			output_policy: Some(String::from("backup")),
+			..Config::default()
+		};
+		let cli = parse(&["pngme", "encode", "-i", "a.png", "message"], &config);
+		assert_eq!(cli.output_policy, OutputPolicy::Backup);
+		let Commands::Encode(args) = cli.command else { panic!("not an encode command") };
+		assert_eq!(args.embed.chunk_type, Some("ruSt".parse().unwrap()));
+		assert_eq!((args.embed.message.as_str(), args.embed.compression, args.embed.recipients.len()), ("message", 1, 1));
+
+		let cli = parse(&["pngme", "--output-policy", "refuse", "encode", "-i", "a.png", "teSt", "message", "--compression", "9"], &config);
+		assert_eq!(cli.output_policy, OutputPolicy::Refuse);
+		let Commands::Encode(args) = cli.command else { panic!("not an encode command") };
+		assert_eq!((args.embed.chunk_type, args.embed.compression), (Some("teSt".parse().unwrap()), 9));
+
+		let cli = parse(&["pngme", "remove", "-i", "a.png"], &config);
+		let Commands::Remove(args) = cli.command else { panic!("not a remove command") };
+		assert_eq!(args.chunk_type, "ruSt".parse().unwrap());
+
+		config.identity = Some(PathBuf::from("/keys/me.txt"));
+		let Commands::Decode(args) = parse(&["pngme", "decode", "-i", "a.png", "ruSt"], &config).command else { panic!("not a decode command") };
+		assert_eq!((args.identity.is_none(), args.identity_file), (true, Some(PathBuf::from("/keys/me.txt"))));
+
+		// the chunk type to check is not a default for a message
+		assert!(config.apply(Cli::command()).try_get_matches_from(["pngme", "chunk-type", "check"]).is_err());
+	}
+
+	#[test]
+	fn test_path_from_args() {
+		let args = |args: &[&str]| path_from_args(args.iter().map(OsString::from));
+		assert_eq!(args(&["pngme", "decode", "--config", "team.toml", "-i", "a.png"]), Some(PathBuf::from("team.toml")));
+		assert_eq!(args(&["pngme", "--config=team.toml", "print"]), Some(PathBuf::from("team.toml")));
+		assert_eq!(args(&["pngme", "encode", "-i", "a.png", "--", "--config"]), None);
+	}
+}
@@ -0,0 +1,256 @@
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hkdf::Hkdf;
+use rand_core::{OsRng, RngCore};
+use sha2::Sha256;
+use x25519_dalek::StaticSecret;
+use crate::envelope::{Encryption, Envelope, WRAPPED_KEY_LEN};
+
+const KEY_WRAP_INFO: &[u8] = b"pngme x25519 key wrap v1";
+const PASSPHRASE_SALT: &[u8] = b"pngme passphrase v1";
+
+/// X25519 public key of a message recipient.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicKey(x25519_dalek::PublicKey);
+
+/// X25519 private key used to decrypt messages addressed to its public key.
+#[derive(Clone)]
+pub struct Identity(StaticSecret);
+
+impl PublicKey {
+	pub fn bytes(&self) -> [u8; 32] {
+		self.0.to_bytes()
+	}
+}
+
+impl Identity {
+	pub fn generate() -> Identity {
+		Identity(StaticSecret::random_from_rng(OsRng))
+	}
+
+	pub fn public_key(&self) -> PublicKey {
+		PublicKey(x25519_dalek::PublicKey::from(&self.0))
+	}
+
+	/// Renders the identity as the contents of an identity file.
+	pub fn to_file_contents(&self) -> String {
+		format!("# public key: {}\n{}\n", self.public_key(), hex::encode(self.0.to_bytes()))
+	}
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum KeyError {
+	#[error("key is not valid hex: {0}")]
+	Hex(#[from] hex::FromHexError),
+
+	#[error("key must be 32 bytes (got {0})")]
+	Length(usize),
+
+	#[error("identity file contains no key")]
+	MissingKey,
+
+	#[error("identity file contains more than one key")]
+	MultipleKeys,
+}
+
+fn parse_key(s: &str) -> Result<[u8; 32], KeyError> {
+	let bytes = hex::decode(s.trim())?;
+	bytes.as_slice().try_into().map_err(|_| KeyError::Length(bytes.len()))
+}
+
+impl std::str::FromStr for PublicKey {
+	type Err = KeyError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(PublicKey(x25519_dalek::PublicKey::from(parse_key(s)?)))
+	}
+}
+
+impl std::str::FromStr for Identity {
+	type Err = KeyError;
+
+	/// Parses the contents of an identity file: blank lines and lines starting
+	/// with `#` are ignored, exactly one hex-encoded key must remain.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut keys = s
+			.lines()
+			.map(str::trim)
+			.filter(|line| !line.is_empty() && !line.starts_with('#'));
+		let key = keys.next().ok_or(KeyError::MissingKey)?;
+		if keys.next().is_some() {
+			return Err(KeyError::MultipleKeys);
+		}
+		Ok(Identity(StaticSecret::from(parse_key(key)?)))
+	}
+}
+
+impl std::fmt::Display for PublicKey {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", hex::encode(self.0.as_bytes()))
+	}
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CryptoError {
+	#[error("at least one recipient is required")]
+	NoRecipients,
+
+	#[error("too many recipients (at most {max}, got {actual})")]
+	TooManyRecipients {
+		max: usize,
+		actual: usize,
+	},
+
+	#[error("recipient public key is a low-order point")]
+	WeakKey,
+
+	#[error("payload is not encrypted")]
+	NotEncrypted,
+
+	#[error("message is not addressed to this identity")]
+	NoMatchingRecipient,
+
+	#[error("decryption failed: payload was modified or corrupted")]
+	Decryption,
+}
+
+fn wrapping_cipher(shared: &x25519_dalek::SharedSecret, ephemeral: &[u8; 32], recipient: &[u8; 32]) -> ChaCha20Poly1305 {
+	let salt = [ephemeral.as_slice(), recipient.as_slice()].concat();
+	let mut key = Key::default();
+	Hkdf::<Sha256>::new(Some(&salt), shared.as_bytes())
+		.expand(KEY_WRAP_INFO, &mut key)
+		.expect("32 bytes is a valid HKDF-SHA256 output length");
+	ChaCha20Poly1305::new(&key)
+}
+
+/// Stretches a passphrase into a 32-byte key with Argon2id. The salt is fixed
+/// because nothing can be stored alongside the key material it protects.
+pub fn derive_key(passphrase: &str) -> [u8; 32] {
+	let mut key = [0u8; 32];
+	argon2::Argon2::default()
+		.hash_password_into(passphrase.as_bytes(), PASSPHRASE_SALT, &mut key)
+		.expect("default Argon2 parameters accept a 32-byte output");
+	key
+}
+
+/// Encrypts `message` under a random content key and wraps that key for each
+/// of the `recipients`.
+pub fn seal(message: &[u8], recipients: &[PublicKey]) -> Result<Envelope, CryptoError> {
+	if recipients.is_empty() {
+		return Err(CryptoError::NoRecipients);
+	}
+	if recipients.len() > u8::MAX as usize {
+		return Err(CryptoError::TooManyRecipients {
+			max: u8::MAX as usize,
+			actual: recipients.len(),
+		});
+	}
+
+	let content_key = ChaCha20Poly1305::generate_key(&mut OsRng);
+	let ephemeral_secret = StaticSecret::random_from_rng(OsRng);
+	let ephemeral = x25519_dalek::PublicKey::from(&ephemeral_secret).to_bytes();
+
+	let mut slots = Vec::with_capacity(recipients.len());
+	for recipient in recipients {
+		let shared = ephemeral_secret.diffie_hellman(&recipient.0);
+		if !shared.was_contributory() {
+			return Err(CryptoError::WeakKey);
+		}
+		let wrapped = wrapping_cipher(&shared, &ephemeral, recipient.0.as_bytes())
+			.encrypt(&Nonce::default(), content_key.as_slice())
+			.map_err(|_| CryptoError::Decryption)?;
+		let slot: [u8; WRAPPED_KEY_LEN] = wrapped.try_into().expect("wrapped key has a fixed length");
+		slots.push(slot);
+	}
+
+	let mut nonce = [0u8; 12];
+	OsRng.fill_bytes(&mut nonce);
+	let body = ChaCha20Poly1305::new(&content_key)
+		.encrypt(&Nonce::from(nonce), Payload { msg: message, aad: &ephemeral })
+		.map_err(|_| CryptoError::Decryption)?;
+
+	Ok(Envelope::encrypted(Encryption { ephemeral, slots, nonce }, body))
+}
+
+/// Finds the slot addressed to `identity` and decrypts the envelope body.
+pub fn open(envelope: &Envelope, identity: &Identity) -> Result<Vec<u8>, CryptoError> {
+	let encryption = envelope.encryption().ok_or(CryptoError::NotEncrypted)?;
+
+	let shared = identity.0.diffie_hellman(&x25519_dalek::PublicKey::from(encryption.ephemeral));
+	let cipher = wrapping_cipher(&shared, &encryption.ephemeral, &identity.public_key().bytes());
+	let content_key = encryption
+		.slots
+		.iter()
+		.find_map(|slot| cipher.decrypt(&Nonce::default(), slot.as_slice()).ok())
+		.ok_or(CryptoError::NoMatchingRecipient)?;
+
+	ChaCha20Poly1305::new(Key::from_slice(&content_key))
+		.decrypt(&Nonce::from(encryption.nonce), Payload { msg: envelope.body(), aad: &encryption.ephemeral })
+		.map_err(|_| CryptoError::Decryption)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+	use std::str::FromStr;
+
+	#[test]
+	fn test_seal_and_open() {
+		let identity = Identity::generate();
+		let envelope = seal(b"secret", &[identity.public_key()]).unwrap();
+		assert_eq!(open(&envelope, &identity).unwrap(), b"secret");
+	}
+
+	#[test]
+	fn test_every_recipient_can_open() {
+		let identities: Vec<Identity> = (0..3).map(|_| Identity::generate()).collect();
+		let recipients: Vec<PublicKey> = identities.iter().map(Identity::public_key).collect();
+		let envelope = seal(b"team message", &recipients).unwrap();
+		for identity in &identities {
+			assert_eq!(open(&envelope, identity).unwrap(), b"team message");
+		}
+	}
+
+	#[test]
+	fn test_other_identity_cannot_open() {
+		let envelope = seal(b"secret", &[Identity::generate().public_key()]).unwrap();
+		let result = open(&envelope, &Identity::generate());
+		assert!(matches!(result, Err(CryptoError::NoMatchingRecipient)));
+	}
+
+	#[test]
+	fn test_tampered_body_is_rejected() {
+		let identity = Identity::generate();
+		let mut bytes = seal(b"secret", &[identity.public_key()]).unwrap().as_bytes();
+		*bytes.last_mut().unwrap() ^= 1;
+		let envelope = Envelope::try_from(bytes.as_slice()).unwrap();
+		assert!(matches!(open(&envelope, &identity), Err(CryptoError::Decryption)));
+	}
+
+	#[test]
+	fn test_seal_requires_recipients() {
+		assert!(matches!(seal(b"secret", &[]), Err(CryptoError::NoRecipients)));
+	}
+
+	#[test]
+	fn test_derive_key_is_deterministic() {
+		assert_eq!(derive_key("correct horse"), derive_key("correct horse"));
+		assert_ne!(derive_key("correct horse"), derive_key("battery staple"));
+	}
+
+	#[test]
+	fn test_identity_file_roundtrip() {
+		let identity = Identity::generate();
+		let parsed = Identity::from_str(&identity.to_file_contents()).unwrap();
+		assert_eq!(parsed.public_key(), identity.public_key());
+	}
+
+	#[test]
+	fn test_public_key_string_roundtrip() {
+		let key = Identity::generate().public_key();
+		assert_eq!(PublicKey::from_str(&key.to_string()).unwrap(), key);
+		assert!(PublicKey::from_str("abcd").is_err());
+		assert!(PublicKey::from_str("not hex").is_err());
+	}
+}
@@ -0,0 +1,339 @@
+use serde::Serialize;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::envelope::Envelope;
+use crate::image::{self, ColorType, Raster};
+use crate::png::{Png, PngError};
+
+mod stats;
+
+// Text chunks larger than this rarely hold genuine metadata.
+const TEXT_LIMIT: usize = 4096;
+
+/// A trait of the file that is commonly left behind by steganography tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Check {
+	PrivateChunk,
+	DataAfterIend,
+	OversizedText,
+	IdatSplit,
+	TrailingZlibData,
+	ChiSquare,
+	RsAnalysis,
+}
+
+impl std::fmt::Display for Check {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let name = match self {
+			Check::PrivateChunk => "private-chunk",
+			Check::DataAfterIend => "data-after-iend",
+			Check::OversizedText => "oversized-text",
+			Check::IdatSplit => "idat-split",
+			Check::TrailingZlibData => "trailing-zlib-data",
+			Check::ChiSquare => "chi-square",
+			Check::RsAnalysis => "rs-analysis",
+		};
+		f.pad(name)
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+	pub check: Check,
+	/// How strongly the finding alone suggests hidden data, 0 to 100.
+	pub score: u8,
+	pub detail: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verdict {
+	Clean,
+	Suspicious,
+	Likely,
+}
+
+impl std::fmt::Display for Verdict {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Verdict::Clean => write!(f, "no hidden data found"),
+			Verdict::Suspicious => write!(f, "suspicious"),
+			Verdict::Likely => write!(f, "hidden data likely"),
+		}
+	}
+}
+
+/// LSB plane statistics of the colour samples, present for 8-bit truecolour
+/// and greyscale images.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Statistics {
+	/// Chi-square probability that the LSBs were replaced by random bits.
+	pub chi_square: f64,
+	/// RS estimate of the fraction of samples whose LSB carries data.
+	pub rs_estimate: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Report {
+	pub score: u8,
+	pub verdict: Verdict,
+	pub findings: Vec<Finding>,
+	pub statistics: Option<Statistics>,
+}
+
+impl Report {
+	fn new(mut findings: Vec<Finding>, statistics: Option<Statistics>) -> Report {
+		findings.sort_by_key(|finding| std::cmp::Reverse(finding.score));
+		// findings are treated as independent evidence
+		let clean = findings.iter().fold(1.0, |clean, finding| clean * (1.0 - finding.score as f64 / 100.0));
+		let score = (100.0 * (1.0 - clean)).round() as u8;
+		let verdict = match score {
+			0..20 => Verdict::Clean,
+			20..60 => Verdict::Suspicious,
+			_ => Verdict::Likely,
+		};
+		Report { score, verdict, findings, statistics }
+	}
+}
+
+// Offset just past the IEND chunk, or the end of the file if there is none.
+fn iend_end(bytes: &[u8]) -> usize {
+	let mut offset = Png::STANDARD_HEADER.len();
+	while offset + 8 <= bytes.len() {
+		let length = u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+		let end = offset.saturating_add(length).saturating_add(Chunk::OVERHEAD_BYTES);
+		if end > bytes.len() {
+			break;
+		}
+		if bytes[offset + 4..offset + 8] == ChunkType::IEND.bytes() {
+			return end;
+		}
+		offset = end;
+	}
+	bytes.len()
+}
+
+fn is_text(chunk_type: &ChunkType) -> bool {
+	matches!(&chunk_type.bytes(), b"tEXt" | b"zTXt" | b"iTXt")
+}
+
+fn chunk_findings(png: &Png) -> Vec<Finding> {
+	let mut findings = Vec::new();
+	for chunk in png.chunks() {
+		let chunk_type = chunk.chunk_type();
+		// acTL, fcTL and fdAT are private by their names but registered
+		if !chunk_type.is_critical() && !chunk_type.is_public() && chunk_type.registered_name().is_none() {
+			let (score, what) = if Envelope::is_envelope(chunk.data()) {
+				(95, "a pngme envelope")
+			} else {
+				(50, "unknown data")
+			};
+			findings.push(Finding {
+				check: Check::PrivateChunk,
+				score,
+				detail: format!("private ancillary chunk {chunk_type} holds {} bytes of {what}", chunk.length()),
+			});
+		}
+		if is_text(chunk_type) && chunk.data().len() > TEXT_LIMIT {
+			findings.push(Finding {
+				check: Check::OversizedText,
+				score: 30,
+				detail: format!("{chunk_type} chunk holds {} bytes", chunk.length()),
+			});
+		}
+	}
+	findings
+}
+
+fn idat_findings(png: &Png) -> Vec<Finding> {
+	let positions: Vec<usize> = png
+		.chunks()
+		.iter()
+		.enumerate()
+		.filter(|(_, chunk)| *chunk.chunk_type() == ChunkType::IDAT)
+		.map(|(index, _)| index)
+		.collect();
+	let sizes: Vec<u32> = positions.iter().map(|&index| png.chunks()[index].length()).collect();
+
+	let mut findings = Vec::new();
+	if positions.windows(2).any(|pair| pair[1] != pair[0] + 1) {
+		findings.push(Finding {
+			check: Check::IdatSplit,
+			score: 40,
+			detail: String::from("IDAT chunks are not consecutive"),
+		});
+	}
+	// encoders fill every IDAT up to a fixed size except the last
+	if let Some((last, rest)) = sizes.split_last() {
+		if rest.windows(2).any(|pair| pair[0] != pair[1]) || rest.first().is_some_and(|first| last > first) {
+			let listed: Vec<String> = sizes.iter().take(8).map(u32::to_string).collect();
+			findings.push(Finding {
+				check: Check::IdatSplit,
+				score: 25,
+				detail: format!(
+					"{} IDAT chunks of irregular sizes ({}{})",
+					sizes.len(),
+					listed.join(", "),
+					if sizes.len() > 8 { ", …" } else { "" },
+				),
+			});
+		}
+	}
+
+	let data = png.image_data();
+	if let Ok(stream_len) = image::zlib_stream_len(&data) {
+		if stream_len < data.len() {
+			findings.push(Finding {
+				check: Check::TrailingZlibData,
+				score: 80,
+				detail: format!("{} bytes follow the end of the zlib stream", data.len() - stream_len),
+			});
+		}
+	}
+	findings
+}
+
+// Colour samples of an 8-bit image, one vector per channel.
+fn channel_samples(raster: &Raster) -> Option<Vec<Vec<u8>>> {
+	let header = raster.header();
+	if header.bit_depth != 8 || header.color_type == ColorType::Indexed {
+		return None;
+	}
+	let channels = header.color_type.channels();
+	let colour_channels = channels - usize::from(header.color_type.has_alpha());
+	Some(
+		(0..colour_channels)
+			.map(|channel| raster.data().iter().skip(channel).step_by(channels).copied().collect())
+			.collect(),
+	)
+}
+
+fn statistics(png: &Png) -> Option<Statistics> {
+	let raster = Raster::from_png(png).ok()?;
+	let channels = channel_samples(&raster)?;
+	// interleaved again, so that prefixes follow the order of sequential embedding
+	let colour: Vec<u8> = (0..channels[0].len())
+		.flat_map(|i| channels.iter().map(move |channel| channel[i]))
+		.collect();
+	let chi_square = stats::chi_square_prefixes(&colour);
+	let rs_estimate = channels.iter().map(|channel| stats::rs_estimate(channel)).sum::<f64>() / channels.len() as f64;
+	Some(Statistics { chi_square, rs_estimate })
+}
+
+fn statistic_findings(statistics: &Statistics) -> Vec<Finding> {
+	let mut findings = Vec::new();
+	if statistics.chi_square > 0.5 {
+		findings.push(Finding {
+			check: Check::ChiSquare,
+			score: (statistics.chi_square * 70.0).round() as u8,
+			detail: format!("LSB value pairs are evened out (p = {:.3})", statistics.chi_square),
+		});
+	}
+	// natural images stay within a few percent of zero
+	if statistics.rs_estimate > 0.1 {
+		findings.push(Finding {
+			check: Check::RsAnalysis,
+			score: (statistics.rs_estimate * 150.0).min(90.0).round() as u8,
+			detail: format!("about {:.0}% of the colour LSBs appear to carry data", statistics.rs_estimate * 100.0),
+		});
+	}
+	findings
+}
+
+/// Inspects the bytes of a PNG file for traces of hidden data.
+pub fn analyze(bytes: &[u8]) -> Result<Report, PngError> {
+	let end = iend_end(bytes);
+	let png = Png::try_from(&bytes[..end])?;
+
+	let mut findings = Vec::new();
+	if end < bytes.len() {
+		let trailing = &bytes[end..];
+		let detail = match Chunk::try_from(trailing) {
+			Ok(chunk) => format!("{} bytes after IEND, starting with a {} chunk", trailing.len(), chunk.chunk_type()),
+			Err(_) => format!("{} bytes after IEND", trailing.len()),
+		};
+		findings.push(Finding { check: Check::DataAfterIend, score: 90, detail });
+	}
+	findings.extend(chunk_findings(&png));
+	findings.extend(idat_findings(&png));
+
+	let statistics = statistics(&png);
+	if let Some(statistics) = &statistics {
+		findings.extend(statistic_findings(statistics));
+	}
+	Ok(Report::new(findings, statistics))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+	use crate::embed::tests::{rgba_header, testing_png};
+
+	fn checks(report: &Report) -> Vec<Check> {
+		report.findings.iter().map(|finding| finding.check).collect()
+	}
+
+	#[test]
+	fn test_clean_image() {
+		let header = rgba_header(16, 16);
+		let report = analyze(&testing_png(header, vec![0; header.data_len().unwrap()]).as_bytes()).unwrap();
+		assert_eq!(report.findings, vec![]);
+		assert_eq!(report.verdict, Verdict::Clean);
+		assert!(report.statistics.is_some());
+	}
+
+	#[test]
+	fn test_animated_image_is_clean() {
+		let header = rgba_header(4, 4);
+		let png = testing_png(header, vec![0; header.data_len().unwrap()]);
+		let frame_control = |sequence: u32| {
+			let data = [sequence, 4, 4, 0, 0].iter().flat_map(|value| value.to_be_bytes()).chain([0, 1, 0, 1, 0, 0]).collect();
+			Chunk::new(ChunkType::FCTL, data)
+		};
+		let frame_data = [2u32.to_be_bytes().to_vec(), png.image_data()].concat();
+		let mut chunks = png.chunks().to_vec();
+		let iend = chunks.pop().unwrap();
+		chunks.insert(1, Chunk::new(ChunkType::ACTL, vec![0, 0, 0, 2, 0, 0, 0, 0]));
+		chunks.insert(2, frame_control(0));
+		chunks.extend([frame_control(1), Chunk::new(ChunkType::FDAT, frame_data), iend]);
+		let report = analyze(&Png::from_chunks(chunks).as_bytes()).unwrap();
+		assert_eq!(report.findings, vec![]);
+	}
+
+	#[test]
+	fn test_data_after_iend() {
+		let header = rgba_header(4, 4);
+		let mut png = testing_png(header, vec![0; header.data_len().unwrap()]);
+		png.append_chunk(Chunk::new("ruSt".parse().unwrap(), b"hidden".to_vec()));
+		let report = analyze(&png.as_bytes()).unwrap();
+		assert_eq!(checks(&report), vec![Check::DataAfterIend]);
+		assert_eq!(report.verdict, Verdict::Likely);
+	}
+
+	#[test]
+	fn test_trailing_zlib_data() {
+		let header = rgba_header(4, 4);
+		let mut png = testing_png(header, vec![0; header.data_len().unwrap()]);
+		let mut data = png.image_data();
+		data.extend_from_slice(b"hidden");
+		png.set_image_data(&data);
+		assert_eq!(checks(&analyze(&png.as_bytes()).unwrap()), vec![Check::TrailingZlibData]);
+	}
+
+	#[test]
+	fn test_irregular_idat_split() {
+		let header = rgba_header(4, 4);
+		let png = testing_png(header, vec![0; header.data_len().unwrap()]);
+		let data = png.image_data();
+		let mut chunks: Vec<Chunk> = png.chunks().to_vec();
+		let idat = chunks.iter().position(|chunk| *chunk.chunk_type() == ChunkType::IDAT).unwrap();
+		chunks.splice(
+			idat..=idat,
+			[Chunk::new(ChunkType::IDAT, data[..2].to_vec()), Chunk::new(ChunkType::IDAT, data[2..].to_vec())],
+		);
+		let report = analyze(&Png::from_chunks(chunks).as_bytes()).unwrap();
+		assert_eq!(checks(&report), vec![Check::IdatSplit]);
+	}
+}
@@ -0,0 +1,201 @@
+//! Statistical attacks on the least significant bit plane of 8-bit samples.
+
+/// Probability that the LSBs of `samples` were overwritten with random bits,
+/// from the chi-square attack of Westfeld and Pfitzmann: embedding evens out
+/// the counts of each pair of values `2k` and `2k + 1`.
+pub fn chi_square(samples: &[u8]) -> f64 {
+	let mut histogram = [0u64; 256];
+	samples.iter().for_each(|&sample| histogram[sample as usize] += 1);
+
+	let mut statistic = 0.0;
+	let mut categories = 0;
+	for pair in histogram.chunks_exact(2) {
+		let expected = (pair[0] + pair[1]) as f64 / 2.0;
+		// sparse pairs say nothing and would dominate the statistic
+		if expected < 5.0 {
+			continue;
+		}
+		statistic += (pair[0] as f64 - expected).powi(2) / expected;
+		categories += 1;
+	}
+	if categories < 2 {
+		return 0.0;
+	}
+	upper_gamma((categories - 1) as f64 / 2.0, statistic / 2.0)
+}
+
+/// Largest chi-square probability over growing prefixes of `samples`, so that
+/// a message written front to back is caught even if it is short.
+pub fn chi_square_prefixes(samples: &[u8]) -> f64 {
+	(1..=10)
+		.map(|tenth| chi_square(&samples[..samples.len() * tenth / 10]))
+		.fold(0.0, f64::max)
+}
+
+/// Regularized upper incomplete gamma function Q(a, x).
+fn upper_gamma(a: f64, x: f64) -> f64 {
+	if x <= 0.0 {
+		return 1.0;
+	}
+	if x < a + 1.0 {
+		// series for the lower function
+		let mut term = 1.0 / a;
+		let mut sum = term;
+		for n in 1..500 {
+			term *= x / (a + n as f64);
+			sum += term;
+			if term < sum * 1e-12 {
+				break;
+			}
+		}
+		1.0 - sum * (-x + a * x.ln() - ln_gamma(a)).exp()
+	} else {
+		// Lentz's continued fraction
+		let tiny = 1e-300;
+		let mut b = x + 1.0 - a;
+		let mut c = 1.0 / tiny;
+		let mut d = 1.0 / b;
+		let mut h = d;
+		for i in 1..500 {
+			let an = -(i as f64) * (i as f64 - a);
+			b += 2.0;
+			d = an * d + b;
+			if d.abs() < tiny {
+				d = tiny;
+			}
+			c = b + an / c;
+			if c.abs() < tiny {
+				c = tiny;
+			}
+			d = 1.0 / d;
+			let delta = d * c;
+			h *= delta;
+			if (delta - 1.0).abs() < 1e-12 {
+				break;
+			}
+		}
+		(-x + a * x.ln() - ln_gamma(a)).exp() * h
+	}
+}
+
+// Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+	const COEFFICIENTS: [f64; 6] = [
+		76.18009172947146,
+		-86.50532032941677,
+		24.01409824083091,
+		-1.231739572450155,
+		0.1208650973866179e-2,
+		-0.5395239384953e-5,
+	];
+	let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+	let series = COEFFICIENTS
+		.iter()
+		.enumerate()
+		.fold(1.000000000190015, |sum, (i, c)| sum + c / (x + 1.0 + i as f64));
+	-tmp + (2.5066282746310005 * series / x).ln()
+}
+
+fn smoothness(group: &[i16]) -> i32 {
+	group.windows(2).map(|pair| (pair[1] - pair[0]).abs() as i32).sum()
+}
+
+// Relative counts of regular and singular groups of four samples when the
+// middle two are flipped: `shifted` selects F-1 (…, -1↔0, 1↔2, …) instead of
+// F1 (0↔1, 2↔3, …).
+fn regular_singular(samples: &[u8], shifted: bool) -> (f64, f64) {
+	let (mut regular, mut singular, mut groups) = (0, 0, 0);
+	for group in samples.chunks_exact(4) {
+		let original = [group[0] as i16, group[1] as i16, group[2] as i16, group[3] as i16];
+		let mut flipped = original;
+		for sample in &mut flipped[1..3] {
+			*sample = if shifted { ((*sample + 1) ^ 1) - 1 } else { *sample ^ 1 };
+		}
+		let (before, after) = (smoothness(&original), smoothness(&flipped));
+		regular += usize::from(after > before);
+		singular += usize::from(after < before);
+		groups += 1;
+	}
+	if groups == 0 {
+		return (0.0, 0.0);
+	}
+	(regular as f64 / groups as f64, singular as f64 / groups as f64)
+}
+
+/// Estimated fraction of `samples` whose LSB carries a message, from the RS
+/// analysis of Fridrich, Goljan and Du. `samples` should be neighbouring
+/// values of a single channel.
+pub fn rs_estimate(samples: &[u8]) -> f64 {
+	let inverted: Vec<u8> = samples.iter().map(|sample| sample ^ 1).collect();
+	let difference = |samples: &[u8], shifted| {
+		let (regular, singular) = regular_singular(samples, shifted);
+		regular - singular
+	};
+	let d0 = difference(samples, false);
+	let d1 = difference(&inverted, false);
+	let n0 = difference(samples, true);
+	let n1 = difference(&inverted, true);
+
+	let a = 2.0 * (d1 + d0);
+	let b = n0 - n1 - d1 - 3.0 * d0;
+	let c = d0 - n0;
+	let x = if a.abs() < 1e-9 {
+		if b.abs() < 1e-9 {
+			return 0.0;
+		}
+		-c / b
+	} else {
+		let discriminant = b * b - 4.0 * a * c;
+		if discriminant < 0.0 {
+			return 0.0;
+		}
+		let roots = [(-b + discriminant.sqrt()) / (2.0 * a), (-b - discriminant.sqrt()) / (2.0 * a)];
+		if roots[0].abs() < roots[1].abs() { roots[0] } else { roots[1] }
+	};
+	(x / (x - 0.5)).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rand_chacha::ChaCha20Rng;
+	use rand_chacha::rand_core::{RngCore, SeedableRng};
+
+	// A smooth gradient with mild noise, standing in for a photograph.
+	fn natural_samples() -> Vec<u8> {
+		let mut rng = ChaCha20Rng::seed_from_u64(7);
+		(0..64 * 64)
+			.map(|i| {
+				let base = 40.0 + 120.0 * ((i % 64) as f64 / 64.0) + 40.0 * ((i / 64) as f64 / 64.0);
+				(base + (rng.next_u32() % 7) as f64 - 3.0) as u8
+			})
+			.collect()
+	}
+
+	fn with_random_lsbs(samples: &[u8]) -> Vec<u8> {
+		let mut rng = ChaCha20Rng::seed_from_u64(11);
+		samples.iter().map(|&sample| (sample & !1) | (rng.next_u32() & 1) as u8).collect()
+	}
+
+	#[test]
+	fn test_upper_gamma() {
+		// Q(1, x) = e^-x
+		assert!((upper_gamma(1.0, 2.0) - (-2.0f64).exp()).abs() < 1e-9);
+		assert!((upper_gamma(1.0, 0.5) - (-0.5f64).exp()).abs() < 1e-9);
+	}
+
+	#[test]
+	fn test_chi_square_flags_even_pairs() {
+		// only even values: pairs are as uneven as they can be
+		let clean: Vec<u8> = (0..4096).map(|i| (i % 128 * 2) as u8).collect();
+		assert!(chi_square(&clean) < 0.01);
+		assert!(chi_square(&with_random_lsbs(&clean)) > 0.5);
+	}
+
+	#[test]
+	fn test_rs_estimate() {
+		let clean = natural_samples();
+		assert!(rs_estimate(&clean) < 0.15);
+		assert!(rs_estimate(&with_random_lsbs(&clean)) > 0.6);
+	}
+}
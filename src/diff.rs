@@ -0,0 +1,351 @@
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+use serde::Serialize;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::image::Raster;
+use crate::png::Png;
+
+/// Where a chunk sits in a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Location {
+	pub index: usize,
+	/// Byte offset of the chunk's length field.
+	pub offset: usize,
+	/// Length of the chunk's data.
+	pub length: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "change", rename_all = "kebab-case")]
+pub enum Change {
+	Inserted {
+		chunk_type: ChunkType,
+		after: Location,
+	},
+	Removed {
+		chunk_type: ChunkType,
+		before: Location,
+	},
+	/// A chunk whose data changed.
+	Modified {
+		chunk_type: ChunkType,
+		before: Location,
+		after: Location,
+	},
+	/// An unchanged chunk that moved relative to the others.
+	Moved {
+		chunk_type: ChunkType,
+		before: Location,
+		after: Location,
+	},
+}
+
+/// How the main image differs between two files.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum ImageData {
+	/// The compressed streams are the same, whichever way they are split into
+	/// IDAT chunks.
+	Identical,
+	/// The compressed streams differ but decode to the same pixels.
+	Recompressed,
+	/// The decoded samples differ.
+	Changed {
+		differing: usize,
+		total: usize,
+	},
+	/// The images have different dimensions or pixel formats.
+	Header,
+	Undecodable {
+		error: String,
+	},
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Report {
+	pub changes: Vec<Change>,
+	pub image_data: ImageData,
+}
+
+/// Locations of all chunks of `png`.
+pub fn locations(png: &Png) -> Vec<Location> {
+	let mut offset = Png::STANDARD_HEADER.len();
+	png.chunks()
+		.iter()
+		.enumerate()
+		.map(|(index, chunk)| {
+			let location = Location { index, offset, length: chunk.data().len() };
+			offset += chunk.data().len() + Chunk::OVERHEAD_BYTES;
+			location
+		})
+		.collect()
+}
+
+fn same(a: &Chunk, b: &Chunk) -> bool {
+	a.chunk_type() == b.chunk_type() && a.data() == b.data()
+}
+
+// Splits `chunks` into units for the LCS: a run of consecutive IDAT chunks is
+// one unit, every other chunk is one on its own.
+fn units(chunks: &[Chunk]) -> Vec<Range<usize>> {
+	let mut units: Vec<Range<usize>> = Vec::new();
+	for (index, chunk) in chunks.iter().enumerate() {
+		match units.last_mut() {
+			Some(unit) if chunk.chunk_type() == &ChunkType::IDAT && chunks[unit.start].chunk_type() == &ChunkType::IDAT => unit.end += 1,
+			_ => units.push(index..index + 1),
+		}
+	}
+	units
+}
+
+// Pairs of indices of identical chunks in a longest common subsequence. The
+// common prefix and suffix are matched directly, and the LCS table in between
+// is built over units, so a file with thousands of IDAT chunks does not need
+// a table with millions of entries.
+fn common_chunks(before: &[Chunk], after: &[Chunk]) -> Vec<(usize, usize)> {
+	let prefix = before.iter().zip(after).take_while(|(a, b)| same(a, b)).count();
+	let suffix = before[prefix..].iter().rev().zip(after[prefix..].iter().rev()).take_while(|(a, b)| same(a, b)).count();
+	let (before_units, after_units) = (units(&before[prefix..before.len() - suffix]), units(&after[prefix..after.len() - suffix]));
+	let same_unit = |i: usize, j: usize| {
+		let (a, b) = (&before[prefix..][before_units[i].clone()], &after[prefix..][after_units[j].clone()]);
+		a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+	};
+
+	// lengths[i][j] is the LCS length of before_units[i..] and after_units[j..]
+	let mut lengths = vec![vec![0usize; after_units.len() + 1]; before_units.len() + 1];
+	for i in (0..before_units.len()).rev() {
+		for j in (0..after_units.len()).rev() {
+			lengths[i][j] = if same_unit(i, j) {
+				lengths[i + 1][j + 1] + 1
+			} else {
+				lengths[i + 1][j].max(lengths[i][j + 1])
+			};
+		}
+	}
+
+	let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|index| (index, index)).collect();
+	let (mut i, mut j) = (0, 0);
+	while i < before_units.len() && j < after_units.len() {
+		if same_unit(i, j) {
+			pairs.extend(before_units[i].clone().zip(after_units[j].clone()).map(|(a, b)| (prefix + a, prefix + b)));
+			i += 1;
+			j += 1;
+		} else if lengths[i + 1][j] >= lengths[i][j + 1] {
+			i += 1;
+		} else {
+			j += 1;
+		}
+	}
+	pairs.extend((0..suffix).map(|index| (before.len() - suffix + index, after.len() - suffix + index)));
+	pairs
+}
+
+/// Chunk-level changes that turn `before` into `after`. Chunks outside the
+/// longest common subsequence were moved if an identical chunk is left in
+/// `after`, modified if one of the same type is (paired in order), and
+/// removed otherwise; the rest of `after` was inserted.
+pub fn diff(before: &Png, after: &Png) -> Vec<Change> {
+	let (old, new) = (before.chunks(), after.chunks());
+	let (old_locations, new_locations) = (locations(before), locations(after));
+	let mut old_matched = vec![false; old.len()];
+	let mut new_matched = vec![false; new.len()];
+	for (i, j) in common_chunks(old, new) {
+		old_matched[i] = true;
+		new_matched[j] = true;
+	}
+
+	// unmatched chunks of `after` in order, by content and by type
+	let mut identical: HashMap<([u8; 4], &[u8]), VecDeque<usize>> = HashMap::new();
+	let mut by_type: HashMap<[u8; 4], VecDeque<usize>> = HashMap::new();
+	for j in (0..new.len()).filter(|&j| !new_matched[j]) {
+		identical.entry((new[j].chunk_type().bytes(), new[j].data())).or_default().push_back(j);
+		by_type.entry(new[j].chunk_type().bytes()).or_default().push_back(j);
+	}
+
+	let mut changes = Vec::new();
+	let unmatched: Vec<usize> = (0..old.len()).filter(|&i| !old_matched[i]).collect();
+	let mut paired = vec![None; old.len()];
+	for &i in &unmatched {
+		if let Some(j) = identical.get_mut(&(old[i].chunk_type().bytes(), old[i].data())).and_then(VecDeque::pop_front) {
+			new_matched[j] = true;
+			paired[i] = Some(j);
+		}
+	}
+	for &i in &unmatched {
+		let chunk_type = old[i].chunk_type().clone();
+		let before = old_locations[i];
+		let same_type = by_type.get_mut(&chunk_type.bytes());
+		if let Some(j) = paired[i] {
+			changes.push(Change::Moved { chunk_type, before, after: new_locations[j] });
+		} else if let Some(j) = same_type.and_then(|queue| std::iter::from_fn(|| queue.pop_front()).find(|&j| !new_matched[j])) {
+			new_matched[j] = true;
+			changes.push(Change::Modified { chunk_type, before, after: new_locations[j] });
+		} else {
+			changes.push(Change::Removed { chunk_type, before });
+		}
+	}
+	changes.extend(
+		(0..new.len())
+			.filter(|&j| !new_matched[j])
+			.map(|j| Change::Inserted { chunk_type: new[j].chunk_type().clone(), after: new_locations[j] }),
+	);
+	changes
+}
+
+/// Compares the main images, first as compressed streams and then, if those
+/// differ, as decoded samples.
+pub fn image_data(before: &Png, after: &Png) -> ImageData {
+	if before.chunk_by_type(&ChunkType::IHDR).map(Chunk::data) == after.chunk_by_type(&ChunkType::IHDR).map(Chunk::data)
+		&& before.image_data() == after.image_data()
+	{
+		return ImageData::Identical;
+	}
+	let (before, after) = match (Raster::from_png(before), Raster::from_png(after)) {
+		(Ok(before), Ok(after)) => (before, after),
+		(Err(e), _) | (_, Err(e)) => return ImageData::Undecodable { error: e.to_string() },
+	};
+	if before.header() != after.header() {
+		return ImageData::Header;
+	}
+	let differing = before.data().iter().zip(after.data()).filter(|(a, b)| a != b).count();
+	match differing {
+		0 => ImageData::Recompressed,
+		_ => ImageData::Changed { differing, total: before.data().len() },
+	}
+}
+
+pub fn compare(before: &Png, after: &Png) -> Report {
+	Report { changes: diff(before, after), image_data: image_data(before, after) }
+}
+
+impl std::fmt::Display for Location {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "chunk {} at offset {}, {} bytes", self.index, self.offset, self.length)
+	}
+}
+
+impl std::fmt::Display for Change {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Change::Inserted { chunk_type, after } => write!(f, "+ {chunk_type} inserted as {after}"),
+			Change::Removed { chunk_type, before } => write!(f, "- {chunk_type} removed from {before}"),
+			Change::Modified { chunk_type, before, after } => write!(f, "~ {chunk_type} modified: {before} -> {after}"),
+			Change::Moved { chunk_type, before, after } => write!(f, "> {chunk_type} moved: {before} -> {after}"),
+		}
+	}
+}
+
+impl std::fmt::Display for ImageData {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ImageData::Identical => write!(f, "identical"),
+			ImageData::Recompressed => write!(f, "compressed data differs, decoded pixels are identical"),
+			ImageData::Changed { differing, total } => write!(f, "{differing} of {total} decoded sample bytes differ"),
+			ImageData::Header => write!(f, "dimensions or pixel format differ"),
+			ImageData::Undecodable { error } => write!(f, "cannot be compared: {error}"),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+	use crate::image::{ColorType, ImageHeader};
+	use flate2::Compression;
+
+	fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+		Chunk::new(chunk_type.parse().unwrap(), data.to_vec())
+	}
+
+	fn testing_png(middle: Vec<Chunk>) -> Png {
+		let mut chunks = vec![chunk("IHDR", &[0; 13])];
+		chunks.extend(middle);
+		chunks.push(chunk("IEND", &[]));
+		Png::from_chunks(chunks)
+	}
+
+	fn summary(changes: &[Change]) -> Vec<String> {
+		changes
+			.iter()
+			.map(|change| match change {
+				Change::Inserted { chunk_type, after } => format!("+{chunk_type}@{}", after.index),
+				Change::Removed { chunk_type, before } => format!("-{chunk_type}@{}", before.index),
+				Change::Modified { chunk_type, before, after } => format!("~{chunk_type}@{}>{}", before.index, after.index),
+				Change::Moved { chunk_type, before, after } => format!(">{chunk_type}@{}>{}", before.index, after.index),
+			})
+			.collect()
+	}
+
+	#[test]
+	fn test_identical_files_have_no_changes() {
+		let png = testing_png(vec![chunk("IDAT", b"image")]);
+		assert!(diff(&png, &png).is_empty());
+	}
+
+	#[test]
+	fn test_inserted_and_removed() {
+		let before = testing_png(vec![chunk("tEXt", b"a\0b"), chunk("IDAT", b"image")]);
+		let after = testing_png(vec![chunk("IDAT", b"image"), chunk("ruSt", b"hidden")]);
+		let changes = diff(&before, &after);
+		assert_eq!(summary(&changes), ["-tEXt@1", "+ruSt@2"]);
+		// 8 signature bytes, then IHDR (12 + 13) and IDAT (12 + 5)
+		let after = Location { index: 2, offset: 50, length: 6 };
+		assert_eq!(changes[1], Change::Inserted { chunk_type: "ruSt".parse().unwrap(), after });
+	}
+
+	#[test]
+	fn test_modified_chunks_are_paired_by_type() {
+		let before = testing_png(vec![chunk("gAMA", &[0, 0, 177, 143]), chunk("IDAT", b"image"), chunk("IDAT", b"more")]);
+		let after = testing_png(vec![chunk("gAMA", &[0, 1, 134, 160]), chunk("IDAT", b"image, longer")]);
+		assert_eq!(summary(&diff(&before, &after)), ["~gAMA@1>1", "~IDAT@2>2", "-IDAT@3"]);
+	}
+
+	#[test]
+	fn test_moved_chunks() {
+		let before = testing_png(vec![chunk("tEXt", b"a\0b"), chunk("tEXt", b"c\0d"), chunk("IDAT", b"image")]);
+		let after = testing_png(vec![chunk("IDAT", b"image"), chunk("tEXt", b"c\0e"), chunk("tEXt", b"a\0b")]);
+		assert_eq!(summary(&diff(&before, &after)), [">tEXt@1>3", "~tEXt@2>2"]);
+	}
+
+	#[test]
+	fn test_common_chunks_matches_idat_runs_as_a_whole() {
+		let idats = |data: &[u8]| data.chunks(1).map(|part| chunk("IDAT", part)).collect::<Vec<_>>();
+		let before = [vec![chunk("IHDR", &[0; 13]), chunk("tEXt", b"a\0b")], idats(b"image"), vec![chunk("IEND", &[])]].concat();
+		let after = [vec![chunk("IHDR", &[0; 13])], idats(b"imagf"), vec![chunk("tEXt", b"a\0b"), chunk("IEND", &[])]].concat();
+		// the IDAT runs differ, so none of their chunks are common
+		assert_eq!(common_chunks(&before, &after), [(0, 0), (1, 6), (7, 7)]);
+		assert_eq!(common_chunks(&after, &after).len(), after.len());
+
+		let many = testing_png(idats(&[7; 20_000]));
+		let changes = diff(&many, &testing_png(idats(&[8; 20_000])));
+		assert_eq!(changes.len(), 20_000);
+	}
+
+	#[test]
+	fn test_image_data_levels() {
+		let header = ImageHeader { width: 2, height: 2, bit_depth: 8, color_type: ColorType::Grayscale, interlaced: false };
+		let png = |raster: &Raster, split: bool| {
+			let zlib = raster.encode(Compression::best());
+			let mut chunks = vec![Chunk::new(ChunkType::IHDR, header.as_bytes().to_vec())];
+			match split {
+				true => chunks.extend(zlib.chunks(4).map(|part| Chunk::new(ChunkType::IDAT, part.to_vec()))),
+				false => chunks.push(Chunk::new(ChunkType::IDAT, zlib)),
+			}
+			chunks.push(Chunk::new(ChunkType::IEND, Vec::new()));
+			Png::from_chunks(chunks)
+		};
+		let raster = Raster::new(header, vec![1, 2, 3, 4]);
+		assert_eq!(image_data(&png(&raster, false), &png(&raster, true)), ImageData::Identical);
+
+		let mut recompressed = png(&raster, false);
+		let mut zlib = recompressed.image_data();
+		zlib.extend_from_slice(b"trailing");
+		recompressed.set_image_data(&zlib);
+		assert_eq!(image_data(&png(&raster, false), &recompressed), ImageData::Recompressed);
+
+		let changed = Raster::new(header, vec![1, 2, 3, 5]);
+		assert_eq!(image_data(&png(&raster, false), &png(&changed, false)), ImageData::Changed { differing: 1, total: 4 });
+	}
+}
@@ -0,0 +1,384 @@
+use clap::ValueEnum;
+use flate2::Compression;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::fec;
+use crate::image::{DecodeError, ImageError, Raster};
+use crate::png::Png;
+
+mod alpha;
+mod apng;
+mod lsb;
+mod order;
+mod palette;
+mod scatter;
+mod trailing;
+
+pub use scatter::ScatterKey;
+use scatter::Scatter;
+
+/// Where in the PNG a payload is hidden.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Method {
+	/// A dedicated ancillary chunk
+	Chunk,
+	/// Least significant bits of the colour samples
+	Lsb,
+	/// Colour samples of fully transparent pixels
+	Alpha,
+	/// Least significant bits of the APNG animation frames
+	Apng,
+	/// Bytes after the end of the zlib stream in the last IDAT chunk
+	Trailing,
+	/// Palette entries that no pixel refers to
+	Palette,
+	/// Order of the palette entries
+	PaletteOrder,
+}
+
+impl std::fmt::Display for Method {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let value = self.to_possible_value().expect("no method is skipped");
+		write!(f, "{}", value.get_name())
+	}
+}
+
+/// A method together with the parameters it needs. Pixel-domain methods
+/// use their carrier bits in a keyed pseudo-random order if given a key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+	Chunk(ChunkType),
+	Lsb { bits: u8, key: Option<ScatterKey> },
+	Alpha { alpha_lsb: bool, key: Option<ScatterKey> },
+	Apng { bits: u8, key: Option<ScatterKey> },
+	Trailing,
+	Palette { key: Option<ScatterKey> },
+	PaletteOrder { key: Option<ScatterKey> },
+}
+
+impl Target {
+	pub fn new(
+		method: Method,
+		bits: u8,
+		alpha_lsb: bool,
+		chunk_type: Option<ChunkType>,
+		key: Option<ScatterKey>,
+	) -> Result<Target, EmbedError> {
+		if alpha_lsb && method != Method::Alpha {
+			return Err(EmbedError::AlphaLsbNotApplicable);
+		}
+		Ok(match method {
+			Method::Chunk | Method::Trailing if key.is_some() => return Err(EmbedError::KeyNotApplicable),
+			Method::Chunk => Target::Chunk(chunk_type.ok_or(EmbedError::MissingChunkType)?),
+			Method::Lsb => Target::Lsb { bits, key },
+			Method::Alpha => Target::Alpha { alpha_lsb, key },
+			Method::Apng => Target::Apng { bits, key },
+			Method::Trailing => Target::Trailing,
+			Method::Palette => Target::Palette { key },
+			Method::PaletteOrder => Target::PaletteOrder { key },
+		})
+	}
+
+	pub fn method(&self) -> Method {
+		match self {
+			Target::Chunk(_) => Method::Chunk,
+			Target::Lsb { .. } => Method::Lsb,
+			Target::Alpha { .. } => Method::Alpha,
+			Target::Apng { .. } => Method::Apng,
+			Target::Trailing => Method::Trailing,
+			Target::Palette { .. } => Method::Palette,
+			Target::PaletteOrder { .. } => Method::PaletteOrder,
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EmbedError {
+	#[error(transparent)]
+	Image(#[from] ImageError),
+
+	#[error(transparent)]
+	Decode(#[from] DecodeError),
+
+	#[error("{method} embedding is not possible in this image: {reason}")]
+	Unsupported {
+		method: Method,
+		reason: &'static str,
+	},
+
+	#[error("payload needs {needed} bytes but only {available} fit using {method}")]
+	Capacity {
+		method: Method,
+		needed: usize,
+		available: usize,
+	},
+
+	#[error("malformed animation: {0}")]
+	Animation(&'static str),
+
+	#[error("a chunk type is required for the chunk method")]
+	MissingChunkType,
+
+	#[error("a key only applies to pixel-domain methods")]
+	KeyNotApplicable,
+
+	#[error("alpha LSBs only apply to the alpha method")]
+	AlphaLsbNotApplicable,
+}
+
+/// Bit-addressable storage inside the image data.
+pub(crate) trait Carrier {
+	fn bit_len(&self) -> usize;
+	fn bit(&self, index: usize) -> bool;
+	fn set_bit(&mut self, index: usize, value: bool);
+}
+
+// Pixel-domain payloads are prefixed with their length as a big-endian u32,
+// followed by Reed–Solomon check bytes so that a few flipped bits in the
+// prefix don't lose the whole payload.
+const LENGTH_PARITY: usize = 4;
+const LENGTH_PREFIX: usize = 4 + LENGTH_PARITY;
+
+fn usable_bytes(carrier: &impl Carrier) -> usize {
+	(carrier.bit_len() / 8).saturating_sub(LENGTH_PREFIX)
+}
+
+// Carrier bit positions in the order payload bits are stored.
+fn positions(carrier: &impl Carrier, key: Option<&ScatterKey>) -> Box<dyn Iterator<Item = usize>> {
+	match key {
+		Some(key) => Box::new(Scatter::new(key, carrier.bit_len())),
+		None => Box::new(0..carrier.bit_len()),
+	}
+}
+
+fn write_payload(
+	carrier: &mut impl Carrier,
+	key: Option<&ScatterKey>,
+	method: Method,
+	payload: &[u8],
+) -> Result<(), EmbedError> {
+	let available = usable_bytes(carrier);
+	if payload.len() > available {
+		return Err(EmbedError::Capacity { method, needed: payload.len(), available });
+	}
+
+	let length = fec::encode_block(&(payload.len() as u32).to_be_bytes(), LENGTH_PARITY);
+	let bits = length
+		.iter()
+		.chain(payload)
+		.flat_map(|byte| (0..8).rev().map(move |shift| (byte >> shift) & 1 == 1));
+	for (position, bit) in positions(carrier, key).zip(bits) {
+		carrier.set_bit(position, bit);
+	}
+	Ok(())
+}
+
+fn read_bytes(carrier: &impl Carrier, positions: &mut impl Iterator<Item = usize>, count: usize) -> Vec<u8> {
+	(0..count)
+		.map(|_| positions.take(8).fold(0u8, |acc, position| (acc << 1) | carrier.bit(position) as u8))
+		.collect()
+}
+
+fn read_payload(carrier: &impl Carrier, key: Option<&ScatterKey>) -> Option<Vec<u8>> {
+	if carrier.bit_len() < LENGTH_PREFIX * 8 {
+		return None;
+	}
+	let mut positions = positions(carrier, key);
+	let mut length = read_bytes(carrier, &mut positions, LENGTH_PREFIX);
+	fec::decode_block(&mut length, LENGTH_PARITY).ok()?;
+	let length = u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize;
+	if length > usable_bytes(carrier) {
+		return None;
+	}
+	Some(read_bytes(carrier, &mut positions, length))
+}
+
+/// Number of payload bytes `target` can hold in `png`.
+pub fn capacity(png: &Png, target: &Target) -> Result<usize, EmbedError> {
+	match target {
+		Target::Chunk(_) => Ok(Chunk::MAX_LENGTH as usize),
+		Target::Lsb { bits, .. } => {
+			let mut raster = Raster::from_png(png)?;
+			Ok(usable_bytes(&lsb::Lsb::new(&mut raster, *bits)?))
+		}
+		Target::Alpha { alpha_lsb, .. } => {
+			let mut raster = Raster::from_png(png)?;
+			Ok(usable_bytes(&alpha::Alpha::new(&mut raster, *alpha_lsb)?))
+		}
+		Target::Apng { bits, .. } => {
+			let mut frames = apng::Frames::from_png(png)?;
+			Ok(usable_bytes(&frames.carrier(*bits)?))
+		}
+		Target::Trailing => trailing::capacity(png),
+		Target::Palette { .. } => Ok(usable_bytes(&palette::Palette::from_png(png)?)),
+		Target::PaletteOrder { .. } => Ok(usable_bytes(&order::Order::from_png(png)?)),
+	}
+}
+
+/// Hides `payload` in `png`, failing if it doesn't fit. Methods that change
+/// the pixels deflate the image data again at `compression`.
+pub fn embed(png: &mut Png, target: &Target, payload: &[u8], compression: Compression) -> Result<(), EmbedError> {
+	let method = target.method();
+	match target {
+		Target::Chunk(chunk_type) => {
+			if payload.len() > Chunk::MAX_LENGTH as usize {
+				return Err(EmbedError::Capacity {
+					method,
+					needed: payload.len(),
+					available: Chunk::MAX_LENGTH as usize,
+				});
+			}
+			png.append_chunk(Chunk::new(chunk_type.clone(), payload.to_vec()));
+		}
+		Target::Lsb { bits, key } => {
+			let mut raster = Raster::from_png(png)?;
+			write_payload(&mut lsb::Lsb::new(&mut raster, *bits)?, key.as_ref(), method, payload)?;
+			png.set_image_data(&raster.encode(compression));
+		}
+		Target::Alpha { alpha_lsb, key } => {
+			let mut raster = Raster::from_png(png)?;
+			write_payload(&mut alpha::Alpha::new(&mut raster, *alpha_lsb)?, key.as_ref(), method, payload)?;
+			png.set_image_data(&raster.encode(compression));
+		}
+		Target::Apng { bits, key } => {
+			let mut frames = apng::Frames::from_png(png)?;
+			write_payload(&mut frames.carrier(*bits)?, key.as_ref(), method, payload)?;
+			frames.write_to(png, compression);
+		}
+		Target::Trailing => trailing::write(png, payload)?,
+		Target::Palette { key } => {
+			let mut palette = palette::Palette::from_png(png)?;
+			write_payload(&mut palette, key.as_ref(), method, payload)?;
+			palette.write_to(png);
+		}
+		Target::PaletteOrder { key } => {
+			let mut order = order::Order::from_png(png)?;
+			write_payload(&mut order, key.as_ref(), method, payload)?;
+			order.write_to(png, compression);
+		}
+	}
+	Ok(())
+}
+
+/// Recovers a payload hidden with `target`, if there is one.
+pub fn extract(png: &Png, target: &Target) -> Result<Option<Vec<u8>>, EmbedError> {
+	Ok(match target {
+		Target::Chunk(chunk_type) => png.chunk_by_type(chunk_type).map(|chunk| chunk.data().to_vec()),
+		Target::Lsb { bits, key } => {
+			let mut raster = Raster::from_png(png)?;
+			read_payload(&lsb::Lsb::new(&mut raster, *bits)?, key.as_ref())
+		}
+		Target::Alpha { alpha_lsb, key } => {
+			let mut raster = Raster::from_png(png)?;
+			read_payload(&alpha::Alpha::new(&mut raster, *alpha_lsb)?, key.as_ref())
+		}
+		Target::Apng { bits, key } => {
+			let mut frames = apng::Frames::from_png(png)?;
+			read_payload(&frames.carrier(*bits)?, key.as_ref())
+		}
+		Target::Trailing => trailing::read(png)?,
+		Target::Palette { key } => read_payload(&palette::Palette::from_png(png)?, key.as_ref()),
+		Target::PaletteOrder { key } => read_payload(&order::Order::from_png(png)?, key.as_ref()),
+	})
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+	use crate::image::{ColorType, ImageHeader};
+
+	/// Builds a PNG from raw unfiltered pixel data.
+	pub(crate) fn testing_png(header: ImageHeader, data: Vec<u8>) -> Png {
+		Png::from_chunks(vec![
+			Chunk::new(ChunkType::IHDR, header.as_bytes().to_vec()),
+			Chunk::new(ChunkType::IDAT, Raster::new(header, data).encode(Compression::best())),
+			Chunk::new(ChunkType::IEND, Vec::new()),
+		])
+	}
+
+	pub(crate) fn rgba_header(width: u32, height: u32) -> ImageHeader {
+		ImageHeader { width, height, bit_depth: 8, color_type: ColorType::Rgba, interlaced: false }
+	}
+
+	#[test]
+	fn test_lsb_roundtrip() {
+		let header = rgba_header(16, 16);
+		let mut png = testing_png(header, vec![200; header.data_len().unwrap()]);
+		let target = Target::Lsb { bits: 2, key: None };
+		embed(&mut png, &target, b"hidden in plain sight", Compression::best()).unwrap();
+
+		let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+		assert_eq!(extract(&png, &target).unwrap().unwrap(), b"hidden in plain sight");
+	}
+
+	#[test]
+	fn test_capacity_depends_on_bits() {
+		let header = rgba_header(16, 16);
+		let png = testing_png(header, vec![0; header.data_len().unwrap()]);
+		// 256 pixels * 3 colour channels, minus the length prefix
+		assert_eq!(capacity(&png, &Target::Lsb { bits: 1, key: None }).unwrap(), 96 - 8);
+		assert_eq!(capacity(&png, &Target::Lsb { bits: 4, key: None }).unwrap(), 384 - 8);
+	}
+
+	#[test]
+	fn test_payload_too_large() {
+		let header = rgba_header(6, 6);
+		let mut png = testing_png(header, vec![0; header.data_len().unwrap()]);
+		let result = embed(&mut png, &Target::Lsb { bits: 1, key: None }, &[0; 64], Compression::best());
+		assert!(matches!(result, Err(EmbedError::Capacity { needed: 64, available: 5, .. })));
+	}
+
+	#[test]
+	fn test_length_prefix_survives_bit_flips() {
+		let header = rgba_header(16, 16);
+		let mut png = testing_png(header, vec![0; header.data_len().unwrap()]);
+		let target = Target::Lsb { bits: 1, key: None };
+		embed(&mut png, &target, b"payload", Compression::best()).unwrap();
+
+		let mut raster = Raster::from_png(&png).unwrap();
+		// first colour samples of the first two pixels hold prefix bits
+		raster.data_mut()[0] ^= 1;
+		raster.data_mut()[4] ^= 1;
+		png.set_image_data(&raster.encode(Compression::best()));
+		assert_eq!(extract(&png, &target).unwrap().unwrap(), b"payload");
+	}
+
+	#[test]
+	fn test_chunk_target_requires_chunk_type() {
+		assert!(matches!(Target::new(Method::Chunk, 1, false, None, None), Err(EmbedError::MissingChunkType)));
+		assert_eq!(Target::new(Method::Lsb, 3, false, None, None).unwrap(), Target::Lsb { bits: 3, key: None });
+	}
+
+	#[test]
+	fn test_keyed_lsb_scatters_bits() {
+		let header = rgba_header(32, 32);
+		let original = testing_png(header, vec![0; header.data_len().unwrap()]);
+		let key = ScatterKey::from_passphrase("hunter2");
+		let target = Target::Lsb { bits: 1, key: Some(key) };
+		let mut png = Png::try_from(original.as_bytes().as_slice()).unwrap();
+		embed(&mut png, &target, &[0xff; 16], Compression::best()).unwrap();
+
+		let changed: Vec<usize> = Raster::from_png(&png)
+			.unwrap()
+			.data()
+			.iter()
+			.enumerate()
+			.filter(|(_, byte)| **byte != 0)
+			.map(|(index, _)| index)
+			.collect();
+		// the 128 set payload bits must not all land in the first rows
+		assert!(changed.iter().any(|&index| index >= header.data_len().unwrap() / 2));
+
+		assert_eq!(extract(&png, &target).unwrap().unwrap(), vec![0xff; 16]);
+		let wrong_key = Target::Lsb { bits: 1, key: Some(ScatterKey::from_passphrase("hunter3")) };
+		assert_ne!(extract(&png, &wrong_key).unwrap(), Some(vec![0xff; 16]));
+	}
+
+	#[test]
+	fn test_chunk_target_rejects_key() {
+		let chunk_type = Some(ChunkType::IEND);
+		let key = Some(ScatterKey::from_passphrase("key"));
+		assert!(matches!(Target::new(Method::Chunk, 1, false, chunk_type, key), Err(EmbedError::KeyNotApplicable)));
+	}
+}
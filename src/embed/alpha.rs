@@ -0,0 +1,131 @@
+use crate::embed::{Carrier, EmbedError, Method};
+use crate::image::Raster;
+
+/// Every bit of the colour samples of fully transparent pixels, which no
+/// viewer renders, optionally followed by the least significant alpha bit of
+/// pixels with an alpha of at least 2, which stay visibly the same.
+pub(crate) struct Alpha<'a> {
+	data: &'a mut [u8],
+	colour: Vec<usize>,
+	alpha: Vec<usize>,
+}
+
+impl<'a> Alpha<'a> {
+	pub(crate) fn new(raster: &'a mut Raster, alpha_lsb: bool) -> Result<Alpha<'a>, EmbedError> {
+		let header = *raster.header();
+		if !header.color_type.has_alpha() {
+			return Err(EmbedError::Unsupported {
+				method: Method::Alpha,
+				reason: "image has no alpha channel",
+			});
+		}
+
+		let sample_bytes = header.bit_depth as usize / 8;
+		let pixel_bytes = header.color_type.channels() * sample_bytes;
+		let alpha_offset = pixel_bytes - sample_bytes;
+		let data = raster.data_mut();
+		// start offset and alpha value of every pixel
+		let pixels: Vec<(usize, u16)> = data
+			.chunks_exact(pixel_bytes)
+			.enumerate()
+			.map(|(pixel, bytes)| {
+				let alpha = bytes[alpha_offset..].iter().fold(0, |value, &byte| value << 8 | byte as u16);
+				(pixel * pixel_bytes, alpha)
+			})
+			.collect();
+
+		let colour = pixels
+			.iter()
+			.filter(|(_, alpha)| *alpha == 0)
+			.flat_map(|&(start, _)| start..start + alpha_offset)
+			.collect();
+		// an alpha of 0 or 1 could flip between transparent and not
+		let alpha = pixels
+			.iter()
+			.filter(|(_, alpha)| alpha_lsb && *alpha > 1)
+			.map(|&(start, _)| start + pixel_bytes - 1)
+			.collect();
+		Ok(Alpha { data, colour, alpha })
+	}
+}
+
+impl Carrier for Alpha<'_> {
+	fn bit_len(&self) -> usize {
+		self.colour.len() * 8 + self.alpha.len()
+	}
+
+	fn bit(&self, index: usize) -> bool {
+		match index.checked_sub(self.colour.len() * 8) {
+			None => self.data[self.colour[index / 8]] >> (7 - index % 8) & 1 == 1,
+			Some(index) => self.data[self.alpha[index]] & 1 == 1,
+		}
+	}
+
+	fn set_bit(&mut self, index: usize, value: bool) {
+		let (byte, mask) = match index.checked_sub(self.colour.len() * 8) {
+			None => (&mut self.data[self.colour[index / 8]], 1 << (7 - index % 8)),
+			Some(index) => (&mut self.data[self.alpha[index]], 1),
+		};
+		*byte = (*byte & !mask) | if value { mask } else { 0 };
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+	use crate::embed::tests::{rgba_header, testing_png};
+	use crate::embed::{embed, extract, capacity, Target};
+	use flate2::Compression;
+	use crate::image::{ColorType, ImageHeader};
+
+	#[test]
+	fn test_only_transparent_pixels_change() {
+		let header = rgba_header(4, 4);
+		let data: Vec<u8> = (0..16).flat_map(|pixel| [90, 90, 90, if pixel % 2 == 0 { 0 } else { 255 }]).collect();
+		let mut png = testing_png(header, data.clone());
+		assert_eq!(capacity(&png, &Target::Alpha { alpha_lsb: false, key: None }).unwrap(), 8 * 3 - 8);
+
+		embed(&mut png, &Target::Alpha { alpha_lsb: false, key: None }, b"invisible", Compression::best()).unwrap();
+		let embedded = Raster::from_png(&png).unwrap();
+		for (pixel, (before, after)) in data.chunks(4).zip(embedded.data().chunks(4)).enumerate() {
+			if pixel % 2 == 1 {
+				assert_eq!(before, after);
+			}
+			assert_eq!(before[3], after[3]);
+		}
+		assert_eq!(extract(&png, &Target::Alpha { alpha_lsb: false, key: None }).unwrap().unwrap(), b"invisible");
+	}
+
+	#[test]
+	fn test_sixteen_bit_grey_alpha_with_alpha_lsb() {
+		let header = ImageHeader { width: 4, height: 2, bit_depth: 16, color_type: ColorType::GrayscaleAlpha, interlaced: false };
+		// alternating transparent and half-opaque pixels: grey 0x1234, alpha 0 or 0x8000
+		let data: Vec<u8> = (0..8).flat_map(|pixel| [0x12, 0x34, if pixel % 2 == 0 { 0 } else { 0x80 }, 0]).collect();
+		let mut png = testing_png(header, data.clone());
+		let target = Target::Alpha { alpha_lsb: true, key: None };
+		// 4 transparent pixels * 2 grey bytes * 8 bits, plus 4 alpha LSBs
+		assert_eq!(Alpha::new(&mut Raster::from_png(&png).unwrap(), true).unwrap().bit_len(), 4 * 16 + 4);
+		assert_eq!(capacity(&png, &target).unwrap(), 0);
+
+		let header = ImageHeader { height: 8, ..header };
+		let data: Vec<u8> = data.repeat(4);
+		png = testing_png(header, data.clone());
+		embed(&mut png, &target, b"sixteen", Compression::best()).unwrap();
+		let embedded = Raster::from_png(&png).unwrap();
+		for (before, after) in data.chunks(4).zip(embedded.data().chunks(4)) {
+			if before[2] != 0 {
+				assert_eq!(before[..3], after[..3]);
+			}
+			assert_eq!(before[2], after[2]);
+		}
+		assert_eq!(extract(&png, &target).unwrap().unwrap(), b"sixteen");
+	}
+
+	#[test]
+	fn test_requires_alpha_channel() {
+		let header = ImageHeader { color_type: ColorType::Rgb, ..rgba_header(2, 2) };
+		let png = testing_png(header, vec![0; header.data_len().unwrap()]);
+		assert!(matches!(capacity(&png, &Target::Alpha { alpha_lsb: false, key: None }), Err(EmbedError::Unsupported { .. })));
+	}
+}
@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+use flate2::Compression;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::embed::{EmbedError, Method};
+use crate::embed::lsb::Lsb;
+use crate::image::{ImageHeader, Raster};
+use crate::png::Png;
+
+const FRAME_CONTROL_LEN: usize = 26;
+const SEQUENCE_LEN: usize = 4;
+
+/// The animation frames of an APNG that are stored in fdAT chunks. A frame
+/// whose data is the IDAT default image is skipped, the `lsb` method covers it.
+pub(crate) struct Frames {
+	header: ImageHeader,
+	rasters: Vec<Raster>,
+	chunk_indices: Vec<Vec<usize>>,
+	data: Vec<u8>,
+}
+
+impl Frames {
+	pub(crate) fn from_png(png: &Png) -> Result<Frames, EmbedError> {
+		let header = ImageHeader::from_png(png)?;
+		if png.chunk_by_type(&ChunkType::ACTL).is_none() {
+			return Err(EmbedError::Unsupported {
+				method: Method::Apng,
+				reason: "not an animated PNG",
+			});
+		}
+
+		// (width, height, fdAT chunk indices, uses the default image)
+		let mut layout: Vec<(u32, u32, Vec<usize>, bool)> = Vec::new();
+		for (index, chunk) in png.chunks().iter().enumerate() {
+			match *chunk.chunk_type() {
+				ChunkType::FCTL => {
+					let data = chunk.data();
+					if data.len() != FRAME_CONTROL_LEN {
+						return Err(EmbedError::Animation("fcTL chunk has the wrong length"));
+					}
+					let width = u32::from_be_bytes([data[4], data[5], data[6], data[7]]);
+					let height = u32::from_be_bytes([data[8], data[9], data[10], data[11]]);
+					if width == 0 || height == 0 {
+						return Err(EmbedError::Animation("frame has zero width or height"));
+					}
+					// the frame must lie within the canvas, which also bounds its size
+					let x_offset = u32::from_be_bytes([data[12], data[13], data[14], data[15]]);
+					let y_offset = u32::from_be_bytes([data[16], data[17], data[18], data[19]]);
+					if x_offset.checked_add(width).is_none_or(|right| right > header.width)
+						|| y_offset.checked_add(height).is_none_or(|bottom| bottom > header.height)
+					{
+						return Err(EmbedError::Animation("frame extends outside the image"));
+					}
+					layout.push((width, height, Vec::new(), false));
+				}
+				ChunkType::IDAT => {
+					if let Some(frame) = layout.last_mut().filter(|frame| frame.2.is_empty()) {
+						frame.3 = true;
+					}
+				}
+				ChunkType::FDAT => {
+					let frame = layout
+						.last_mut()
+						.ok_or(EmbedError::Animation("fdAT chunk before the first fcTL"))?;
+					if chunk.data().len() < SEQUENCE_LEN {
+						return Err(EmbedError::Animation("fdAT chunk has no sequence number"));
+					}
+					frame.2.push(index);
+				}
+				_ => {}
+			}
+		}
+
+		let mut rasters = Vec::new();
+		let mut chunk_indices = Vec::new();
+		for (width, height, indices, _) in layout.into_iter().filter(|frame| !frame.3 && !frame.2.is_empty()) {
+			let zlib: Vec<u8> = indices
+				.iter()
+				.flat_map(|&index| png.chunks()[index].data()[SEQUENCE_LEN..].iter().copied())
+				.collect();
+			rasters.push(Raster::decode(header.with_size(width, height), &zlib)?);
+			chunk_indices.push(indices);
+		}
+		let data = rasters.iter().flat_map(|raster| raster.data().iter().copied()).collect();
+
+		Ok(Frames { header, rasters, chunk_indices, data })
+	}
+
+	pub(crate) fn carrier(&mut self, bits: u8) -> Result<Lsb<'_>, EmbedError> {
+		Lsb::with_header(&self.header, &mut self.data, bits).map_err(|error| match error {
+			EmbedError::Unsupported { reason, .. } => EmbedError::Unsupported { method: Method::Apng, reason },
+			other => other,
+		})
+	}
+
+	/// Writes the frames back, one fdAT chunk per frame, and renumbers the
+	/// animation sequence.
+	pub(crate) fn write_to(mut self, png: &mut Png, compression: Compression) {
+		let mut offset = 0;
+		let mut encoded = Vec::new();
+		for raster in &mut self.rasters {
+			let len = raster.data().len();
+			raster.data_mut().copy_from_slice(&self.data[offset..offset + len]);
+			offset += len;
+			encoded.push(raster.encode(compression));
+		}
+
+		let first_indices: Vec<usize> = self.chunk_indices.iter().map(|indices| indices[0]).collect();
+		let dropped: HashSet<usize> = self.chunk_indices.iter().flat_map(|indices| indices[1..].iter().copied()).collect();
+		let mut sequence = 0u32;
+		let mut chunks = Vec::with_capacity(png.chunks().len());
+		for (index, chunk) in png.chunks().iter().enumerate() {
+			if dropped.contains(&index) {
+				continue;
+			}
+			let body = match first_indices.iter().position(|&first| first == index) {
+				Some(frame) => &encoded[frame][..],
+				None => chunk.data().get(SEQUENCE_LEN..).unwrap_or_default(),
+			};
+			let chunk = match *chunk.chunk_type() {
+				ChunkType::FCTL | ChunkType::FDAT => {
+					let data = sequence.to_be_bytes().iter().chain(body).copied().collect();
+					sequence += 1;
+					Chunk::new(chunk.chunk_type().clone(), data)
+				}
+				_ => chunk.clone(),
+			};
+			chunks.push(chunk);
+		}
+		*png = Png::from_chunks(chunks);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+	use crate::embed::tests::{rgba_header, testing_png};
+	use crate::embed::{capacity, embed, extract, Target};
+
+	fn frame_control(sequence: u32, width: u32, height: u32) -> Chunk {
+		let data = [sequence, width, height, 0, 0]
+			.iter()
+			.flat_map(|value| value.to_be_bytes())
+			.chain([0, 1, 0, 1, 0, 0])
+			.collect();
+		Chunk::new(ChunkType::FCTL, data)
+	}
+
+	fn frame_data(sequence: u32, zlib: &[u8]) -> Chunk {
+		Chunk::new(ChunkType::FDAT, sequence.to_be_bytes().iter().chain(zlib).copied().collect())
+	}
+
+	fn testing_apng() -> Png {
+		let header = rgba_header(8, 8);
+		let png = testing_png(header, vec![128; header.data_len().unwrap()]);
+		let frame = Raster::new(header.with_size(4, 4), vec![64; 4 * 4 * 4]).encode(Compression::best());
+		let (first, second) = frame.split_at(frame.len() / 2);
+
+		let mut chunks = png.chunks().to_vec();
+		let iend = chunks.pop().unwrap();
+		chunks.insert(1, Chunk::new(ChunkType::ACTL, [0, 0, 0, 2, 0, 0, 0, 0].to_vec()));
+		chunks.insert(2, frame_control(0, 8, 8));
+		chunks.push(frame_control(1, 4, 4));
+		chunks.push(frame_data(2, first));
+		chunks.push(frame_data(3, second));
+		chunks.push(iend);
+		Png::from_chunks(chunks)
+	}
+
+	#[test]
+	fn test_default_image_is_skipped() {
+		let png = testing_apng();
+		// one 4x4 frame: 16 pixels * 3 colour channels * 4 bits
+		assert_eq!(capacity(&png, &Target::Apng { bits: 4, key: None }).unwrap(), 24 - 8);
+	}
+
+	#[test]
+	fn test_apng_roundtrip_renumbers_sequence() {
+		let mut png = testing_apng();
+		embed(&mut png, &Target::Apng { bits: 4, key: None }, b"frame", Compression::best()).unwrap();
+
+		let sequences: Vec<u32> = png
+			.chunks()
+			.iter()
+			.filter(|chunk| matches!(*chunk.chunk_type(), ChunkType::FCTL | ChunkType::FDAT))
+			.map(|chunk| u32::from_be_bytes(chunk.data()[..4].try_into().unwrap()))
+			.collect();
+		assert_eq!(sequences, vec![0, 1, 2]);
+		assert_eq!(extract(&png, &Target::Apng { bits: 4, key: None }).unwrap().unwrap(), b"frame");
+	}
+
+	#[test]
+	fn test_frame_outside_the_image() {
+		let mut chunks = testing_apng().chunks().to_vec();
+		let frame = chunks.iter().rposition(|chunk| chunk.chunk_type() == &ChunkType::FCTL).unwrap();
+		chunks[frame] = frame_control(1, u32::MAX, 4);
+		let png = Png::from_chunks(chunks);
+		assert!(matches!(capacity(&png, &Target::Apng { bits: 1, key: None }), Err(EmbedError::Animation(_))));
+	}
+
+	#[test]
+	fn test_static_png_is_unsupported() {
+		let header = rgba_header(2, 2);
+		let png = testing_png(header, vec![0; header.data_len().unwrap()]);
+		assert!(matches!(capacity(&png, &Target::Apng { bits: 1, key: None }), Err(EmbedError::Unsupported { .. })));
+	}
+}
@@ -0,0 +1,103 @@
+use crate::embed::{Carrier, EmbedError, Method};
+use crate::image::{ColorType, ImageHeader, Raster};
+
+/// The low `bits` bits of every colour sample; alpha samples are left alone.
+/// For 16-bit images only the less significant byte of a sample is used.
+pub(crate) struct Lsb<'a> {
+	data: &'a mut [u8],
+	bits: usize,
+	pixel_bytes: usize,
+	sample_bytes: usize,
+	colour_channels: usize,
+}
+
+impl<'a> Lsb<'a> {
+	pub(crate) fn new(raster: &'a mut Raster, bits: u8) -> Result<Lsb<'a>, EmbedError> {
+		let header = *raster.header();
+		Lsb::with_header(&header, raster.data_mut(), bits)
+	}
+
+	/// Carrier over unfiltered `data` laid out as described by `header`.
+	pub(crate) fn with_header(header: &ImageHeader, data: &'a mut [u8], bits: u8) -> Result<Lsb<'a>, EmbedError> {
+		if header.color_type == ColorType::Indexed {
+			return Err(EmbedError::Unsupported {
+				method: Method::Lsb,
+				reason: "indexed images store palette indices, not colour samples",
+			});
+		}
+		if header.bit_depth < 8 {
+			return Err(EmbedError::Unsupported {
+				method: Method::Lsb,
+				reason: "bit depths below 8 are not supported",
+			});
+		}
+
+		let sample_bytes = header.bit_depth as usize / 8;
+		let colour_channels = match header.color_type.has_alpha() {
+			true => header.color_type.channels() - 1,
+			false => header.color_type.channels(),
+		};
+		Ok(Lsb {
+			data,
+			bits: bits.clamp(1, 8) as usize,
+			pixel_bytes: header.color_type.channels() * sample_bytes,
+			sample_bytes,
+			colour_channels,
+		})
+	}
+
+	fn locate(&self, index: usize) -> (usize, usize) {
+		let sample = index / self.bits;
+		let pixel = sample / self.colour_channels;
+		let channel = sample % self.colour_channels;
+		let byte = pixel * self.pixel_bytes + (channel + 1) * self.sample_bytes - 1;
+		(byte, index % self.bits)
+	}
+}
+
+impl Carrier for Lsb<'_> {
+	fn bit_len(&self) -> usize {
+		self.data.len() / self.pixel_bytes * self.colour_channels * self.bits
+	}
+
+	fn bit(&self, index: usize) -> bool {
+		let (byte, bit) = self.locate(index);
+		self.data[byte] >> bit & 1 == 1
+	}
+
+	fn set_bit(&mut self, index: usize, value: bool) {
+		let (byte, bit) = self.locate(index);
+		self.data[byte] = (self.data[byte] & !(1 << bit)) | ((value as u8) << bit);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+
+	#[test]
+	fn test_alpha_samples_are_untouched() {
+		let header = crate::embed::tests::rgba_header(2, 1);
+		let mut data = vec![0u8; 8];
+		let mut lsb = Lsb::with_header(&header, &mut data, 1).unwrap();
+		assert_eq!(lsb.bit_len(), 6);
+		(0..6).for_each(|index| lsb.set_bit(index, true));
+		assert_eq!(data, vec![1, 1, 1, 0, 1, 1, 1, 0]);
+	}
+
+	#[test]
+	fn test_sixteen_bit_uses_low_byte() {
+		let header = ImageHeader { width: 1, height: 1, bit_depth: 16, color_type: ColorType::Grayscale, interlaced: false };
+		let mut data = vec![0u8; 2];
+		let mut lsb = Lsb::with_header(&header, &mut data, 3).unwrap();
+		(0..3).for_each(|index| lsb.set_bit(index, true));
+		assert_eq!(data, vec![0, 0b111]);
+	}
+
+	#[test]
+	fn test_indexed_is_unsupported() {
+		let header = ImageHeader { width: 1, height: 1, bit_depth: 8, color_type: ColorType::Indexed, interlaced: false };
+		assert!(Lsb::with_header(&header, &mut [0], 1).is_err());
+	}
+}
@@ -0,0 +1,281 @@
+use flate2::Compression;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::embed::palette::{indices, set_indices};
+use crate::embed::{Carrier, EmbedError, Method};
+use crate::image::{ColorType, ImageHeader, Raster};
+use crate::png::Png;
+
+// Big-endian unsigned integers of arbitrary size, just enough arithmetic to
+// convert between bits and the factorial number system.
+
+fn mul_add(number: &mut Vec<u8>, factor: u32, addend: u32) {
+	let mut carry = addend;
+	for byte in number.iter_mut().rev() {
+		let value = *byte as u32 * factor + carry;
+		*byte = value as u8;
+		carry = value >> 8;
+	}
+	while carry > 0 {
+		number.insert(0, carry as u8);
+		carry >>= 8;
+	}
+}
+
+fn div_rem(number: &mut [u8], divisor: u32) -> u32 {
+	let mut remainder = 0;
+	for byte in number.iter_mut() {
+		let value = remainder << 8 | *byte as u32;
+		*byte = (value / divisor) as u8;
+		remainder = value % divisor;
+	}
+	remainder
+}
+
+// floor(log2(n!)): the number of bits any permutation of n entries can hold.
+fn permutation_bits(n: usize) -> usize {
+	let mut factorial = vec![1u8];
+	(2..=n as u32).for_each(|k| mul_add(&mut factorial, k, 0));
+	let leading = factorial.iter().take_while(|&&byte| byte == 0).count();
+	let significant = &factorial[leading..];
+	significant.len() * 8 - significant[0].leading_zeros() as usize - 1
+}
+
+/// Payload bits stored in the order of the PLTE entries. Entries are ranked
+/// by their RGBA value; the permutation of those ranks encodes a number in
+/// the factorial number system. Pixel indices, bKGD and hIST are remapped to
+/// follow their entries, so the image looks exactly the same.
+pub(crate) struct Order {
+	raster: Raster,
+	entries: Vec<[u8; 4]>,
+	has_trns: bool,
+	bits: Vec<bool>,
+}
+
+impl Order {
+	pub(crate) fn from_png(png: &Png) -> Result<Order, EmbedError> {
+		let unsupported = |reason| EmbedError::Unsupported { method: Method::PaletteOrder, reason };
+		let header = ImageHeader::from_png(png)?;
+		if header.color_type != ColorType::Indexed {
+			return Err(unsupported("only indexed-colour images have a palette"));
+		}
+		let plte = png.chunk_by_type(&ChunkType::PLTE).ok_or(unsupported("image has no PLTE chunk"))?;
+		let trns = png.chunk_by_type(&ChunkType::TRNS).map(Chunk::data);
+
+		let entries: Vec<[u8; 4]> = plte
+			.data()
+			.chunks_exact(3)
+			.enumerate()
+			.map(|(entry, rgb)| {
+				let alpha = trns.and_then(|trns| trns.get(entry)).copied().unwrap_or(u8::MAX);
+				[rgb[0], rgb[1], rgb[2], alpha]
+			})
+			.collect();
+		let mut ranked = entries.clone();
+		ranked.sort();
+		ranked.dedup();
+		if ranked.len() != entries.len() {
+			return Err(unsupported("palette has duplicate entries"));
+		}
+
+		let raster = Raster::from_png(png)?;
+		if indices(&raster).iter().any(|&index| index as usize >= entries.len()) {
+			return Err(unsupported("a pixel refers to an entry past the end of the palette"));
+		}
+
+		let mut order = Order {
+			raster,
+			entries,
+			has_trns: trns.is_some(),
+			bits: Vec::new(),
+		};
+		order.bits = order.read_bits(&ranked);
+		Ok(order)
+	}
+
+	fn read_bits(&self, ranked: &[[u8; 4]]) -> Vec<bool> {
+		let bit_len = permutation_bits(self.entries.len());
+		let mut remaining = ranked.to_vec();
+		let mut number = vec![0u8];
+		for (position, entry) in self.entries.iter().enumerate() {
+			let digit = remaining.iter().position(|candidate| candidate == entry).expect("entries are ranked");
+			remaining.remove(digit);
+			// Horner's scheme over radices n, n - 1, …, 1
+			mul_add(&mut number, (self.entries.len() - position) as u32, digit as u32);
+		}
+		let mut bits: Vec<bool> = (0..bit_len).map(|_| div_rem(&mut number, 2) == 1).collect();
+		bits.reverse();
+		bits
+	}
+
+	/// Reorders the palette to match the carrier bits and remaps the pixels.
+	pub(crate) fn write_to(mut self, png: &mut Png, compression: Compression) {
+		let n = self.entries.len();
+		let mut number = vec![0u8];
+		self.bits.iter().for_each(|&bit| mul_add(&mut number, 2, bit as u32));
+		// least significant digit has radix 1 and belongs to the last position
+		let digits: Vec<usize> = (1..=n as u32).map(|radix| div_rem(&mut number, radix) as usize).collect();
+
+		let mut remaining = self.entries.clone();
+		remaining.sort();
+		let reordered: Vec<[u8; 4]> = digits.iter().rev().map(|&digit| remaining.remove(digit)).collect();
+
+		let new_index: Vec<u8> = self
+			.entries
+			.iter()
+			.map(|entry| reordered.iter().position(|candidate| candidate == entry).expect("same entries") as u8)
+			.collect();
+		let remapped: Vec<u8> = indices(&self.raster).iter().map(|&index| new_index[index as usize]).collect();
+		set_indices(&mut self.raster, &remapped);
+
+		let plte: Vec<u8> = reordered.iter().flat_map(|entry| entry[..3].to_vec()).collect();
+		let trns: Vec<u8> = reordered.iter().map(|entry| entry[3]).collect();
+		// bKGD and hIST refer to entries by their index
+		let remap = |chunk: &Chunk| match chunk.data() {
+			&[index] if (index as usize) < n => Chunk::new(ChunkType::BKGD, vec![new_index[index as usize]]),
+			_ => chunk.clone(),
+		};
+		let permute = |chunk: &Chunk| {
+			if chunk.data().len() != 2 * n {
+				return chunk.clone();
+			}
+			let mut hist = vec![0; 2 * n];
+			for (old, frequency) in chunk.data().chunks_exact(2).enumerate() {
+				let new = new_index[old] as usize;
+				hist[2 * new..2 * new + 2].copy_from_slice(frequency);
+			}
+			Chunk::new(ChunkType::HIST, hist)
+		};
+		let chunks = png
+			.chunks()
+			.iter()
+			.map(|chunk| match *chunk.chunk_type() {
+				ChunkType::PLTE => Chunk::new(ChunkType::PLTE, plte.clone()),
+				ChunkType::TRNS if self.has_trns => Chunk::new(ChunkType::TRNS, trns.clone()),
+				ChunkType::BKGD => remap(chunk),
+				ChunkType::HIST => permute(chunk),
+				_ => chunk.clone(),
+			})
+			.collect();
+		*png = Png::from_chunks(chunks);
+		png.set_image_data(&self.raster.encode(compression));
+	}
+}
+
+impl Carrier for Order {
+	fn bit_len(&self) -> usize {
+		self.bits.len()
+	}
+
+	fn bit(&self, index: usize) -> bool {
+		self.bits[index]
+	}
+
+	fn set_bit(&mut self, index: usize, value: bool) {
+		self.bits[index] = value;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+	use crate::embed::{capacity, embed, extract, Target};
+
+	fn indexed_png(entries: usize) -> Png {
+		let header = ImageHeader { width: 16, height: 16, bit_depth: 8, color_type: ColorType::Indexed, interlaced: false };
+		let indices = (0..256).map(|i| (i % entries) as u8).collect();
+		Png::from_chunks(vec![
+			Chunk::new(ChunkType::IHDR, header.as_bytes().to_vec()),
+			Chunk::new(ChunkType::PLTE, (0..entries * 3).map(|i| (i * 7) as u8).collect()),
+			Chunk::new(ChunkType::TRNS, (0..entries / 2).map(|i| i as u8).collect()),
+			Chunk::new(ChunkType::IDAT, Raster::new(header, indices).encode(Compression::best())),
+			Chunk::new(ChunkType::IEND, Vec::new()),
+		])
+	}
+
+	// Colour of every pixel, which must not change.
+	fn rendered(png: &Png) -> Vec<[u8; 4]> {
+		let order = Order::from_png(png).unwrap();
+		indices(&order.raster).iter().map(|&index| order.entries[index as usize]).collect()
+	}
+
+	#[test]
+	fn test_permutation_bits() {
+		assert_eq!(permutation_bits(1), 0);
+		assert_eq!(permutation_bits(4), 4); // 24
+		assert_eq!(permutation_bits(20), 61);
+		assert_eq!(permutation_bits(256), 1683);
+	}
+
+	#[test]
+	fn test_roundtrip_keeps_rendering() {
+		let png = indexed_png(64);
+		let target = Target::PaletteOrder { key: None };
+		// log2(64!) is about 295.9 bits
+		assert_eq!(capacity(&png, &target).unwrap(), 295 / 8 - 8);
+
+		let mut embedded = Png::try_from(png.as_bytes().as_slice()).unwrap();
+		embed(&mut embedded, &target, b"reordered", Compression::best()).unwrap();
+		assert_ne!(embedded.chunk_by_type(&ChunkType::PLTE).unwrap().data(), png.chunk_by_type(&ChunkType::PLTE).unwrap().data());
+		assert_eq!(rendered(&embedded), rendered(&png));
+		assert_eq!(extract(&embedded, &target).unwrap().unwrap(), b"reordered");
+	}
+
+	#[test]
+	fn test_background_and_histogram_follow_their_entries() {
+		let png = indexed_png(64);
+		let mut chunks = png.chunks().to_vec();
+		let hist = (0..64u16).flat_map(|entry| (entry * 100).to_be_bytes()).collect();
+		chunks.insert(3, Chunk::new(ChunkType::BKGD, vec![5]));
+		chunks.insert(3, Chunk::new(ChunkType::HIST, hist));
+		let png = Png::from_chunks(chunks);
+		let entry = |png: &Png, index: usize| Order::from_png(png).unwrap().entries[index];
+		let frequency = |png: &Png, entry: [u8; 4]| {
+			let index = Order::from_png(png).unwrap().entries.iter().position(|&candidate| candidate == entry).unwrap();
+			png.chunk_by_type(&ChunkType::HIST).unwrap().data()[2 * index..2 * index + 2].to_vec()
+		};
+
+		let mut embedded = png.clone();
+		embed(&mut embedded, &Target::PaletteOrder { key: None }, b"bg", Compression::best()).unwrap();
+		let background = |png: &Png| png.chunk_by_type(&ChunkType::BKGD).unwrap().data()[0] as usize;
+		assert_ne!(background(&embedded), 5);
+		assert_eq!(entry(&embedded, background(&embedded)), entry(&png, 5));
+		for index in 0..64 {
+			let original = entry(&png, index);
+			assert_eq!(frequency(&embedded, original), frequency(&png, original));
+		}
+	}
+
+	#[test]
+	fn test_rejects_duplicate_entries() {
+		let mut png = indexed_png(4);
+		png = Png::from_chunks(
+			png.chunks()
+				.iter()
+				.map(|chunk| match *chunk.chunk_type() {
+					ChunkType::PLTE => Chunk::new(ChunkType::PLTE, vec![0; 12]),
+					ChunkType::TRNS => Chunk::new(ChunkType::TRNS, Vec::new()),
+					_ => chunk.clone(),
+				})
+				.collect(),
+		);
+		assert!(matches!(Order::from_png(&png), Err(EmbedError::Unsupported { .. })));
+	}
+
+	#[test]
+	fn test_rejects_indices_past_the_palette() {
+		let png = indexed_png(8);
+		let chunks = png
+			.chunks()
+			.iter()
+			.map(|chunk| match *chunk.chunk_type() {
+				ChunkType::PLTE => Chunk::new(ChunkType::PLTE, chunk.data()[..12].to_vec()),
+				_ => chunk.clone(),
+			})
+			.collect();
+		let png = Png::from_chunks(chunks);
+		assert!(matches!(Order::from_png(&png), Err(EmbedError::Unsupported { .. })));
+		assert!(embed(&mut png.clone(), &Target::PaletteOrder { key: None }, b"x", Compression::best()).is_err());
+	}
+}
@@ -0,0 +1,201 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::embed::{Carrier, EmbedError, Method};
+use crate::image::{ImageHeader, Raster};
+use crate::png::Png;
+
+/// Every bit of the PLTE entries (and their tRNS alpha, if present) that no
+/// pixel refers to, so changing them leaves the rendered image untouched.
+pub(crate) struct Palette {
+	// PLTE data followed by tRNS data
+	bytes: Vec<u8>,
+	plte_len: usize,
+	positions: Vec<usize>,
+}
+
+// Offset and width of every scanline of every sub-image.
+fn rows(header: &ImageHeader) -> Vec<(usize, usize)> {
+	let mut rows = Vec::new();
+	let mut offset = 0;
+	for (width, height) in header.sub_images() {
+		if width == 0 {
+			continue;
+		}
+		let row_len = header.row_len(width);
+		for _ in 0..height {
+			rows.push((offset, width as usize));
+			offset += row_len;
+		}
+	}
+	rows
+}
+
+/// Palette index of every pixel, unpacked from sub-byte bit depths.
+pub(crate) fn indices(raster: &Raster) -> Vec<u8> {
+	let header = raster.header();
+	let depth = header.bit_depth as usize;
+	let mask = ((1u16 << depth) - 1) as u8;
+	rows(header)
+		.into_iter()
+		.flat_map(|(offset, width)| {
+			(0..width).map(move |x| {
+				let bit = x * depth;
+				(raster.data()[offset + bit / 8] >> (8 - depth - bit % 8)) & mask
+			})
+		})
+		.collect()
+}
+
+/// Writes back indices in the order `indices` returned them.
+pub(crate) fn set_indices(raster: &mut Raster, indices: &[u8]) {
+	let header = *raster.header();
+	let depth = header.bit_depth as usize;
+	let mask = ((1u16 << depth) - 1) as u8;
+	let mut indices = indices.iter();
+	for (offset, width) in rows(&header) {
+		for x in 0..width {
+			let bit = x * depth;
+			let shift = 8 - depth - bit % 8;
+			let byte = &mut raster.data_mut()[offset + bit / 8];
+			let index = indices.next().expect("one index per pixel");
+			*byte = (*byte & !(mask << shift)) | ((index & mask) << shift);
+		}
+	}
+}
+
+impl Palette {
+	pub(crate) fn from_png(png: &Png) -> Result<Palette, EmbedError> {
+		let unsupported = |reason| EmbedError::Unsupported { method: Method::Palette, reason };
+		let header = ImageHeader::from_png(png)?;
+		if header.color_type != crate::image::ColorType::Indexed {
+			return Err(unsupported("only indexed-colour images have a palette"));
+		}
+		let plte = png.chunk_by_type(&ChunkType::PLTE).ok_or(unsupported("image has no PLTE chunk"))?;
+		let trns = png.chunk_by_type(&ChunkType::TRNS).map(Chunk::data).unwrap_or_default();
+
+		let mut used = [false; 256];
+		indices(&Raster::from_png(png)?).into_iter().for_each(|index| used[index as usize] = true);
+		// the background colour is shown by viewers too
+		if let Some(&[index]) = png.chunk_by_type(&ChunkType::BKGD).map(Chunk::data) {
+			used[index as usize] = true;
+		}
+
+		let plte_len = plte.data().len();
+		let positions = (0..plte_len / 3)
+			.filter(|&entry| !used[entry])
+			.flat_map(|entry| {
+				let alpha = (entry < trns.len()).then_some(plte_len + entry);
+				(entry * 3..entry * 3 + 3).chain(alpha)
+			})
+			.collect();
+		let bytes = plte.data().iter().chain(trns).copied().collect();
+		Ok(Palette { bytes, plte_len, positions })
+	}
+
+	/// Replaces the PLTE and tRNS data of `png` with the carrier's.
+	pub(crate) fn write_to(self, png: &mut Png) {
+		let (plte, trns) = self.bytes.split_at(self.plte_len);
+		let chunks = png
+			.chunks()
+			.iter()
+			.map(|chunk| match *chunk.chunk_type() {
+				ChunkType::PLTE => Chunk::new(ChunkType::PLTE, plte.to_vec()),
+				ChunkType::TRNS => Chunk::new(ChunkType::TRNS, trns.to_vec()),
+				_ => chunk.clone(),
+			})
+			.collect();
+		*png = Png::from_chunks(chunks);
+	}
+}
+
+impl Carrier for Palette {
+	fn bit_len(&self) -> usize {
+		self.positions.len() * 8
+	}
+
+	fn bit(&self, index: usize) -> bool {
+		self.bytes[self.positions[index / 8]] >> (7 - index % 8) & 1 == 1
+	}
+
+	fn set_bit(&mut self, index: usize, value: bool) {
+		let byte = &mut self.bytes[self.positions[index / 8]];
+		let mask = 1 << (7 - index % 8);
+		*byte = (*byte & !mask) | if value { mask } else { 0 };
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+	use crate::embed::{capacity, embed, extract, Target};
+	use crate::image::ColorType;
+	use flate2::Compression;
+
+	fn indexed_png(bit_depth: u8, indices: Vec<u8>, entries: usize, alphas: usize) -> Png {
+		let header = ImageHeader { width: 4, height: 4, bit_depth, color_type: ColorType::Indexed, interlaced: false };
+		Png::from_chunks(vec![
+			Chunk::new(ChunkType::IHDR, header.as_bytes().to_vec()),
+			Chunk::new(ChunkType::PLTE, (0..entries * 3).map(|i| i as u8).collect()),
+			Chunk::new(ChunkType::TRNS, vec![255; alphas]),
+			Chunk::new(ChunkType::IDAT, Raster::new(header, indices).encode(Compression::best())),
+			Chunk::new(ChunkType::IEND, Vec::new()),
+		])
+	}
+
+	#[test]
+	fn test_unpacks_sub_byte_indices() {
+		// rows of four 2-bit indices: 0, 1, 2, 3
+		let png = indexed_png(2, vec![0b00_01_10_11; 4], 4, 0);
+		let raster = Raster::from_png(&png).unwrap();
+		assert_eq!(indices(&raster), [0, 1, 2, 3].repeat(4));
+	}
+
+	#[test]
+	fn test_set_indices_roundtrip() {
+		let png = indexed_png(4, vec![0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0], 16, 0);
+		let mut raster = Raster::from_png(&png).unwrap();
+		let reversed: Vec<u8> = indices(&raster).into_iter().rev().collect();
+		set_indices(&mut raster, &reversed);
+		assert_eq!(indices(&raster), reversed);
+	}
+
+	#[test]
+	fn test_only_unused_entries_change() {
+		// pixels use entries 0 to 3 of 16; tRNS covers the first 8
+		let png = indexed_png(8, (0..16).map(|i| i % 4).collect(), 16, 8);
+		let target = Target::Palette { key: None };
+		assert_eq!(capacity(&png, &target).unwrap(), 12 * 3 + 4 - 8);
+
+		let mut embedded = Png::try_from(png.as_bytes().as_slice()).unwrap();
+		embed(&mut embedded, &target, b"unseen", Compression::best()).unwrap();
+		assert_eq!(extract(&embedded, &target).unwrap().unwrap(), b"unseen");
+
+		let plte = |png: &Png| png.chunk_by_type(&ChunkType::PLTE).unwrap().data().to_vec();
+		let trns = |png: &Png| png.chunk_by_type(&ChunkType::TRNS).unwrap().data().to_vec();
+		assert_eq!(plte(&embedded)[..12], plte(&png)[..12]);
+		assert_eq!(trns(&embedded)[..4], trns(&png)[..4]);
+		assert_eq!(Raster::from_png(&embedded).unwrap(), Raster::from_png(&png).unwrap());
+	}
+
+	#[test]
+	fn test_background_entry_is_used() {
+		let png = indexed_png(8, (0..16).map(|i| i % 4).collect(), 16, 0);
+		let mut chunks = png.chunks().to_vec();
+		chunks.insert(2, Chunk::new(ChunkType::BKGD, vec![9]));
+		let png = Png::from_chunks(chunks);
+		assert_eq!(capacity(&png, &Target::Palette { key: None }).unwrap(), 11 * 3 - 8);
+
+		let mut embedded = png.clone();
+		embed(&mut embedded, &Target::Palette { key: None }, b"bg", Compression::best()).unwrap();
+		let plte = |png: &Png| png.chunk_by_type(&ChunkType::PLTE).unwrap().data()[27..30].to_vec();
+		assert_eq!(plte(&embedded), plte(&png));
+	}
+
+	#[test]
+	fn test_requires_indexed_image() {
+		let header = crate::embed::tests::rgba_header(2, 2);
+		let png = crate::embed::tests::testing_png(header, vec![0; header.data_len().unwrap()]);
+		assert!(matches!(capacity(&png, &Target::Palette { key: None }), Err(EmbedError::Unsupported { .. })));
+	}
+}
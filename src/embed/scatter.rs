@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use rand_chacha::ChaCha20Rng;
+use rand_core::{RngCore, SeedableRng};
+
+/// Key that selects the order in which carrier bits are used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScatterKey([u8; 32]);
+
+impl ScatterKey {
+	pub fn from_passphrase(passphrase: &str) -> ScatterKey {
+		ScatterKey(crate::crypto::derive_key(passphrase))
+	}
+}
+
+/// A keyed pseudo-random permutation of `0..len`, produced lazily by a
+/// Fisher–Yates shuffle so that only the positions actually used are drawn.
+pub(crate) struct Scatter {
+	rng: ChaCha20Rng,
+	len: usize,
+	next: usize,
+	swapped: HashMap<usize, usize>,
+}
+
+impl Scatter {
+	pub(crate) fn new(key: &ScatterKey, len: usize) -> Scatter {
+		Scatter {
+			rng: ChaCha20Rng::from_seed(key.0),
+			len,
+			next: 0,
+			swapped: HashMap::new(),
+		}
+	}
+
+	// Uniform value in `0..bound` without modulo bias.
+	fn below(&mut self, bound: usize) -> usize {
+		let bound = bound as u64;
+		let zone = u64::MAX - u64::MAX % bound;
+		loop {
+			let value = self.rng.next_u64();
+			if value < zone {
+				return (value % bound) as usize;
+			}
+		}
+	}
+}
+
+impl Iterator for Scatter {
+	type Item = usize;
+
+	fn next(&mut self) -> Option<usize> {
+		if self.next >= self.len {
+			return None;
+		}
+		let i = self.next;
+		let j = i + self.below(self.len - i);
+		let at_i = self.swapped.remove(&i).unwrap_or(i);
+		let at_j = if j == i { at_i } else { self.swapped.insert(j, at_i).unwrap_or(j) };
+		self.next += 1;
+		Some(at_j)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+
+	fn key(byte: u8) -> ScatterKey {
+		ScatterKey([byte; 32])
+	}
+
+	#[test]
+	fn test_is_a_permutation() {
+		let mut positions: Vec<usize> = Scatter::new(&key(1), 1000).collect();
+		assert_ne!(positions, (0..1000).collect::<Vec<_>>());
+		positions.sort_unstable();
+		assert_eq!(positions, (0..1000).collect::<Vec<_>>());
+	}
+
+	#[test]
+	fn test_same_key_same_order() {
+		let first: Vec<usize> = Scatter::new(&key(7), 500).take(50).collect();
+		let second: Vec<usize> = Scatter::new(&key(7), 500).take(50).collect();
+		let other: Vec<usize> = Scatter::new(&key(8), 500).take(50).collect();
+		assert_eq!(first, second);
+		assert_ne!(first, other);
+	}
+
+	#[test]
+	fn test_positions_spread_over_whole_range() {
+		let len = 10_000;
+		let upper_half = Scatter::new(&key(3), len).take(1000).filter(|&p| p >= len / 2).count();
+		assert!((400..600).contains(&upper_half));
+	}
+}
@@ -0,0 +1,117 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::image;
+use crate::png::Png;
+use super::{EmbedError, Method};
+
+// Length of the zlib stream inside the concatenated IDAT data. Everything
+// after it is ignored by decoders, so it is the payload.
+fn stream_len(png: &Png) -> Result<usize, EmbedError> {
+	if png.chunk_by_type(&ChunkType::IDAT).is_none() {
+		return Err(EmbedError::Unsupported { method: Method::Trailing, reason: "the image has no IDAT chunk" });
+	}
+	Ok(image::zlib_stream_len(&png.image_data())?)
+}
+
+// Bytes of the zlib stream that end up in the IDAT chunk holding its end.
+fn stream_tail_len(png: &Png, stream_len: usize) -> usize {
+	let mut offset = 0;
+	for chunk in png.chunks().iter().filter(|chunk| *chunk.chunk_type() == ChunkType::IDAT) {
+		let end = offset + chunk.data().len();
+		if end >= stream_len {
+			return stream_len - offset;
+		}
+		offset = end;
+	}
+	0
+}
+
+pub(crate) fn capacity(png: &Png) -> Result<usize, EmbedError> {
+	let stream_len = stream_len(png)?;
+	Ok(Chunk::MAX_LENGTH as usize - stream_tail_len(png, stream_len))
+}
+
+/// Replaces whatever follows the zlib stream with `payload`, appended to the
+/// IDAT chunk the stream ends in. IDAT chunks that only held trailing bytes
+/// are dropped.
+pub(crate) fn write(png: &mut Png, payload: &[u8]) -> Result<(), EmbedError> {
+	let stream_len = stream_len(png)?;
+	let available = Chunk::MAX_LENGTH as usize - stream_tail_len(png, stream_len);
+	if payload.len() > available {
+		return Err(EmbedError::Capacity { method: Method::Trailing, needed: payload.len(), available });
+	}
+
+	let mut offset = 0;
+	let mut chunks = Vec::with_capacity(png.chunks().len());
+	for chunk in png.chunks() {
+		if *chunk.chunk_type() != ChunkType::IDAT {
+			chunks.push(chunk.clone());
+			continue;
+		}
+		let start = offset;
+		offset += chunk.data().len();
+		if start >= stream_len {
+			continue;
+		}
+		if offset < stream_len {
+			chunks.push(chunk.clone());
+		} else {
+			let data = chunk.data()[..stream_len - start].iter().chain(payload).copied().collect();
+			chunks.push(Chunk::new(ChunkType::IDAT, data));
+		}
+	}
+	*png = Png::from_chunks(chunks);
+	Ok(())
+}
+
+pub(crate) fn read(png: &Png) -> Result<Option<Vec<u8>>, EmbedError> {
+	let stream_len = stream_len(png)?;
+	let trailing = png.image_data().split_off(stream_len);
+	Ok((!trailing.is_empty()).then_some(trailing))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+	use crate::embed::tests::{rgba_header, testing_png};
+	use crate::image::Raster;
+
+	#[test]
+	fn test_roundtrip_keeps_image() {
+		let header = rgba_header(4, 4);
+		let mut png = testing_png(header, (0..header.data_len().unwrap() as u8).collect());
+		let pixels = Raster::from_png(&png).unwrap();
+
+		write(&mut png, b"after the stream").unwrap();
+		assert_eq!(read(&png).unwrap().unwrap(), b"after the stream");
+		assert_eq!(Raster::from_png(&png).unwrap(), pixels);
+
+		// a second message replaces the first
+		write(&mut png, b"second").unwrap();
+		assert_eq!(read(&png).unwrap().unwrap(), b"second");
+	}
+
+	#[test]
+	fn test_no_trailing_bytes() {
+		let header = rgba_header(4, 4);
+		let png = testing_png(header, vec![0; header.data_len().unwrap()]);
+		assert_eq!(read(&png).unwrap(), None);
+	}
+
+	#[test]
+	fn test_trailer_only_idats_are_dropped() {
+		let header = rgba_header(4, 4);
+		let mut png = testing_png(header, vec![0; header.data_len().unwrap()]);
+		let stream = png.image_data();
+		let mut chunks = png.chunks().to_vec();
+		let idat = chunks.iter().position(|chunk| *chunk.chunk_type() == ChunkType::IDAT).unwrap();
+		chunks.insert(idat + 1, Chunk::new(ChunkType::IDAT, b"old payload".to_vec()));
+		png = Png::from_chunks(chunks);
+
+		write(&mut png, b"new").unwrap();
+		let idats: Vec<&Chunk> = png.chunks().iter().filter(|chunk| *chunk.chunk_type() == ChunkType::IDAT).collect();
+		assert_eq!(idats.len(), 1);
+		assert_eq!(idats[0].data(), [stream.as_slice(), b"new"].concat());
+	}
+}
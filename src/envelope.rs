@@ -0,0 +1,402 @@
+use std::io::Read;
+use crate::fec::{self, FecError};
+
+/// Length of a content key wrapped for one recipient (key + AEAD tag).
+pub const WRAPPED_KEY_LEN: usize = 32 + 16;
+
+// A protected envelope starts with one codeword holding the magic, version,
+// flags, parity and body length, so that damage to any of them can be
+// corrected too. Its check bytes correct four damaged bytes.
+const HEADER_LEN: usize = 5 + 1 + 1 + 1 + 4;
+const HEADER_PARITY: usize = 8;
+const HEADER_CODEWORD_LEN: usize = HEADER_LEN + HEADER_PARITY;
+
+/// Container written into a chunk instead of the raw message whenever the
+/// message needs extra framing, e.g. because it is encrypted. Encryption and
+/// error correction are separate layers: a protected envelope wraps the
+/// bytes of an encrypted one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+	encryption: Option<Encryption>,
+	protection: Option<Protection>,
+	header_corrections: usize,
+	body: Vec<u8>,
+}
+
+/// Key material needed by a recipient to decrypt the envelope body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encryption {
+	pub ephemeral: [u8; 32],
+	pub slots: Vec<[u8; WRAPPED_KEY_LEN]>,
+	pub nonce: [u8; 12],
+}
+
+/// Reed–Solomon parameters of a protected envelope body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Protection {
+	pub parity: u8,
+	pub length: u32,
+}
+
+impl Envelope {
+	pub const MAGIC: [u8; 5] = *b"pngme";
+	pub const VERSION: u8 = 1;
+
+	const FLAG_ENCRYPTED: u8 = 0b0000_0001;
+	const FLAG_PROTECTED: u8 = 0b0000_0010;
+
+	/// Range of check bytes per codeword a protected envelope may use.
+	pub const MIN_PARITY: u8 = 2;
+	pub const MAX_PARITY: u8 = 254;
+
+	pub fn new(body: Vec<u8>) -> Envelope {
+		Envelope { encryption: None, protection: None, header_corrections: 0, body }
+	}
+
+	pub fn encrypted(encryption: Encryption, body: Vec<u8>) -> Envelope {
+		Envelope { encryption: Some(encryption), protection: None, header_corrections: 0, body }
+	}
+
+	/// Wraps `payload` in Reed–Solomon codewords with `parity` check bytes each.
+	pub fn protected(payload: &[u8], parity: u8) -> Envelope {
+		let protection = Protection { parity, length: payload.len() as u32 };
+		let body = fec::encode(payload, parity as usize);
+		Envelope { encryption: None, protection: Some(protection), header_corrections: 0, body }
+	}
+
+	/// Returns true if `data` starts with the envelope magic, i.e. it was not
+	/// stored as a raw message. The magic of a protected envelope is found
+	/// even if it is damaged.
+	pub fn is_envelope(data: &[u8]) -> bool {
+		data.starts_with(&Self::MAGIC) || Self::protected_header(data).is_some()
+	}
+
+	// Corrects the header codeword at the start of `data`, returning the header
+	// and the number of corrected bytes if it is the header of a protected
+	// envelope.
+	fn protected_header(data: &[u8]) -> Option<([u8; HEADER_LEN], usize)> {
+		let mut codeword: [u8; HEADER_CODEWORD_LEN] = data.get(..HEADER_CODEWORD_LEN)?.try_into().ok()?;
+		let corrections = fec::decode_block(&mut codeword, HEADER_PARITY).ok()?;
+		let header: [u8; HEADER_LEN] = codeword[..HEADER_LEN].try_into().ok()?;
+		let prefix = [&Self::MAGIC[..], &[Self::VERSION, Self::FLAG_PROTECTED]].concat();
+		header.starts_with(&prefix).then_some((header, corrections))
+	}
+
+	/// Bytes to store for a plain message: the message itself, unless it starts
+	/// with the magic and would be taken for an envelope, in which case it is
+	/// wrapped in one.
+	pub fn frame_plain(message: &[u8]) -> Vec<u8> {
+		match Self::is_envelope(message) {
+			true => Envelope::new(message.to_vec()).as_bytes(),
+			false => message.to_vec(),
+		}
+	}
+
+	/// Parses the outermost layer of a stored payload, or returns None for a
+	/// raw message. Older versions stored every plain message raw, so one that
+	/// starts with the magic but doesn't parse is taken as a raw message too.
+	pub fn parse_stored(data: &[u8]) -> Option<Envelope> {
+		Self::is_envelope(data).then(|| Envelope::try_from(data).ok()).flatten()
+	}
+
+	pub fn encryption(&self) -> Option<&Encryption> {
+		self.encryption.as_ref()
+	}
+
+	pub fn protection(&self) -> Option<&Protection> {
+		self.protection.as_ref()
+	}
+
+	pub fn body(&self) -> &[u8] {
+		&self.body
+	}
+
+	/// Corrects the body of a protected envelope and returns the wrapped
+	/// payload, along with the number of bytes that had to be corrected.
+	pub fn recover(&self) -> Result<(Vec<u8>, usize), FecError> {
+		let Some(protection) = self.protection else {
+			return Ok((self.body.clone(), 0));
+		};
+		let (payload, corrected) = fec::decode(&self.body, protection.length as usize, protection.parity as usize)?;
+		Ok((payload, corrected + self.header_corrections))
+	}
+
+	fn flags(&self) -> u8 {
+		let mut flags = 0;
+		if self.encryption.is_some() {
+			flags |= Self::FLAG_ENCRYPTED;
+		}
+		if self.protection.is_some() {
+			flags |= Self::FLAG_PROTECTED;
+		}
+		flags
+	}
+
+	pub fn as_bytes(&self) -> Vec<u8> {
+		if let Some(protection) = &self.protection {
+			let header: Vec<u8> = Self::MAGIC
+				.iter()
+				.copied()
+				.chain([Self::VERSION, self.flags(), protection.parity])
+				.chain(protection.length.to_be_bytes())
+				.collect();
+			let mut bytes = fec::encode_block(&header, HEADER_PARITY);
+			bytes.extend_from_slice(&self.body);
+			return bytes;
+		}
+
+		let mut bytes = Self::MAGIC.to_vec();
+		bytes.push(Self::VERSION);
+		bytes.push(self.flags());
+		if let Some(encryption) = &self.encryption {
+			bytes.extend_from_slice(&encryption.ephemeral);
+			bytes.push(encryption.slots.len() as u8);
+			encryption.slots.iter().for_each(|slot| bytes.extend_from_slice(slot));
+			bytes.extend_from_slice(&encryption.nonce);
+		}
+		bytes.extend_from_slice(&self.body);
+		bytes
+	}
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReadError {
+	#[error("failed to read payload envelope: {0}")]
+	Io(#[from] std::io::Error),
+}
+
+// Magic, version, flags, key material and body as read from the payload of
+// an envelope without protection.
+type RawEnvelope = ([u8; 5], u8, u8, Option<Encryption>, Vec<u8>);
+
+impl Envelope {
+	fn read(value: &[u8]) -> Result<RawEnvelope, ReadError> {
+		let mut reader = std::io::BufReader::new(value);
+
+		let mut magic = [0u8; 5];
+		reader.read_exact(&mut magic)?;
+
+		let mut version_and_flags = [0u8; 2];
+		reader.read_exact(&mut version_and_flags)?;
+		let [version, flags] = version_and_flags;
+
+		let encryption = if flags & Self::FLAG_ENCRYPTED != 0 {
+			let mut ephemeral = [0u8; 32];
+			reader.read_exact(&mut ephemeral)?;
+
+			let mut count = [0u8; 1];
+			reader.read_exact(&mut count)?;
+			let mut slots = Vec::with_capacity(count[0] as usize);
+			for _ in 0..count[0] {
+				let mut slot = [0u8; WRAPPED_KEY_LEN];
+				reader.read_exact(&mut slot)?;
+				slots.push(slot);
+			}
+
+			let mut nonce = [0u8; 12];
+			reader.read_exact(&mut nonce)?;
+			Some(Encryption { ephemeral, slots, nonce })
+		} else {
+			None
+		};
+
+		let mut body = Vec::new();
+		reader.read_to_end(&mut body)?;
+
+		Ok((magic, version, flags, encryption, body))
+	}
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ValidationError {
+	#[error("payload is not a pngme envelope")]
+	InvalidMagic,
+
+	#[error("unsupported envelope version {0}")]
+	UnsupportedVersion(u8),
+
+	#[error("unknown envelope flags {0:#010b}")]
+	UnknownFlags(u8),
+
+	#[error("encrypted envelope has no recipients")]
+	NoRecipients,
+
+	#[error("envelope cannot be both encrypted and protected")]
+	NestedLayers,
+
+	#[error("protection header is damaged beyond repair")]
+	ProtectionHeader(#[from] FecError),
+
+	#[error("protection parity must be between {min} and {max} (got {0})", min = Envelope::MIN_PARITY, max = Envelope::MAX_PARITY)]
+	Parity(u8),
+}
+
+impl Envelope {
+	fn validate(
+		magic: [u8; 5],
+		version: u8,
+		flags: u8,
+		encryption: Option<Encryption>,
+		body: Vec<u8>,
+	) -> Result<Envelope, ValidationError> {
+		if magic != Self::MAGIC {
+			return Err(ValidationError::InvalidMagic);
+		}
+
+		if version != Self::VERSION {
+			return Err(ValidationError::UnsupportedVersion(version));
+		}
+
+		if flags & !(Self::FLAG_ENCRYPTED | Self::FLAG_PROTECTED) != 0 {
+			return Err(ValidationError::UnknownFlags(flags));
+		}
+
+		// a protected envelope whose header codeword could be corrected was
+		// handled before
+		if flags & Self::FLAG_PROTECTED != 0 {
+			return Err(match encryption {
+				Some(_) => ValidationError::NestedLayers,
+				None => ValidationError::ProtectionHeader(FecError::Uncorrectable),
+			});
+		}
+
+		if encryption.as_ref().is_some_and(|encryption| encryption.slots.is_empty()) {
+			return Err(ValidationError::NoRecipients);
+		}
+
+		Ok(Envelope { encryption, protection: None, header_corrections: 0, body })
+	}
+
+	fn validate_protected(header: [u8; HEADER_LEN], header_corrections: usize, body: Vec<u8>) -> Result<Envelope, ValidationError> {
+		let parity = header[7];
+		if !(Self::MIN_PARITY..=Self::MAX_PARITY).contains(&parity) {
+			return Err(ValidationError::Parity(parity));
+		}
+		let length = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+		Ok(Envelope { encryption: None, protection: Some(Protection { parity, length }), header_corrections, body })
+	}
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EnvelopeError {
+	#[error(transparent)]
+	Validation(#[from] ValidationError),
+
+	#[error(transparent)]
+	Read(#[from] ReadError),
+}
+
+impl TryFrom<&[u8]> for Envelope {
+	type Error = EnvelopeError;
+
+	fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+		if let Some((header, corrections)) = Envelope::protected_header(value) {
+			return Ok(Envelope::validate_protected(header, corrections, value[HEADER_CODEWORD_LEN..].to_vec())?);
+		}
+		let (magic, version, flags, encryption, body) = Envelope::read(value)?;
+		let envelope = Envelope::validate(magic, version, flags, encryption, body)?;
+		Ok(envelope)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+
+	fn testing_encryption() -> Encryption {
+		Encryption {
+			ephemeral: [7; 32],
+			slots: vec![[1; WRAPPED_KEY_LEN], [2; WRAPPED_KEY_LEN]],
+			nonce: [9; 12],
+		}
+	}
+
+	#[test]
+	fn test_plain_envelope_roundtrip() {
+		let envelope = Envelope::new(b"hello".to_vec());
+		let bytes = envelope.as_bytes();
+		assert!(Envelope::is_envelope(&bytes));
+		assert_eq!(Envelope::try_from(bytes.as_slice()).unwrap(), envelope);
+	}
+
+	#[test]
+	fn test_encrypted_envelope_roundtrip() {
+		let envelope = Envelope::encrypted(testing_encryption(), b"ciphertext".to_vec());
+		let bytes = envelope.as_bytes();
+		let parsed = Envelope::try_from(bytes.as_slice()).unwrap();
+		assert_eq!(parsed.encryption().unwrap().slots.len(), 2);
+		assert_eq!(parsed.body(), b"ciphertext");
+	}
+
+	#[test]
+	fn test_raw_message_is_not_envelope() {
+		assert!(!Envelope::is_envelope(b"This is a secret message!"));
+		assert!(Envelope::try_from(&b"This is a secret message!"[..]).is_err());
+	}
+
+	#[test]
+	fn test_message_starting_with_magic() {
+		assert_eq!(Envelope::frame_plain(b"hello"), b"hello");
+		let stored = Envelope::frame_plain(b"pngme rocks");
+		assert_eq!(Envelope::parse_stored(&stored).unwrap().body(), b"pngme rocks");
+		// stored raw by an older version
+		assert!(Envelope::parse_stored(b"pngme rocks").is_none());
+		assert!(Envelope::parse_stored(b"hello").is_none());
+	}
+
+	#[test]
+	fn test_truncated_envelope() {
+		let bytes = Envelope::encrypted(testing_encryption(), Vec::new()).as_bytes();
+		assert!(Envelope::try_from(&bytes[..40]).is_err());
+	}
+
+	#[test]
+	fn test_protected_envelope_recovers_damage() {
+		let payload = b"This is a secret message!".repeat(20);
+		let mut bytes = Envelope::protected(&payload, 16).as_bytes();
+		// damage the protection header and the body
+		bytes[8] ^= 0xff;
+		bytes[40] ^= 0xff;
+		bytes[41] ^= 0x01;
+
+		let envelope = Envelope::try_from(bytes.as_slice()).unwrap();
+		assert_eq!(envelope.protection().unwrap().parity, 16);
+		assert_eq!(envelope.recover().unwrap(), (payload, 3));
+	}
+
+	#[test]
+	fn test_protected_envelope_recovers_damaged_magic() {
+		let payload = b"This is a secret message!".to_vec();
+		let mut bytes = Envelope::protected(&payload, 8).as_bytes();
+		// magic, version and flags
+		bytes[0] ^= 0xff;
+		bytes[4] ^= 0x20;
+		bytes[5] = 0;
+		bytes[6] = 0;
+		assert!(Envelope::is_envelope(&bytes));
+		let envelope = Envelope::parse_stored(&bytes).unwrap();
+		assert_eq!(envelope.recover().unwrap(), (payload, 4));
+
+		bytes[1] ^= 0xff;
+		assert!(!Envelope::is_envelope(&bytes));
+	}
+
+	#[test]
+	fn test_parity_range() {
+		let mut bytes = Envelope::protected(b"data", 2).as_bytes();
+		assert!(Envelope::try_from(bytes.as_slice()).is_ok());
+		// parity 1, with the header codeword made consistent again
+		let mut header = bytes[..HEADER_LEN].to_vec();
+		header[7] = 1;
+		bytes.splice(..HEADER_CODEWORD_LEN, fec::encode_block(&header, HEADER_PARITY));
+		assert!(matches!(Envelope::try_from(bytes.as_slice()), Err(EnvelopeError::Validation(ValidationError::Parity(1)))));
+	}
+
+	#[test]
+	fn test_unknown_flags() {
+		let mut bytes = Envelope::new(Vec::new()).as_bytes();
+		bytes[6] = 0b1000_0000;
+		assert!(Envelope::try_from(bytes.as_slice()).is_err());
+	}
+}
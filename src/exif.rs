@@ -0,0 +1,640 @@
+use std::collections::HashSet;
+
+/// Byte order of a TIFF structure, given by its first two bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+	Little,
+	Big,
+}
+
+impl ByteOrder {
+	fn u16(self, bytes: &[u8]) -> u16 {
+		let bytes = [bytes[0], bytes[1]];
+		match self {
+			ByteOrder::Little => u16::from_le_bytes(bytes),
+			ByteOrder::Big => u16::from_be_bytes(bytes),
+		}
+	}
+
+	fn u32(self, bytes: &[u8]) -> u32 {
+		let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+		match self {
+			ByteOrder::Little => u32::from_le_bytes(bytes),
+			ByteOrder::Big => u32::from_be_bytes(bytes),
+		}
+	}
+
+	fn u16_bytes(self, value: u16) -> [u8; 2] {
+		match self {
+			ByteOrder::Little => value.to_le_bytes(),
+			ByteOrder::Big => value.to_be_bytes(),
+		}
+	}
+
+	fn u32_bytes(self, value: u32) -> [u8; 4] {
+		match self {
+			ByteOrder::Little => value.to_le_bytes(),
+			ByteOrder::Big => value.to_be_bytes(),
+		}
+	}
+}
+
+/// The image file directories Exif data is organised in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Ifd {
+	Primary,
+	Exif,
+	Gps,
+	Interop,
+	Thumbnail,
+}
+
+impl std::fmt::Display for Ifd {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let name = match self {
+			Ifd::Primary => "IFD0",
+			Ifd::Exif => "Exif",
+			Ifd::Gps => "GPS",
+			Ifd::Interop => "Interop",
+			Ifd::Thumbnail => "IFD1",
+		};
+		f.pad(name)
+	}
+}
+
+// TIFF field types.
+const BYTE: u16 = 1;
+const ASCII: u16 = 2;
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const RATIONAL: u16 = 5;
+const SBYTE: u16 = 6;
+const UNDEFINED: u16 = 7;
+const SSHORT: u16 = 8;
+const SLONG: u16 = 9;
+const SRATIONAL: u16 = 10;
+const FLOAT: u16 = 11;
+const DOUBLE: u16 = 12;
+
+fn type_size(field_type: u16) -> Option<usize> {
+	match field_type {
+		BYTE | ASCII | SBYTE | UNDEFINED => Some(1),
+		SHORT | SSHORT => Some(2),
+		LONG | SLONG | FLOAT => Some(4),
+		RATIONAL | SRATIONAL | DOUBLE => Some(8),
+		_ => None,
+	}
+}
+
+// Tags holding offsets to other IFDs or data, which are rewritten on output.
+const EXIF_POINTER: u16 = 0x8769;
+const GPS_POINTER: u16 = 0x8825;
+const INTEROP_POINTER: u16 = 0xa005;
+const THUMBNAIL_OFFSET: u16 = 0x0201;
+const THUMBNAIL_LENGTH: u16 = 0x0202;
+const STRIP_OFFSETS: u16 = 0x0111;
+
+/// A tag pngme knows by name.
+pub struct TagInfo {
+	pub ifd: Ifd,
+	pub tag: u16,
+	pub name: &'static str,
+	field_type: u16,
+}
+
+const fn info(ifd: Ifd, tag: u16, name: &'static str, field_type: u16) -> TagInfo {
+	TagInfo { ifd, tag, name, field_type }
+}
+
+pub const TAGS: &[TagInfo] = &[
+	info(Ifd::Primary, 0x010e, "ImageDescription", ASCII),
+	info(Ifd::Primary, 0x010f, "Make", ASCII),
+	info(Ifd::Primary, 0x0110, "Model", ASCII),
+	info(Ifd::Primary, 0x0112, "Orientation", SHORT),
+	info(Ifd::Primary, 0x011a, "XResolution", RATIONAL),
+	info(Ifd::Primary, 0x011b, "YResolution", RATIONAL),
+	info(Ifd::Primary, 0x0128, "ResolutionUnit", SHORT),
+	info(Ifd::Primary, 0x0131, "Software", ASCII),
+	info(Ifd::Primary, 0x0132, "DateTime", ASCII),
+	info(Ifd::Primary, 0x013b, "Artist", ASCII),
+	info(Ifd::Primary, 0x8298, "Copyright", ASCII),
+	info(Ifd::Exif, 0x829a, "ExposureTime", RATIONAL),
+	info(Ifd::Exif, 0x829d, "FNumber", RATIONAL),
+	info(Ifd::Exif, 0x8827, "ISOSpeedRatings", SHORT),
+	info(Ifd::Exif, 0x9003, "DateTimeOriginal", ASCII),
+	info(Ifd::Exif, 0x9004, "DateTimeDigitized", ASCII),
+	info(Ifd::Exif, 0x920a, "FocalLength", RATIONAL),
+	info(Ifd::Exif, 0x927c, "MakerNote", UNDEFINED),
+	info(Ifd::Exif, 0xa001, "ColorSpace", SHORT),
+	info(Ifd::Exif, 0xa002, "PixelXDimension", LONG),
+	info(Ifd::Exif, 0xa003, "PixelYDimension", LONG),
+	info(Ifd::Exif, 0xa430, "CameraOwnerName", ASCII),
+	info(Ifd::Exif, 0xa431, "BodySerialNumber", ASCII),
+	info(Ifd::Exif, 0xa434, "LensModel", ASCII),
+	info(Ifd::Gps, 0x0000, "GPSVersionID", BYTE),
+	info(Ifd::Gps, 0x0001, "GPSLatitudeRef", ASCII),
+	info(Ifd::Gps, 0x0002, "GPSLatitude", RATIONAL),
+	info(Ifd::Gps, 0x0003, "GPSLongitudeRef", ASCII),
+	info(Ifd::Gps, 0x0004, "GPSLongitude", RATIONAL),
+	info(Ifd::Gps, 0x0005, "GPSAltitudeRef", BYTE),
+	info(Ifd::Gps, 0x0006, "GPSAltitude", RATIONAL),
+	info(Ifd::Gps, 0x0007, "GPSTimeStamp", RATIONAL),
+	info(Ifd::Gps, 0x001d, "GPSDateStamp", ASCII),
+];
+
+impl TagInfo {
+	/// Looks a tag up by name, ignoring case.
+	pub fn find(name: &str) -> Option<&'static TagInfo> {
+		TAGS.iter().find(|info| info.name.eq_ignore_ascii_case(name))
+	}
+
+	fn lookup(ifd: Ifd, tag: u16) -> Option<&'static TagInfo> {
+		// the thumbnail IFD uses the same tags as the primary one
+		let ifd = if ifd == Ifd::Thumbnail { Ifd::Primary } else { ifd };
+		TAGS.iter().find(|info| info.ifd == ifd && info.tag == tag)
+	}
+}
+
+/// A single IFD entry; `data` holds the raw value in the structure's byte order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+	pub tag: u16,
+	field_type: u16,
+	count: u32,
+	data: Vec<u8>,
+}
+
+impl Entry {
+	pub fn name(&self, ifd: Ifd) -> String {
+		match TagInfo::lookup(ifd, self.tag) {
+			Some(info) => info.name.to_string(),
+			None => format!("0x{:04x}", self.tag),
+		}
+	}
+
+	/// Human-readable value.
+	pub fn display(&self, order: ByteOrder) -> String {
+		let size = type_size(self.field_type).expect("entries have known types");
+		let values = self.data.chunks_exact(size);
+		let joined = |values: Vec<String>| values.join(", ");
+		let rational = |numerator: f64, denominator: f64| match denominator {
+			0.0 => String::from("undefined"),
+			_ => format!("{}", (numerator / denominator * 10000.0).round() / 10000.0),
+		};
+		match self.field_type {
+			ASCII => String::from_utf8_lossy(&self.data).trim_end_matches('\0').to_string(),
+			BYTE => joined(values.map(|value| value[0].to_string()).collect()),
+			SBYTE => joined(values.map(|value| (value[0] as i8).to_string()).collect()),
+			SHORT => joined(values.map(|value| order.u16(value).to_string()).collect()),
+			SSHORT => joined(values.map(|value| (order.u16(value) as i16).to_string()).collect()),
+			LONG => joined(values.map(|value| order.u32(value).to_string()).collect()),
+			SLONG => joined(values.map(|value| (order.u32(value) as i32).to_string()).collect()),
+			RATIONAL => joined(values.map(|value| rational(order.u32(value) as f64, order.u32(&value[4..]) as f64)).collect()),
+			SRATIONAL => joined(
+				values.map(|value| rational(order.u32(value) as i32 as f64, order.u32(&value[4..]) as i32 as f64)).collect(),
+			),
+			FLOAT => joined(values.map(|value| f32::from_bits(order.u32(value)).to_string()).collect()),
+			DOUBLE => joined(
+				values
+					.map(|value| {
+						let (high, low) = match order {
+							ByteOrder::Big => (order.u32(value), order.u32(&value[4..])),
+							ByteOrder::Little => (order.u32(&value[4..]), order.u32(value)),
+						};
+						f64::from_bits((high as u64) << 32 | low as u64).to_string()
+					})
+					.collect(),
+			),
+			_ if self.data.len() <= 16 => hex::encode(&self.data),
+			_ => format!("{} bytes", self.data.len()),
+		}
+	}
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ExifError {
+	#[error("Exif data does not start with a TIFF header")]
+	Header,
+
+	#[error("Exif data is truncated at offset {0}")]
+	Truncated(usize),
+
+	#[error("Exif IFDs form a loop at offset {0}")]
+	Loop(usize),
+
+	#[error("unknown Exif tag \"{0}\"")]
+	UnknownTag(String),
+
+	#[error("invalid value \"{value}\" for {tag}")]
+	Value {
+		tag: &'static str,
+		value: String,
+	},
+
+	#[error("{0} cannot be set from the command line")]
+	ReadOnly(&'static str),
+}
+
+/// Parsed contents of an eXIf chunk: a TIFF structure without image data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exif {
+	order: ByteOrder,
+	ifds: Vec<(Ifd, Vec<Entry>)>,
+	thumbnail: Option<Vec<u8>>,
+}
+
+impl Exif {
+	/// Empty big-endian Exif data, as the PNG specification recommends.
+	pub fn new() -> Exif {
+		Exif { order: ByteOrder::Big, ifds: Vec::new(), thumbnail: None }
+	}
+
+	pub fn order(&self) -> ByteOrder {
+		self.order
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.ifds.iter().all(|(_, entries)| entries.is_empty()) && self.thumbnail.is_none()
+	}
+
+	/// Every entry with the IFD it belongs to, in directory and tag order.
+	pub fn entries(&self) -> impl Iterator<Item = (Ifd, &Entry)> {
+		self.ifds.iter().flat_map(|(ifd, entries)| entries.iter().map(move |entry| (*ifd, entry)))
+	}
+
+	pub fn get(&self, info: &TagInfo) -> Option<&Entry> {
+		self.ifds
+			.iter()
+			.find(|(ifd, _)| *ifd == info.ifd)
+			.and_then(|(_, entries)| entries.iter().find(|entry| entry.tag == info.tag))
+	}
+
+	/// Sets a tag from its textual form: text for ASCII tags, and
+	/// comma-separated numbers (decimals or fractions for rationals) otherwise.
+	pub fn set(&mut self, info: &'static TagInfo, value: &str) -> Result<(), ExifError> {
+		let invalid = || ExifError::Value { tag: info.name, value: value.to_string() };
+		let numbers = || value.split(',').map(str::trim);
+		let data: Vec<u8> = match info.field_type {
+			ASCII => value.bytes().chain([0]).collect(),
+			BYTE => numbers().map(|number| number.parse::<u8>()).collect::<Result<_, _>>().map_err(|_| invalid())?,
+			SHORT => numbers()
+				.map(|number| number.parse::<u16>().map(|number| self.order.u16_bytes(number)))
+				.collect::<Result<Vec<_>, _>>()
+				.map_err(|_| invalid())?
+				.concat(),
+			LONG => numbers()
+				.map(|number| number.parse::<u32>().map(|number| self.order.u32_bytes(number)))
+				.collect::<Result<Vec<_>, _>>()
+				.map_err(|_| invalid())?
+				.concat(),
+			RATIONAL => numbers()
+				.map(|number| {
+					let (numerator, denominator) = parse_rational(number).ok_or_else(invalid)?;
+					Ok([self.order.u32_bytes(numerator), self.order.u32_bytes(denominator)].concat())
+				})
+				.collect::<Result<Vec<_>, ExifError>>()?
+				.concat(),
+			_ => return Err(ExifError::ReadOnly(info.name)),
+		};
+		let count = (data.len() / type_size(info.field_type).expect("known type")) as u32;
+		let entry = Entry { tag: info.tag, field_type: info.field_type, count, data };
+
+		let entries = match self.ifds.iter().position(|(ifd, _)| *ifd == info.ifd) {
+			Some(index) => &mut self.ifds[index].1,
+			None => {
+				self.ifds.push((info.ifd, Vec::new()));
+				self.ifds.sort_by_key(|(ifd, _)| *ifd);
+				let index = self.ifds.iter().position(|(ifd, _)| *ifd == info.ifd).expect("just added");
+				&mut self.ifds[index].1
+			}
+		};
+		entries.retain(|existing| existing.tag != info.tag);
+		entries.push(entry);
+		entries.sort_by_key(|entry| entry.tag);
+		Ok(())
+	}
+
+	/// Removes a tag, returning whether it was present.
+	pub fn delete(&mut self, info: &TagInfo) -> bool {
+		let Some((_, entries)) = self.ifds.iter_mut().find(|(ifd, _)| *ifd == info.ifd) else {
+			return false;
+		};
+		let before = entries.len();
+		entries.retain(|entry| entry.tag != info.tag);
+		before != entries.len()
+	}
+
+	/// Removes the GPS IFD, returning the number of tags it held.
+	pub fn strip_gps(&mut self) -> usize {
+		let removed = self.ifds.iter().filter(|(ifd, _)| *ifd == Ifd::Gps).map(|(_, entries)| entries.len()).sum();
+		self.ifds.retain(|(ifd, _)| *ifd != Ifd::Gps);
+		removed
+	}
+}
+
+fn parse_rational(text: &str) -> Option<(u32, u32)> {
+	if let Some((numerator, denominator)) = text.split_once('/') {
+		return Some((numerator.trim().parse().ok()?, denominator.trim().parse().ok()?));
+	}
+	let value: f64 = text.parse().ok()?;
+	if !(0.0..=u32::MAX as f64 / 10000.0).contains(&value) {
+		return None;
+	}
+	let (mut numerator, mut denominator) = ((value * 10000.0).round() as u32, 10000);
+	let gcd = {
+		let (mut a, mut b) = (numerator, denominator);
+		while b != 0 {
+			(a, b) = (b, a % b);
+		}
+		a
+	};
+	if gcd > 1 {
+		numerator /= gcd;
+		denominator /= gcd;
+	}
+	Some((numerator, denominator))
+}
+
+impl Exif {
+	fn read_ifd(
+		data: &[u8],
+		order: ByteOrder,
+		offset: usize,
+		visited: &mut HashSet<usize>,
+	) -> Result<(Vec<Entry>, usize), ExifError> {
+		if !visited.insert(offset) {
+			return Err(ExifError::Loop(offset));
+		}
+		let slice = |start: usize, len: usize| data.get(start..start + len).ok_or(ExifError::Truncated(start));
+		let count = order.u16(slice(offset, 2)?) as usize;
+		let mut entries = Vec::new();
+		for index in 0..count {
+			let raw = slice(offset + 2 + index * 12, 12)?;
+			let (tag, field_type, count) = (order.u16(raw), order.u16(&raw[2..]), order.u32(&raw[4..]));
+			// readers must skip fields of unknown types
+			let Some(size) = type_size(field_type) else {
+				continue;
+			};
+			let len = size * count as usize;
+			let value = match len {
+				0..=4 => raw[8..8 + len].to_vec(),
+				_ => slice(order.u32(&raw[8..]) as usize, len)?.to_vec(),
+			};
+			entries.push(Entry { tag, field_type, count, data: value });
+		}
+		let next = order.u32(slice(offset + 2 + count * 12, 4)?) as usize;
+		entries.sort_by_key(|entry| entry.tag);
+		Ok((entries, next))
+	}
+
+	// Removes a pointer tag from `entries`, returning the offset it held.
+	fn take_pointer(entries: &mut Vec<Entry>, tag: u16, order: ByteOrder) -> Option<usize> {
+		let index = entries.iter().position(|entry| entry.tag == tag)?;
+		let entry = entries.remove(index);
+		(entry.data.len() == 4).then(|| order.u32(&entry.data) as usize)
+	}
+
+	fn read(data: &[u8]) -> Result<Exif, ExifError> {
+		let order = match data.get(..4) {
+			Some(b"II*\0") => ByteOrder::Little,
+			Some(b"MM\0*") => ByteOrder::Big,
+			_ => return Err(ExifError::Header),
+		};
+		let first = order.u32(data.get(4..8).ok_or(ExifError::Truncated(4))?) as usize;
+		let mut visited = HashSet::new();
+		let mut ifds = Vec::new();
+		let mut thumbnail = None;
+
+		let (mut primary, next) = Self::read_ifd(data, order, first, &mut visited)?;
+		let exif = Self::take_pointer(&mut primary, EXIF_POINTER, order);
+		let gps = Self::take_pointer(&mut primary, GPS_POINTER, order);
+		ifds.push((Ifd::Primary, primary));
+		if let Some(offset) = exif {
+			let (mut entries, _) = Self::read_ifd(data, order, offset, &mut visited)?;
+			let interop = Self::take_pointer(&mut entries, INTEROP_POINTER, order);
+			ifds.push((Ifd::Exif, entries));
+			if let Some(offset) = interop {
+				ifds.push((Ifd::Interop, Self::read_ifd(data, order, offset, &mut visited)?.0));
+			}
+		}
+		if let Some(offset) = gps {
+			ifds.push((Ifd::Gps, Self::read_ifd(data, order, offset, &mut visited)?.0));
+		}
+		if next != 0 {
+			let (mut entries, _) = Self::read_ifd(data, order, next, &mut visited)?;
+			// strip-based thumbnails can't be relocated safely, so they are dropped
+			if entries.iter().all(|entry| entry.tag != STRIP_OFFSETS) {
+				let offset = Self::take_pointer(&mut entries, THUMBNAIL_OFFSET, order);
+				let length = Self::take_pointer(&mut entries, THUMBNAIL_LENGTH, order);
+				if let (Some(offset), Some(length)) = (offset, length) {
+					thumbnail = Some(data.get(offset..offset + length).ok_or(ExifError::Truncated(offset))?.to_vec());
+				}
+				ifds.push((Ifd::Thumbnail, entries));
+			}
+		}
+		ifds.sort_by_key(|(ifd, _)| *ifd);
+		Ok(Exif { order, ifds, thumbnail })
+	}
+}
+
+impl TryFrom<&[u8]> for Exif {
+	type Error = ExifError;
+
+	fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+		Self::read(value)
+	}
+}
+
+impl Exif {
+	/// Serializes to TIFF, regenerating every IFD and thumbnail offset.
+	pub fn as_bytes(&self) -> Vec<u8> {
+		let order = self.order;
+		let pointer = |tag: u16| Entry { tag, field_type: LONG, count: 1, data: vec![0; 4] };
+		let has = |wanted: Ifd| self.ifds.iter().any(|(ifd, _)| *ifd == wanted);
+
+		// every IFD with its pointer entries, which are filled in once offsets are known
+		let mut layout: Vec<(Ifd, Vec<Entry>)> = self.ifds.clone();
+		if !has(Ifd::Primary) && !self.ifds.is_empty() {
+			layout.insert(0, (Ifd::Primary, Vec::new()));
+		}
+		for (ifd, entries) in layout.iter_mut() {
+			match ifd {
+				Ifd::Primary => {
+					entries.extend(has(Ifd::Exif).then(|| pointer(EXIF_POINTER)));
+					entries.extend(has(Ifd::Gps).then(|| pointer(GPS_POINTER)));
+				}
+				Ifd::Exif => entries.extend(has(Ifd::Interop).then(|| pointer(INTEROP_POINTER))),
+				Ifd::Thumbnail => {
+					if let Some(thumbnail) = &self.thumbnail {
+						entries.push(pointer(THUMBNAIL_OFFSET));
+						entries.push(Entry {
+							tag: THUMBNAIL_LENGTH,
+							field_type: LONG,
+							count: 1,
+							data: order.u32_bytes(thumbnail.len() as u32).to_vec(),
+						});
+					}
+				}
+				_ => {}
+			}
+			entries.sort_by_key(|entry| entry.tag);
+		}
+
+		let ifd_len = |entries: &[Entry]| {
+			let overflow: usize = entries.iter().filter(|entry| entry.data.len() > 4).map(|entry| entry.data.len().next_multiple_of(2)).sum();
+			2 + entries.len() * 12 + 4 + overflow
+		};
+		let mut offsets = Vec::new();
+		let mut offset = 8;
+		for (ifd, entries) in &layout {
+			offsets.push((*ifd, offset));
+			offset += ifd_len(entries);
+		}
+		let offset_of = |wanted: Ifd| offsets.iter().find(|(ifd, _)| *ifd == wanted).map(|(_, offset)| *offset as u32);
+		let thumbnail_offset = offset as u32;
+
+		let mut bytes: Vec<u8> = match order {
+			ByteOrder::Little => b"II*\0".to_vec(),
+			ByteOrder::Big => b"MM\0*".to_vec(),
+		};
+		bytes.extend(order.u32_bytes(offset_of(Ifd::Primary).unwrap_or(0)));
+		for (ifd, entries) in &layout {
+			let start = bytes.len();
+			let mut overflow_offset = start + 2 + entries.len() * 12 + 4;
+			let mut overflow = Vec::new();
+			bytes.extend(order.u16_bytes(entries.len() as u16));
+			for entry in entries {
+				bytes.extend(order.u16_bytes(entry.tag));
+				bytes.extend(order.u16_bytes(entry.field_type));
+				bytes.extend(order.u32_bytes(entry.count));
+				let value = match entry.tag {
+					EXIF_POINTER if *ifd == Ifd::Primary => offset_of(Ifd::Exif),
+					GPS_POINTER if *ifd == Ifd::Primary => offset_of(Ifd::Gps),
+					INTEROP_POINTER if *ifd == Ifd::Exif => offset_of(Ifd::Interop),
+					THUMBNAIL_OFFSET if *ifd == Ifd::Thumbnail && self.thumbnail.is_some() => Some(thumbnail_offset),
+					_ => None,
+				};
+				match value {
+					Some(value) => bytes.extend(order.u32_bytes(value)),
+					None if entry.data.len() <= 4 => {
+						bytes.extend(&entry.data);
+						bytes.extend(vec![0; 4 - entry.data.len()]);
+					}
+					None => {
+						bytes.extend(order.u32_bytes(overflow_offset as u32));
+						overflow.extend(&entry.data);
+						if entry.data.len() % 2 == 1 {
+							overflow.push(0);
+						}
+						overflow_offset = start + 2 + entries.len() * 12 + 4 + overflow.len();
+					}
+				}
+			}
+			let next = match ifd {
+				Ifd::Primary => offset_of(Ifd::Thumbnail).unwrap_or(0),
+				_ => 0,
+			};
+			bytes.extend(order.u32_bytes(next));
+			bytes.extend(overflow);
+		}
+		if let Some(thumbnail) = &self.thumbnail {
+			bytes.extend(thumbnail);
+		}
+		bytes
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+
+	// Little-endian Exif with Make in IFD0, an Exif IFD and a GPS IFD.
+	fn testing_exif() -> Vec<u8> {
+		let mut exif = Exif { order: ByteOrder::Little, ..Exif::new() };
+		exif.set(TagInfo::find("Make").unwrap(), "Camera Co").unwrap();
+		exif.set(TagInfo::find("Orientation").unwrap(), "1").unwrap();
+		exif.set(TagInfo::find("FNumber").unwrap(), "2.8").unwrap();
+		exif.set(TagInfo::find("GPSLatitudeRef").unwrap(), "N").unwrap();
+		exif.set(TagInfo::find("GPSLatitude").unwrap(), "51, 30, 12.5").unwrap();
+		exif.as_bytes()
+	}
+
+	fn value(exif: &Exif, name: &str) -> Option<String> {
+		exif.get(TagInfo::find(name).unwrap()).map(|entry| entry.display(exif.order()))
+	}
+
+	#[test]
+	fn test_parse_tiff_structure() {
+		let bytes = testing_exif();
+		assert_eq!(&bytes[..4], b"II*\0");
+		let exif = Exif::try_from(bytes.as_slice()).unwrap();
+		assert_eq!(value(&exif, "Make").as_deref(), Some("Camera Co"));
+		assert_eq!(value(&exif, "Orientation").as_deref(), Some("1"));
+		assert_eq!(value(&exif, "FNumber").as_deref(), Some("2.8"));
+		assert_eq!(value(&exif, "GPSLatitude").as_deref(), Some("51, 30, 12.5"));
+		let ifds: Vec<Ifd> = exif.entries().map(|(ifd, _)| ifd).collect();
+		assert_eq!(ifds, [Ifd::Primary, Ifd::Primary, Ifd::Exif, Ifd::Gps, Ifd::Gps]);
+	}
+
+	#[test]
+	fn test_roundtrip_is_stable() {
+		let bytes = testing_exif();
+		let exif = Exif::try_from(bytes.as_slice()).unwrap();
+		assert_eq!(exif.as_bytes(), bytes);
+	}
+
+	#[test]
+	fn test_thumbnail_is_relocated() {
+		let mut exif = Exif::try_from(testing_exif().as_slice()).unwrap();
+		exif.ifds.push((Ifd::Thumbnail, Vec::new()));
+		exif.thumbnail = Some(b"\xff\xd8 jpeg \xff\xd9".to_vec());
+		exif.set(TagInfo::find("Model").unwrap(), "a much longer model name than before").unwrap();
+
+		let reparsed = Exif::try_from(exif.as_bytes().as_slice()).unwrap();
+		assert_eq!(reparsed.thumbnail, exif.thumbnail);
+	}
+
+	#[test]
+	fn test_strip_gps() {
+		let mut exif = Exif::try_from(testing_exif().as_slice()).unwrap();
+		assert_eq!(exif.strip_gps(), 2);
+		let reparsed = Exif::try_from(exif.as_bytes().as_slice()).unwrap();
+		assert_eq!(value(&reparsed, "GPSLatitude"), None);
+		assert_eq!(value(&reparsed, "Make").as_deref(), Some("Camera Co"));
+	}
+
+	#[test]
+	fn test_set_and_delete() {
+		let mut exif = Exif::new();
+		let artist = TagInfo::find("artist").unwrap();
+		exif.set(artist, "Jane Doe").unwrap();
+		exif.set(artist, "John Doe").unwrap();
+		assert_eq!(value(&exif, "Artist").as_deref(), Some("John Doe"));
+		assert!(exif.delete(artist));
+		assert!(!exif.delete(artist));
+		assert!(exif.is_empty());
+
+		assert!(matches!(exif.set(TagInfo::find("Orientation").unwrap(), "up"), Err(ExifError::Value { .. })));
+		assert!(matches!(exif.set(TagInfo::find("MakerNote").unwrap(), "x"), Err(ExifError::ReadOnly(_))));
+	}
+
+	#[test]
+	fn test_rejects_malformed_data() {
+		assert!(matches!(Exif::try_from(&b"JFIF"[..]), Err(ExifError::Header)));
+		let bytes = testing_exif();
+		assert!(matches!(Exif::try_from(&bytes[..20]), Err(ExifError::Truncated(_))));
+
+		// IFD0's next pointer leads back to itself
+		let mut looping = b"MM\0*\0\0\0\x08\0\0".to_vec();
+		looping.extend(8u32.to_be_bytes());
+		assert!(matches!(Exif::try_from(looping.as_slice()), Err(ExifError::Loop(8))));
+	}
+
+	#[test]
+	fn test_parse_rational() {
+		assert_eq!(parse_rational("1/250"), Some((1, 250)));
+		assert_eq!(parse_rational("2.8"), Some((14, 5)));
+		assert_eq!(parse_rational("-1"), None);
+	}
+}
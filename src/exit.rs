@@ -0,0 +1,153 @@
+use std::error::Error;
+use std::io::ErrorKind;
+use std::process::ExitCode;
+use crate::batch::BatchError;
+use crate::chunk::{self, ChunkError};
+use crate::chunk_type::{self, ChunkTypeError};
+use crate::commands::CommandError;
+use crate::config::ConfigError;
+use crate::crypto::{CryptoError, KeyError};
+use crate::embed::EmbedError;
+use crate::png::{self, PngError};
+use crate::script::ScriptError;
+
+/// Exit codes for scripts; 2 is left to clap for usage errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+	/// Any error without a more specific code, including batches in which
+	/// some files failed.
+	Failure = 1,
+	/// Not a PNG file, or one whose structure is broken.
+	Format = 3,
+	/// A chunk's CRC does not match its contents.
+	Crc = 4,
+	/// A file, chunk or message that doesn't exist.
+	NotFound = 5,
+	/// Reading or writing a file failed.
+	Io = 6,
+	/// Bad keys, or a message that cannot be decrypted.
+	Crypto = 7,
+}
+
+fn io_status(error: &std::io::Error) -> Status {
+	match error.kind() {
+		ErrorKind::NotFound => Status::NotFound,
+		_ => Status::Io,
+	}
+}
+
+// Status for one link of an error chain, if it determines one.
+fn link_status(error: &(dyn Error + 'static)) -> Option<Status> {
+	if let Some(error) = error.downcast_ref::<PngError>() {
+		return match error {
+			PngError::Validation(png::ValidationError::Chunk { .. }) => None,
+			_ => Some(Status::Format),
+		};
+	}
+	if let Some(error) = error.downcast_ref::<ChunkError>() {
+		return match error {
+			ChunkError::Validation(chunk::ValidationError::CRC32Mismatch { .. }) => Some(Status::Crc),
+			_ => Some(Status::Format),
+		};
+	}
+	if let Some(error) = error.downcast_ref::<CommandError>() {
+		return match error {
+			CommandError::Read { source, .. } | CommandError::Write { source, .. } => Some(io_status(source)),
+			CommandError::NoChunk(_) | CommandError::NoMessage => Some(Status::NotFound),
+			CommandError::Exists(_) | CommandError::ReservedChunkType { .. } => Some(Status::Failure),
+		};
+	}
+	if let Some(error) = error.downcast_ref::<BatchError>() {
+		return match error {
+			BatchError::NoMatch(_) | BatchError::Empty => Some(Status::NotFound),
+			BatchError::Io { source, .. } => Some(io_status(source)),
+			_ => Some(Status::Failure),
+		};
+	}
+	if let Some(ConfigError::Read { source, .. }) = error.downcast_ref::<ConfigError>() {
+		return Some(io_status(source));
+	}
+	if let Some(error) = error.downcast_ref::<ScriptError>() {
+		return match error {
+			ScriptError::Step { .. } => None,
+			ScriptError::NoText(_) => Some(Status::NotFound),
+			_ => Some(Status::Failure),
+		};
+	}
+	if error.is::<CryptoError>() || error.is::<KeyError>() {
+		return Some(Status::Crypto);
+	}
+	error.downcast_ref::<std::io::Error>().map(io_status)
+}
+
+/// Exit status for `error`, from the first link of its chain that has one.
+pub fn status(error: &(dyn Error + 'static)) -> Status {
+	std::iter::successors(Some(error), |&error| error.source()).find_map(link_status).unwrap_or(Status::Failure)
+}
+
+/// What the user might do about `error`.
+fn hint(error: &(dyn Error + 'static)) -> Option<&'static str> {
+	match error.downcast_ref::<EmbedError>() {
+		Some(EmbedError::Capacity { .. }) => return Some("`pngme capacity` shows how much each method can hold"),
+		Some(EmbedError::MissingChunkType) => return Some("give a chunk type such as ruSt, or pick another --method"),
+		_ => {}
+	}
+	match error.downcast_ref::<CryptoError>() {
+		Some(CryptoError::NoMatchingRecipient) => return Some("pass the --identity or --identity-file of one of the message's recipients"),
+		Some(CryptoError::Decryption) => return Some("the image was changed after encoding; try `pngme verify`"),
+		_ => {}
+	}
+	if let Some(ChunkTypeError::Validation(chunk_type::ValidationError::ReservedBit)) = error.downcast_ref::<ChunkTypeError>() {
+		return Some("the third letter must be uppercase; `pngme chunk-type suggest` generates a valid type");
+	}
+	match error.downcast_ref::<CommandError>() {
+		Some(CommandError::NoChunk(_)) => return Some("`pngme print` lists the chunks of a file"),
+		Some(CommandError::ReservedChunkType { .. }) => {
+			return Some("pass --force to embed it anyway, or pick a type with `pngme chunk-type suggest`")
+		}
+		Some(CommandError::Exists(_)) => return Some("write to a new file with -o, or pass --output-policy overwrite or backup"),
+		Some(CommandError::NoMessage) => return Some("check the chunk type, and the --method, --bits and --key used to encode the message"),
+		_ => {}
+	}
+	if error.is::<ConfigError>() {
+		return Some("fix the config file, or pass --config with another one");
+	}
+	match status(error) {
+		Status::Format => Some("the file is not a PNG image, or it is truncated or damaged"),
+		Status::Crc => Some("the file is damaged; `pngme verify` lists the damaged chunks, and `decode` reads them anyway"),
+		_ => None,
+	}
+}
+
+/// Prints `error` with a hint for the first link of its chain that has one,
+/// and returns its exit code.
+pub fn report(error: &(dyn Error + 'static)) -> ExitCode {
+	eprintln!("error: {error}");
+	if let Some(hint) = std::iter::successors(Some(error), |&error| error.source()).find_map(hint) {
+		eprintln!("hint: {hint}");
+	}
+	ExitCode::from(status(error) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+	use crate::chunk_type::ChunkType;
+
+	#[test]
+	fn test_status_follows_the_error_chain() {
+		let bytes = [png::Png::STANDARD_HEADER.as_slice(), &[0, 0, 0, 0], b"IEND", &[0, 0, 0, 0]].concat();
+		let crc: crate::Error = png::Png::try_from(bytes.as_slice()).err().unwrap().into();
+		assert_eq!(status(&*crc), Status::Crc);
+
+		let signature: crate::Error = png::Png::try_from(&[0u8; 8][..]).err().unwrap().into();
+		assert_eq!(status(&*signature), Status::Format);
+
+		let missing = CommandError::Read { path: "a.png".into(), source: ErrorKind::NotFound.into() };
+		assert_eq!(status(&missing), Status::NotFound);
+		assert_eq!(status(&CommandError::NoChunk(ChunkType::IEND)), Status::NotFound);
+		assert_eq!(status(&CryptoError::Decryption), Status::Crypto);
+		assert_eq!(status(&*crate::Error::from("anything else")), Status::Failure);
+	}
+}
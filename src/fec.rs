@@ -0,0 +1,337 @@
+//! Reed–Solomon forward error correction over GF(2^8).
+//!
+//! Data is split into codewords of at most 255 bytes, each carrying `parity`
+//! check bytes and correcting up to `parity / 2` corrupted bytes. Codewords
+//! are interleaved byte by byte so that a burst of damage is spread over all
+//! of them instead of exhausting one.
+
+const PRIMITIVE: u16 = 0x11d;
+const FIELD_SIZE: usize = 255;
+
+const fn tables() -> ([u8; 2 * FIELD_SIZE], [u8; FIELD_SIZE + 1]) {
+	let mut exp = [0u8; 2 * FIELD_SIZE];
+	let mut log = [0u8; FIELD_SIZE + 1];
+	let mut x: u16 = 1;
+	let mut i = 0;
+	while i < FIELD_SIZE {
+		exp[i] = x as u8;
+		log[x as usize] = i as u8;
+		x <<= 1;
+		if x & 0x100 != 0 {
+			x ^= PRIMITIVE;
+		}
+		i += 1;
+	}
+	while i < 2 * FIELD_SIZE {
+		exp[i] = exp[i - FIELD_SIZE];
+		i += 1;
+	}
+	(exp, log)
+}
+
+const EXP: [u8; 2 * FIELD_SIZE] = tables().0;
+const LOG: [u8; FIELD_SIZE + 1] = tables().1;
+
+fn mul(a: u8, b: u8) -> u8 {
+	if a == 0 || b == 0 {
+		return 0;
+	}
+	EXP[LOG[a as usize] as usize + LOG[b as usize] as usize]
+}
+
+fn div(a: u8, b: u8) -> u8 {
+	if a == 0 {
+		return 0;
+	}
+	EXP[(LOG[a as usize] as usize + FIELD_SIZE - LOG[b as usize] as usize) % FIELD_SIZE]
+}
+
+fn inverse(a: u8) -> u8 {
+	EXP[FIELD_SIZE - LOG[a as usize] as usize]
+}
+
+// α^power
+fn alpha(power: usize) -> u8 {
+	EXP[power % FIELD_SIZE]
+}
+
+// Polynomials are stored highest degree first.
+
+fn poly_scale(p: &[u8], x: u8) -> Vec<u8> {
+	p.iter().map(|&c| mul(c, x)).collect()
+}
+
+fn poly_add(p: &[u8], q: &[u8]) -> Vec<u8> {
+	let len = p.len().max(q.len());
+	let mut r = vec![0u8; len];
+	r[len - p.len()..].copy_from_slice(p);
+	for (i, &c) in q.iter().enumerate() {
+		r[i + len - q.len()] ^= c;
+	}
+	r
+}
+
+fn poly_mul(p: &[u8], q: &[u8]) -> Vec<u8> {
+	let mut r = vec![0u8; p.len() + q.len() - 1];
+	for (j, &b) in q.iter().enumerate() {
+		for (i, &a) in p.iter().enumerate() {
+			r[i + j] ^= mul(a, b);
+		}
+	}
+	r
+}
+
+fn poly_eval(p: &[u8], x: u8) -> u8 {
+	p.iter().fold(0, |acc, &c| mul(acc, x) ^ c)
+}
+
+fn generator(parity: usize) -> Vec<u8> {
+	(0..parity).fold(vec![1], |g, i| poly_mul(&g, &[1, alpha(i)]))
+}
+
+/// Appends `parity` check bytes to `message` (at most 255 bytes in total).
+pub fn encode_block(message: &[u8], parity: usize) -> Vec<u8> {
+	let generator = generator(parity);
+	let mut out = message.to_vec();
+	out.resize(message.len() + parity, 0);
+	for i in 0..message.len() {
+		let coef = out[i];
+		if coef != 0 {
+			for (j, &g) in generator.iter().enumerate().skip(1) {
+				out[i + j] ^= mul(g, coef);
+			}
+		}
+	}
+	out[..message.len()].copy_from_slice(message);
+	out
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum FecError {
+	#[error("too many corrupted bytes to correct")]
+	Uncorrectable,
+
+	#[error("protected data is truncated (expected {expected} bytes, got {actual})")]
+	Truncated {
+		expected: usize,
+		actual: usize,
+	},
+}
+
+// Syndromes with a leading zero, so that index k holds S(k - 1).
+fn syndromes(codeword: &[u8], parity: usize) -> Vec<u8> {
+	std::iter::once(0)
+		.chain((0..parity).map(|i| poly_eval(codeword, alpha(i))))
+		.collect()
+}
+
+// Berlekamp–Massey.
+fn error_locator(syndromes: &[u8], parity: usize) -> Result<Vec<u8>, FecError> {
+	let mut locator = vec![1u8];
+	let mut old = vec![1u8];
+	for k in 1..=parity {
+		let mut delta = syndromes[k];
+		for j in 1..locator.len() {
+			delta ^= mul(locator[locator.len() - 1 - j], syndromes[k - j]);
+		}
+		old.push(0);
+		if delta != 0 {
+			if old.len() > locator.len() {
+				let new = poly_scale(&old, delta);
+				old = poly_scale(&locator, inverse(delta));
+				locator = new;
+			}
+			locator = poly_add(&locator, &poly_scale(&old, delta));
+		}
+	}
+
+	let leading = locator.iter().take_while(|&&c| c == 0).count();
+	locator.drain(..leading);
+	if (locator.len() - 1) * 2 > parity {
+		return Err(FecError::Uncorrectable);
+	}
+	Ok(locator)
+}
+
+// Chien search: positions (from the start of the codeword) of the errors.
+fn error_positions(locator: &[u8], len: usize) -> Result<Vec<usize>, FecError> {
+	let reversed: Vec<u8> = locator.iter().rev().copied().collect();
+	let positions: Vec<usize> = (0..len)
+		.filter(|&i| poly_eval(&reversed, alpha(i)) == 0)
+		.map(|i| len - 1 - i)
+		.collect();
+	if positions.len() != locator.len() - 1 {
+		return Err(FecError::Uncorrectable);
+	}
+	Ok(positions)
+}
+
+// Forney algorithm.
+fn correct_errata(codeword: &mut [u8], syndromes: &[u8], positions: &[usize]) {
+	let len = codeword.len();
+	let coefficients: Vec<usize> = positions.iter().map(|&p| len - 1 - p).collect();
+	let locator = coefficients
+		.iter()
+		.fold(vec![1u8], |loc, &c| poly_mul(&loc, &poly_add(&[1], &[alpha(c), 0])));
+
+	let reversed_syndromes: Vec<u8> = syndromes.iter().rev().copied().collect();
+	let product = poly_mul(&reversed_syndromes, &locator);
+	let evaluator = &product[product.len() - locator.len()..];
+
+	let x: Vec<u8> = coefficients.iter().map(|&c| alpha(c)).collect();
+	for (i, &xi) in x.iter().enumerate() {
+		let xi_inv = inverse(xi);
+		let locator_prime = x
+			.iter()
+			.enumerate()
+			.filter(|&(j, _)| j != i)
+			.fold(1u8, |acc, (_, &xj)| mul(acc, 1 ^ mul(xi_inv, xj)));
+		let y = mul(xi, poly_eval(evaluator, xi_inv));
+		codeword[positions[i]] ^= div(y, locator_prime);
+	}
+}
+
+/// Corrects `codeword` in place and returns the number of corrected bytes.
+pub fn decode_block(codeword: &mut [u8], parity: usize) -> Result<usize, FecError> {
+	let syndromes = syndromes(codeword, parity);
+	if syndromes.iter().all(|&s| s == 0) {
+		return Ok(0);
+	}
+	let locator = error_locator(&syndromes, parity)?;
+	let positions = error_positions(&locator, codeword.len())?;
+	correct_errata(codeword, &syndromes, &positions);
+	if syndromes_are_zero(codeword, parity) {
+		Ok(positions.len())
+	} else {
+		Err(FecError::Uncorrectable)
+	}
+}
+
+fn syndromes_are_zero(codeword: &[u8], parity: usize) -> bool {
+	(0..parity).all(|i| poly_eval(codeword, alpha(i)) == 0)
+}
+
+// Number of interleaved codewords for `len` bytes of data.
+fn block_count(len: usize, parity: usize) -> usize {
+	len.div_ceil(FIELD_SIZE - parity).max(1)
+}
+
+// Lengths of the interleaved messages `len` bytes are split into.
+fn message_lengths(len: usize, parity: usize) -> Vec<usize> {
+	let blocks = block_count(len, parity);
+	(0..blocks).map(|b| len / blocks + usize::from(b < len % blocks)).collect()
+}
+
+/// Number of bytes `encode` produces for `len` bytes of data.
+pub fn encoded_len(len: usize, parity: usize) -> usize {
+	len.saturating_add(block_count(len, parity).saturating_mul(parity))
+}
+
+/// Protects `data` with `parity` check bytes per codeword (1..=254).
+pub fn encode(data: &[u8], parity: usize) -> Vec<u8> {
+	let lengths = message_lengths(data.len(), parity);
+	let blocks = lengths.len();
+	let codewords: Vec<Vec<u8>> = (0..blocks)
+		.map(|b| {
+			let message: Vec<u8> = data.iter().skip(b).step_by(blocks).copied().collect();
+			encode_block(&message, parity)
+		})
+		.collect();
+
+	let longest = lengths[0] + parity;
+	(0..longest)
+		.flat_map(|i| codewords.iter().filter_map(move |codeword| codeword.get(i).copied()))
+		.collect()
+}
+
+/// Recovers `len` bytes of data from the output of `encode`, returning the
+/// data and the number of corrected bytes.
+pub fn decode(encoded: &[u8], len: usize, parity: usize) -> Result<(Vec<u8>, usize), FecError> {
+	// `len` comes from the envelope header, so it is checked against the input
+	// before anything is allocated for it
+	let expected = encoded_len(len, parity);
+	if encoded.len() < expected {
+		return Err(FecError::Truncated { expected, actual: encoded.len() });
+	}
+
+	let lengths = message_lengths(len, parity);
+	let mut codewords: Vec<Vec<u8>> = lengths.iter().map(|l| Vec::with_capacity(l + parity)).collect();
+	let longest = lengths[0] + parity;
+	let mut bytes = encoded.iter();
+	for i in 0..longest {
+		for (codeword, message_len) in codewords.iter_mut().zip(&lengths) {
+			if i < message_len + parity {
+				codeword.push(*bytes.next().expect("length checked above"));
+			}
+		}
+	}
+
+	let mut corrected = 0;
+	for (codeword, &message_len) in codewords.iter_mut().zip(&lengths) {
+		corrected += decode_block(codeword, parity)?;
+		codeword.truncate(message_len);
+	}
+
+	let blocks = codewords.len();
+	let data = (0..len).map(|i| codewords[i % blocks][i / blocks]).collect();
+	Ok((data, corrected))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+
+	#[test]
+	fn test_known_codeword() {
+		// "hello world" with 10 check bytes, from the Wikiversity RS tutorial
+		let codeword = encode_block(b"hello world", 10);
+		assert_eq!(&codeword[11..], &[0xed, 0x25, 0x54, 0xc4, 0xfd, 0xfd, 0x89, 0xf3, 0xa8, 0xaa]);
+	}
+
+	#[test]
+	fn test_corrects_up_to_half_parity() {
+		let mut codeword = encode_block(b"hello world", 10);
+		for i in [0, 3, 7, 12, 20] {
+			codeword[i] ^= 0x5a;
+		}
+		assert_eq!(decode_block(&mut codeword, 10), Ok(5));
+		assert_eq!(&codeword[..11], b"hello world");
+	}
+
+	#[test]
+	fn test_too_many_errors() {
+		let mut codeword = encode_block(b"hello world", 4);
+		for i in [0, 1, 2] {
+			codeword[i] ^= 0xff;
+		}
+		assert!(decode_block(&mut codeword, 4).is_err() || &codeword[..11] != b"hello world");
+	}
+
+	#[test]
+	fn test_interleaved_roundtrip_with_burst() {
+		let data: Vec<u8> = (0..1000).map(|i| (i * 7 % 256) as u8).collect();
+		let mut encoded = encode(&data, 16);
+		assert_eq!(encoded.len(), encoded_len(data.len(), 16));
+
+		// a burst longer than one codeword could correct on its own
+		encoded[100..130].iter_mut().for_each(|byte| *byte = !*byte);
+		let (decoded, corrected) = decode(&encoded, data.len(), 16).unwrap();
+		assert_eq!(decoded, data);
+		assert_eq!(corrected, 30);
+	}
+
+	#[test]
+	fn test_empty_data() {
+		let encoded = encode(&[], 8);
+		assert_eq!(decode(&encoded, 0, 8).unwrap(), (Vec::new(), 0));
+	}
+
+	#[test]
+	fn test_truncated() {
+		let encoded = encode(b"some data", 8);
+		assert!(matches!(decode(&encoded[..10], 9, 8), Err(FecError::Truncated { .. })));
+		assert!(matches!(decode(&encoded, u32::MAX as usize, 8), Err(FecError::Truncated { .. })));
+		assert_eq!(encoded_len(usize::MAX, 8), usize::MAX);
+	}
+}
@@ -0,0 +1,176 @@
+use std::io::{Read, Write};
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use crate::image::ColorType;
+
+/// An ICC colour profile whose header has been checked.
+pub struct IccProfile {
+	data: Vec<u8>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum IccError {
+	#[error("ICC profile is {0} bytes, shorter than its 128 byte header")]
+	TooShort(usize),
+
+	#[error("ICC profile header declares {declared} bytes but the profile has {actual}")]
+	Size {
+		declared: usize,
+		actual: usize,
+	},
+
+	#[error("ICC profile lacks the 'acsp' signature")]
+	Signature,
+
+	#[error("{color_type} images need a {expected} ICC profile, not {actual}")]
+	ColourSpace {
+		color_type: ColorType,
+		expected: &'static str,
+		actual: String,
+	},
+
+	#[error("failed to inflate ICC profile: {0}")]
+	Inflate(#[from] std::io::Error),
+}
+
+impl IccProfile {
+	pub const HEADER_LENGTH: usize = 128;
+
+	fn validate(data: Vec<u8>) -> Result<IccProfile, IccError> {
+		if data.len() < Self::HEADER_LENGTH {
+			return Err(IccError::TooShort(data.len()));
+		}
+		let declared = u32::from_be_bytes(data[0..4].try_into().expect("four bytes")) as usize;
+		if declared != data.len() {
+			return Err(IccError::Size { declared, actual: data.len() });
+		}
+		if &data[36..40] != b"acsp" {
+			return Err(IccError::Signature);
+		}
+		Ok(IccProfile { data })
+	}
+
+	/// Inflates the profile held by an iCCP chunk without checking it.
+	pub fn inflate(zlib: &[u8]) -> Result<Vec<u8>, IccError> {
+		let mut data = Vec::new();
+		ZlibDecoder::new(zlib).read_to_end(&mut data)?;
+		Ok(data)
+	}
+
+	/// Inflates and checks the profile held by an iCCP chunk.
+	#[cfg(test)]
+	pub fn decompress(zlib: &[u8]) -> Result<IccProfile, IccError> {
+		Self::validate(Self::inflate(zlib)?)
+	}
+
+	pub fn compress(&self) -> Vec<u8> {
+		let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+		encoder.write_all(&self.data).expect("writing to a Vec cannot fail");
+		encoder.finish().expect("writing to a Vec cannot fail")
+	}
+
+	#[cfg(test)]
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.data
+	}
+
+	/// Profile version as (major, minor).
+	pub fn version(&self) -> (u8, u8) {
+		(self.data[8], self.data[9] >> 4)
+	}
+
+	pub fn class(&self) -> &'static str {
+		match &self.data[12..16] {
+			b"scnr" => "input",
+			b"mntr" => "display",
+			b"prtr" => "output",
+			b"link" => "device link",
+			b"spac" => "colour space",
+			b"abst" => "abstract",
+			b"nmcl" => "named colour",
+			_ => "unknown",
+		}
+	}
+
+	/// Colour space signature, e.g. "RGB" or "GRAY".
+	pub fn colour_space(&self) -> String {
+		String::from_utf8_lossy(&self.data[16..20]).trim_end().to_string()
+	}
+
+	/// PNG only allows greyscale profiles for greyscale images and RGB
+	/// profiles for colour images.
+	pub fn check_color_type(&self, color_type: ColorType) -> Result<(), IccError> {
+		let expected = match color_type {
+			ColorType::Grayscale | ColorType::GrayscaleAlpha => "GRAY",
+			_ => "RGB",
+		};
+		let actual = self.colour_space();
+		if actual != expected {
+			return Err(IccError::ColourSpace { color_type, expected, actual });
+		}
+		Ok(())
+	}
+}
+
+impl TryFrom<&[u8]> for IccProfile {
+	type Error = IccError;
+
+	fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+		Self::validate(value.to_vec())
+	}
+}
+
+impl std::fmt::Display for IccProfile {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let (major, minor) = self.version();
+		write!(f, "ICC v{major}.{minor} {} profile, {} data, {} bytes", self.class(), self.colour_space(), self.data.len())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+
+	fn testing_profile(colour_space: &[u8; 4]) -> Vec<u8> {
+		let mut data = vec![0; 132];
+		data[0..4].copy_from_slice(&132u32.to_be_bytes());
+		data[8] = 4;
+		data[9] = 0x30;
+		data[12..16].copy_from_slice(b"mntr");
+		data[16..20].copy_from_slice(colour_space);
+		data[36..40].copy_from_slice(b"acsp");
+		data
+	}
+
+	#[test]
+	fn test_header_fields() {
+		let profile = IccProfile::try_from(testing_profile(b"RGB ").as_slice()).unwrap();
+		assert_eq!(profile.to_string(), "ICC v4.3 display profile, RGB data, 132 bytes");
+		assert!(profile.check_color_type(ColorType::Rgba).is_ok());
+		assert!(profile.check_color_type(ColorType::Indexed).is_ok());
+		assert!(matches!(profile.check_color_type(ColorType::Grayscale), Err(IccError::ColourSpace { .. })));
+	}
+
+	#[test]
+	fn test_compression_roundtrip() {
+		let profile = IccProfile::try_from(testing_profile(b"GRAY").as_slice()).unwrap();
+		let inflated = IccProfile::decompress(&profile.compress()).unwrap();
+		assert_eq!(inflated.as_bytes(), profile.as_bytes());
+		assert!(inflated.check_color_type(ColorType::GrayscaleAlpha).is_ok());
+	}
+
+	#[test]
+	fn test_rejects_invalid_headers() {
+		assert!(matches!(IccProfile::try_from(&[0u8; 64][..]), Err(IccError::TooShort(64))));
+
+		let mut data = testing_profile(b"RGB ");
+		data.push(0);
+		assert!(matches!(IccProfile::try_from(data.as_slice()), Err(IccError::Size { declared: 132, actual: 133 })));
+
+		let mut data = testing_profile(b"RGB ");
+		data[36..40].copy_from_slice(b"jpeg");
+		assert!(matches!(IccProfile::try_from(data.as_slice()), Err(IccError::Signature)));
+	}
+}
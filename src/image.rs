@@ -0,0 +1,569 @@
+use std::io::{Read, Write};
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+	Grayscale,
+	Rgb,
+	Indexed,
+	GrayscaleAlpha,
+	Rgba,
+}
+
+impl ColorType {
+	#[cfg(test)]
+	pub fn code(&self) -> u8 {
+		match self {
+			ColorType::Grayscale => 0,
+			ColorType::Rgb => 2,
+			ColorType::Indexed => 3,
+			ColorType::GrayscaleAlpha => 4,
+			ColorType::Rgba => 6,
+		}
+	}
+
+	/// Number of samples per pixel.
+	pub fn channels(&self) -> usize {
+		match self {
+			ColorType::Grayscale | ColorType::Indexed => 1,
+			ColorType::GrayscaleAlpha => 2,
+			ColorType::Rgb => 3,
+			ColorType::Rgba => 4,
+		}
+	}
+
+	pub fn has_alpha(&self) -> bool {
+		matches!(self, ColorType::GrayscaleAlpha | ColorType::Rgba)
+	}
+
+	fn allows_bit_depth(&self, bit_depth: u8) -> bool {
+		match self {
+			ColorType::Grayscale => matches!(bit_depth, 1 | 2 | 4 | 8 | 16),
+			ColorType::Indexed => matches!(bit_depth, 1 | 2 | 4 | 8),
+			_ => matches!(bit_depth, 8 | 16),
+		}
+	}
+}
+
+impl TryFrom<u8> for ColorType {
+	type Error = ValidationError;
+
+	fn try_from(value: u8) -> Result<Self, Self::Error> {
+		match value {
+			0 => Ok(ColorType::Grayscale),
+			2 => Ok(ColorType::Rgb),
+			3 => Ok(ColorType::Indexed),
+			4 => Ok(ColorType::GrayscaleAlpha),
+			6 => Ok(ColorType::Rgba),
+			other => Err(ValidationError::ColorType(other)),
+		}
+	}
+}
+
+impl std::fmt::Display for ColorType {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let name = match self {
+			ColorType::Grayscale => "grayscale",
+			ColorType::Rgb => "RGB",
+			ColorType::Indexed => "indexed",
+			ColorType::GrayscaleAlpha => "grayscale+alpha",
+			ColorType::Rgba => "RGBA",
+		};
+		write!(f, "{name}")
+	}
+}
+
+/// Decoded contents of the IHDR chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageHeader {
+	pub width: u32,
+	pub height: u32,
+	pub bit_depth: u8,
+	pub color_type: ColorType,
+	pub interlaced: bool,
+}
+
+// Adam7 pass origins and steps: (x0, y0, dx, dy).
+const ADAM7: [(u32, u32, u32, u32); 7] = [
+	(0, 0, 8, 8),
+	(4, 0, 8, 8),
+	(0, 4, 4, 8),
+	(2, 0, 4, 4),
+	(0, 2, 2, 4),
+	(1, 0, 2, 2),
+	(0, 1, 1, 2),
+];
+
+impl ImageHeader {
+	pub const LENGTH: usize = 13;
+	/// Largest width or height the PNG specification allows, 2^31 - 1.
+	pub const MAX_DIMENSION: u32 = i32::MAX as u32;
+
+	pub fn bits_per_pixel(&self) -> usize {
+		self.color_type.channels() * self.bit_depth as usize
+	}
+
+	/// Bytes per complete pixel, rounded up to one as required by the filters.
+	pub fn filter_stride(&self) -> usize {
+		self.bits_per_pixel().div_ceil(8)
+	}
+
+	/// Length of one unfiltered scanline of `width` pixels, without filter byte.
+	pub fn row_len(&self, width: u32) -> usize {
+		(width as usize * self.bits_per_pixel()).div_ceil(8)
+	}
+
+	/// Dimensions of the sub-images stored in the image stream, in order: the
+	/// whole image, or the seven Adam7 passes (some of which may be empty).
+	pub fn sub_images(&self) -> Vec<(u32, u32)> {
+		if !self.interlaced {
+			return vec![(self.width, self.height)];
+		}
+		ADAM7
+			.iter()
+			.map(|&(x0, y0, dx, dy)| {
+				let width = self.width.saturating_sub(x0).div_ceil(dx);
+				let height = self.height.saturating_sub(y0).div_ceil(dy);
+				(width, height)
+			})
+			.collect()
+	}
+
+	/// Total length of the unfiltered image stream, without filter bytes, or
+	/// an error if it does not fit in memory's address space.
+	pub fn data_len(&self) -> Result<usize, DecodeError> {
+		let too_large = || DecodeError::TooLarge { width: self.width, height: self.height };
+		self.sub_images().iter().filter(|(width, _)| *width > 0).try_fold(0usize, |total, &(width, height)| {
+			let row_len = (width as usize).checked_mul(self.bits_per_pixel()).ok_or_else(too_large)?.div_ceil(8);
+			row_len.checked_mul(height as usize).and_then(|len| total.checked_add(len)).ok_or_else(too_large)
+		})
+	}
+
+	/// Number of scanlines (and thus filter bytes) in the image stream.
+	pub fn scanline_count(&self) -> usize {
+		self.sub_images()
+			.iter()
+			.filter(|(width, _)| *width > 0)
+			.map(|&(_, height)| height as usize)
+			.sum()
+	}
+
+	/// Same pixel format with different dimensions, e.g. for an APNG frame.
+	pub fn with_size(&self, width: u32, height: u32) -> ImageHeader {
+		ImageHeader { width, height, ..*self }
+	}
+
+	#[cfg(test)]
+	pub fn as_bytes(&self) -> [u8; Self::LENGTH] {
+		let mut bytes = [0u8; Self::LENGTH];
+		bytes[0..4].copy_from_slice(&self.width.to_be_bytes());
+		bytes[4..8].copy_from_slice(&self.height.to_be_bytes());
+		bytes[8] = self.bit_depth;
+		bytes[9] = self.color_type.code();
+		bytes[12] = self.interlaced as u8;
+		bytes
+	}
+
+	/// Reads the header from the IHDR chunk, which must come first.
+	pub fn from_png(png: &Png) -> Result<ImageHeader, ImageError> {
+		let chunk = png
+			.chunks()
+			.first()
+			.filter(|chunk| chunk.chunk_type() == &ChunkType::IHDR)
+			.ok_or(ImageError::MissingHeader)?;
+		Ok(ImageHeader::try_from(chunk.data())?)
+	}
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ValidationError {
+	#[error("IHDR must be 13 bytes (got {0})")]
+	Length(usize),
+
+	#[error("image dimensions must be non-zero (got {width}x{height})")]
+	Dimensions {
+		width: u32,
+		height: u32,
+	},
+
+	#[error("image dimensions must not exceed 2^31-1 (got {width}x{height})")]
+	TooLarge {
+		width: u32,
+		height: u32,
+	},
+
+	#[error("invalid color type {0}")]
+	ColorType(u8),
+
+	#[error("bit depth {bit_depth} is not allowed for {color_type} images")]
+	BitDepth {
+		color_type: ColorType,
+		bit_depth: u8,
+	},
+
+	#[error("unsupported compression method {0}")]
+	Compression(u8),
+
+	#[error("unsupported filter method {0}")]
+	FilterMethod(u8),
+
+	#[error("invalid interlace method {0}")]
+	Interlace(u8),
+}
+
+impl TryFrom<&[u8]> for ImageHeader {
+	type Error = ValidationError;
+
+	fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+		let bytes: [u8; Self::LENGTH] = value
+			.try_into()
+			.map_err(|_| ValidationError::Length(value.len()))?;
+
+		let width = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+		let height = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+		if width == 0 || height == 0 {
+			return Err(ValidationError::Dimensions { width, height });
+		}
+		if width > ImageHeader::MAX_DIMENSION || height > ImageHeader::MAX_DIMENSION {
+			return Err(ValidationError::TooLarge { width, height });
+		}
+
+		let bit_depth = bytes[8];
+		let color_type = ColorType::try_from(bytes[9])?;
+		if !color_type.allows_bit_depth(bit_depth) {
+			return Err(ValidationError::BitDepth { color_type, bit_depth });
+		}
+
+		if bytes[10] != 0 {
+			return Err(ValidationError::Compression(bytes[10]));
+		}
+		if bytes[11] != 0 {
+			return Err(ValidationError::FilterMethod(bytes[11]));
+		}
+		let interlaced = match bytes[12] {
+			0 => false,
+			1 => true,
+			other => return Err(ValidationError::Interlace(other)),
+		};
+
+		Ok(ImageHeader { width, height, bit_depth, color_type, interlaced })
+	}
+}
+
+/// Unfiltered image data: every scanline of every sub-image concatenated,
+/// with the filter type of each scanline kept aside so that re-encoding
+/// filters the rows the same way the original encoder did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Raster {
+	header: ImageHeader,
+	filters: Vec<u8>,
+	data: Vec<u8>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum DecodeError {
+	#[error("failed to inflate image data: {0}")]
+	Inflate(#[from] std::io::Error),
+
+	#[error("a {width}x{height} image is too large to decode")]
+	TooLarge {
+		width: u32,
+		height: u32,
+	},
+
+	#[error("image data too short (expected {expected} bytes, got {actual})")]
+	Truncated {
+		expected: usize,
+		actual: usize,
+	},
+
+	#[error("invalid filter type {0}")]
+	FilterType(u8),
+}
+
+impl Raster {
+	/// Wraps unfiltered image data; every scanline will be written unfiltered.
+	#[cfg(test)]
+	pub fn new(header: ImageHeader, data: Vec<u8>) -> Raster {
+		Raster { header, filters: vec![0; header.scanline_count()], data }
+	}
+
+	/// Inflates and unfilters a zlib image stream described by `header`.
+	/// Bytes after the end of the zlib stream are ignored.
+	pub fn decode(header: ImageHeader, zlib: &[u8]) -> Result<Raster, DecodeError> {
+		let data_len = header.data_len()?;
+		let expected = data_len
+			.checked_add(header.scanline_count())
+			.ok_or(DecodeError::TooLarge { width: header.width, height: header.height })?;
+		// inflating no more than the image needs keeps a decompression bomb from
+		// exhausting memory
+		let mut filtered = Vec::new();
+		ZlibDecoder::new(zlib).take(expected as u64).read_to_end(&mut filtered)?;
+		if filtered.len() < expected {
+			return Err(DecodeError::Truncated { expected, actual: filtered.len() });
+		}
+
+		let stride = header.filter_stride();
+		let mut filters = Vec::new();
+		let mut data = Vec::with_capacity(data_len);
+		let mut offset = 0;
+		for (width, height) in header.sub_images() {
+			if width == 0 {
+				continue;
+			}
+			let row_len = header.row_len(width);
+			for row in 0..height as usize {
+				let end = offset + 1 + row_len;
+				let filter = filtered[offset];
+				let line_start = data.len();
+				data.extend_from_slice(&filtered[offset + 1..end]);
+				let (previous, current) = data.split_at_mut(line_start);
+				let previous = match row {
+					0 => None,
+					_ => Some(&previous[line_start - row_len..]),
+				};
+				unfilter(filter, stride, previous, current)?;
+				filters.push(filter);
+				offset = end;
+			}
+		}
+
+		Ok(Raster { header, filters, data })
+	}
+
+	/// Filters and deflates the raster into a zlib image stream.
+	pub fn encode(&self, compression: Compression) -> Vec<u8> {
+		let stride = self.header.filter_stride();
+		let mut filtered = Vec::with_capacity(self.data.len() + self.filters.len());
+		let mut offset = 0;
+		let mut filters = self.filters.iter();
+		for (width, height) in self.header.sub_images() {
+			if width == 0 {
+				continue;
+			}
+			let row_len = self.header.row_len(width);
+			for row in 0..height as usize {
+				let filter = *filters.next().expect("one filter type per scanline");
+				let current = &self.data[offset..offset + row_len];
+				let previous = match row {
+					0 => None,
+					_ => Some(&self.data[offset - row_len..offset]),
+				};
+				filtered.push(filter);
+				filter_row(filter, stride, previous, current, &mut filtered);
+				offset += row_len;
+			}
+		}
+
+		let mut encoder = ZlibEncoder::new(Vec::new(), compression);
+		encoder.write_all(&filtered).expect("writing to a Vec cannot fail");
+		encoder.finish().expect("writing to a Vec cannot fail")
+	}
+
+	pub fn header(&self) -> &ImageHeader {
+		&self.header
+	}
+
+	pub fn data(&self) -> &[u8] {
+		&self.data
+	}
+
+	pub fn data_mut(&mut self) -> &mut [u8] {
+		&mut self.data
+	}
+
+	/// Decodes the main image (IHDR + IDAT) of `png`.
+	pub fn from_png(png: &Png) -> Result<Raster, ImageError> {
+		let header = ImageHeader::from_png(png)?;
+		Ok(Raster::decode(header, &png.image_data())?)
+	}
+}
+
+/// Number of bytes the zlib stream at the start of `zlib` occupies; anything
+/// after it is ignored by decoders.
+pub fn zlib_stream_len(zlib: &[u8]) -> Result<usize, DecodeError> {
+	let mut decoder = ZlibDecoder::new(zlib);
+	std::io::copy(&mut decoder, &mut std::io::sink())?;
+	Ok(decoder.total_in() as usize)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+	let p = a as i16 + b as i16 - c as i16;
+	let pa = (p - a as i16).abs();
+	let pb = (p - b as i16).abs();
+	let pc = (p - c as i16).abs();
+	if pa <= pb && pa <= pc {
+		a
+	} else if pb <= pc {
+		b
+	} else {
+		c
+	}
+}
+
+fn unfilter(filter: u8, stride: usize, previous: Option<&[u8]>, current: &mut [u8]) -> Result<(), DecodeError> {
+	let up = |i: usize| previous.map_or(0, |row| row[i]);
+	match filter {
+		0 => {}
+		1 => {
+			for i in stride..current.len() {
+				current[i] = current[i].wrapping_add(current[i - stride]);
+			}
+		}
+		2 => {
+			for (i, byte) in current.iter_mut().enumerate() {
+				*byte = byte.wrapping_add(up(i));
+			}
+		}
+		3 => {
+			for i in 0..current.len() {
+				let left = if i >= stride { current[i - stride] } else { 0 };
+				current[i] = current[i].wrapping_add(((left as u16 + up(i) as u16) / 2) as u8);
+			}
+		}
+		4 => {
+			for i in 0..current.len() {
+				let (left, upper_left) = if i >= stride { (current[i - stride], up(i - stride)) } else { (0, 0) };
+				current[i] = current[i].wrapping_add(paeth(left, up(i), upper_left));
+			}
+		}
+		other => return Err(DecodeError::FilterType(other)),
+	}
+	Ok(())
+}
+
+fn filter_row(filter: u8, stride: usize, previous: Option<&[u8]>, current: &[u8], out: &mut Vec<u8>) {
+	let up = |i: usize| previous.map_or(0, |row| row[i]);
+	let left = |i: usize| if i >= stride { current[i - stride] } else { 0 };
+	let upper_left = |i: usize| if i >= stride { up(i - stride) } else { 0 };
+	out.extend((0..current.len()).map(|i| {
+		let predictor = match filter {
+			1 => left(i),
+			2 => up(i),
+			3 => ((left(i) as u16 + up(i) as u16) / 2) as u8,
+			4 => paeth(left(i), up(i), upper_left(i)),
+			_ => 0,
+		};
+		current[i].wrapping_sub(predictor)
+	}));
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImageError {
+	#[error("PNG has no IHDR chunk at the start")]
+	MissingHeader,
+
+	#[error(transparent)]
+	Header(#[from] ValidationError),
+
+	#[error(transparent)]
+	Decode(#[from] DecodeError),
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+
+	fn header(width: u32, height: u32, bit_depth: u8, color_type: ColorType, interlaced: bool) -> ImageHeader {
+		ImageHeader { width, height, bit_depth, color_type, interlaced }
+	}
+
+	fn header_bytes(width: u32, height: u32, bit_depth: u8, color_type: u8, interlace: u8) -> Vec<u8> {
+		width
+			.to_be_bytes()
+			.iter()
+			.chain(height.to_be_bytes().iter())
+			.chain([bit_depth, color_type, 0, 0, interlace].iter())
+			.copied()
+			.collect()
+	}
+
+	#[test]
+	fn test_header_from_bytes() {
+		let parsed = ImageHeader::try_from(header_bytes(50, 40, 8, 6, 0).as_slice()).unwrap();
+		assert_eq!(parsed, header(50, 40, 8, ColorType::Rgba, false));
+		assert_eq!(parsed.as_bytes().to_vec(), header_bytes(50, 40, 8, 6, 0));
+	}
+
+	#[test]
+	fn test_invalid_headers() {
+		assert!(ImageHeader::try_from(header_bytes(0, 40, 8, 6, 0).as_slice()).is_err());
+		assert!(ImageHeader::try_from(header_bytes(50, 40, 4, 6, 0).as_slice()).is_err());
+		assert!(ImageHeader::try_from(header_bytes(50, 40, 8, 5, 0).as_slice()).is_err());
+		assert!(ImageHeader::try_from(header_bytes(50, 40, 8, 6, 2).as_slice()).is_err());
+		assert!(ImageHeader::try_from(&header_bytes(50, 40, 8, 6, 0)[..12]).is_err());
+		assert!(matches!(ImageHeader::try_from(header_bytes(1 << 31, 40, 8, 6, 0).as_slice()), Err(ValidationError::TooLarge { .. })));
+	}
+
+	#[test]
+	fn test_row_len() {
+		assert_eq!(header(10, 1, 1, ColorType::Grayscale, false).row_len(10), 2);
+		assert_eq!(header(10, 1, 16, ColorType::Rgb, false).row_len(10), 60);
+		assert_eq!(header(10, 1, 4, ColorType::Indexed, false).row_len(3), 2);
+	}
+
+	#[test]
+	fn test_adam7_sub_images() {
+		let passes = header(5, 3, 8, ColorType::Rgb, true).sub_images();
+		assert_eq!(passes, vec![(1, 1), (1, 1), (2, 0), (1, 1), (3, 1), (2, 2), (5, 1)]);
+		let pixels: u32 = passes.iter().map(|(w, h)| w * h).sum();
+		assert_eq!(pixels, 15);
+	}
+
+	#[test]
+	fn test_raster_roundtrip_all_filters() {
+		let header = header(4, 5, 8, ColorType::Rgb, false);
+		let data: Vec<u8> = (0..header.data_len().unwrap()).map(|i| (i * 37 % 251) as u8).collect();
+		let raster = Raster { header, filters: vec![0, 1, 2, 3, 4], data };
+
+		let decoded = Raster::decode(header, &raster.encode(Compression::best())).unwrap();
+		assert_eq!(decoded, raster);
+	}
+
+	#[test]
+	fn test_raster_roundtrip_interlaced() {
+		let header = header(9, 7, 16, ColorType::GrayscaleAlpha, true);
+		let data: Vec<u8> = (0..header.data_len().unwrap()).map(|i| (i * 13 % 256) as u8).collect();
+		let filters = (0..header.scanline_count()).map(|row| (row % 5) as u8).collect();
+		let raster = Raster { header, filters, data };
+
+		let decoded = Raster::decode(header, &raster.encode(Compression::best())).unwrap();
+		assert_eq!(decoded, raster);
+	}
+
+	#[test]
+	fn test_truncated_stream() {
+		let short = Raster::new(header(4, 3, 8, ColorType::Grayscale, false), vec![0; 12]);
+		assert!(matches!(
+			Raster::decode(header(4, 4, 8, ColorType::Grayscale, false), &short.encode(Compression::best())),
+			Err(DecodeError::Truncated { .. })
+		));
+	}
+
+	#[test]
+	fn test_oversized_images() {
+		let huge = ImageHeader::try_from(header_bytes(ImageHeader::MAX_DIMENSION, ImageHeader::MAX_DIMENSION, 16, 6, 0).as_slice()).unwrap();
+		assert!(matches!(huge.data_len(), Err(DecodeError::TooLarge { .. })));
+		assert!(matches!(Raster::decode(huge, &[]), Err(DecodeError::TooLarge { .. })));
+
+		// a stream inflating to far more than the image needs is only read as
+		// far as the image goes
+		let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+		encoder.write_all(&vec![0; 1 << 20]).unwrap();
+		let raster = Raster::decode(header(4, 4, 8, ColorType::Grayscale, false), &encoder.finish().unwrap()).unwrap();
+		assert_eq!(raster.data(), [0; 16]);
+	}
+
+	#[test]
+	fn test_zlib_stream_len_ignores_trailing_bytes() {
+		let mut zlib = Raster::new(header(4, 4, 8, ColorType::Grayscale, false), vec![7; 16]).encode(Compression::best());
+		let len = zlib.len();
+		zlib.extend_from_slice(b"trailing");
+		assert_eq!(zlib_stream_len(&zlib).unwrap(), len);
+	}
+}
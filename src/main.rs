@@ -1,11 +1,34 @@
+use crate::args::OutputPolicy;
 use crate::commands::Commands;
-use clap::Parser;
+use crate::config::Config;
+use clap::{CommandFactory, FromArgMatches, Parser};
+use std::path::PathBuf;
+use std::process::ExitCode;
 
 mod args;
+mod batch;
 mod chunk;
 mod chunk_type;
 mod commands;
+mod completions;
+mod config;
+mod crypto;
+mod detect;
+mod diff;
+mod embed;
+mod envelope;
+mod exit;
+mod exif;
+mod fec;
+mod icc;
+mod image;
 mod png;
+mod script;
+mod standard_chunk;
+mod strip;
+mod transplant;
+mod tui;
+mod xmp;
 
 pub type Error = Box<dyn std::error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
@@ -15,14 +38,46 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    #[arg(long, global = true, value_name = "FILE", help = "Read defaults from this TOML file instead of ~/.config/pngme/config.toml")]
+    pub config: Option<PathBuf>,
+
+    #[arg(long, global = true, value_enum, default_value_t = OutputPolicy::Overwrite, help = "What to do with an existing file that a command writes over")]
+    pub output_policy: OutputPolicy,
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
-    match cli.command {
-        Commands::Encode(args) => { commands::encode(args) }
+fn main() -> ExitCode {
+    completions::handle();
+    let config = match Config::load(config::path_from_args(std::env::args_os()).as_deref()) {
+        Ok(config) => config,
+        Err(error) => return exit::report(&error),
+    };
+    let matches = config.apply(Cli::command()).get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+    let policy = cli.output_policy;
+    let result = match cli.command {
+        Commands::Encode(args) => { commands::encode(args, policy) }
         Commands::Decode(args) => { commands::decode(args) }
-        Commands::Remove(args) => { commands::remove(args) },
+        Commands::Remove(args) => { commands::remove(args, policy) },
         Commands::Print(args) => { commands::print(args) }
+        Commands::Verify(args) => { commands::verify(args) }
+        Commands::Capacity(args) => { commands::capacity(args) }
+        Commands::Keygen(args) => { commands::keygen(args) }
+        Commands::Detect(args) => { commands::detect(args) }
+        Commands::Diff(args) => { commands::diff(args) }
+        Commands::ChunkType(args) => { commands::chunk_type(args) }
+        Commands::Exif(args) => { commands::exif(args, policy) }
+        Commands::Icc(args) => { commands::icc(args, policy) }
+        Commands::Strip(args) => { commands::strip(args, policy) }
+        Commands::Transplant(args) => { commands::transplant(args, policy) }
+        Commands::Xmp(args) => { commands::xmp(args, policy) }
+        Commands::Tui(args) => { commands::tui(args, policy) }
+        Commands::Script(args) => { commands::script(args, &config, policy) }
+        Commands::Completions(args) => { commands::completions(args) }
+        Commands::Man(args) => { commands::man(args) }
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => exit::report(&*error),
     }
 }
@@ -3,6 +3,7 @@ use crate::chunk;
 use crate::chunk::Chunk ;
 use crate::chunk_type::ChunkType;
 
+#[derive(Clone)]
 pub struct Png {
 	chunks: Vec<Chunk>,
 }
@@ -18,6 +19,16 @@ impl Png {
 		self.chunks.push(chunk);
 	}
 
+	/// Inserts `chunk` before the palette and image data, where chunks that
+	/// describe them (eXIf, iCCP, …) must be placed.
+	pub fn insert_before_image_data(&mut self, chunk: Chunk) {
+		let index = self.chunks
+			.iter()
+			.position(|chunk| [ChunkType::PLTE, ChunkType::IDAT, ChunkType::IEND].contains(chunk.chunk_type()))
+			.unwrap_or(self.chunks.len());
+		self.chunks.insert(index, chunk);
+	}
+
 	pub fn remove_first_chunk(&mut self, chunk_type: &ChunkType) -> Option<Chunk> {
 		self.chunks
 			.iter()
@@ -39,6 +50,35 @@ impl Png {
 			.find(|chunk| chunk.chunk_type() == chunk_type)
 	}
 
+	/// Concatenated data of all IDAT chunks, i.e. the compressed image stream.
+	pub fn image_data(&self) -> Vec<u8> {
+		self.chunks
+			.iter()
+			.filter(|chunk| chunk.chunk_type() == &ChunkType::IDAT)
+			.flat_map(|chunk| chunk.data().iter().copied())
+			.collect()
+	}
+
+	/// Replaces the IDAT chunks with `data`, split the same way the original
+	/// stream was split (into chunks the size of the first IDAT).
+	pub fn set_image_data(&mut self, data: &[u8]) {
+		let Some(first) = self.chunks.iter().position(|chunk| chunk.chunk_type() == &ChunkType::IDAT) else {
+			return;
+		};
+		let idat_count = self.chunks.iter().filter(|chunk| chunk.chunk_type() == &ChunkType::IDAT).count();
+		let split_size = match idat_count {
+			1 => data.len(),
+			_ => self.chunks[first].data().len(),
+		}.max(1);
+
+		self.chunks.retain(|chunk| chunk.chunk_type() != &ChunkType::IDAT);
+		let idats = data
+			.chunks(split_size)
+			.map(|part| Chunk::new(ChunkType::IDAT, part.to_vec()))
+			.collect::<Vec<_>>();
+		self.chunks.splice(first..first, idats);
+	}
+
 	pub fn as_bytes(&self) -> Vec<u8> {
 		self.header()
 			.iter()
@@ -58,8 +98,11 @@ pub enum ReadError {
 	Io(#[from] Error),
 }
 
+// A chunk's offset in the file and its bytes.
+type ChunkSlice<'a> = (usize, &'a [u8]);
+
 impl Png {
-	fn read(value: &[u8]) -> Result<([u8; Self::STANDARD_HEADER.len()], Vec<&[u8]>), ReadError> {
+	fn read(value: &[u8]) -> Result<([u8; Self::STANDARD_HEADER.len()], Vec<ChunkSlice<'_>>), ReadError> {
 		let mut cursor = Cursor::new(value);
 
 		let header_len = Self::STANDARD_HEADER.len();
@@ -72,7 +115,7 @@ impl Png {
 		}
 		cursor.read_exact(&mut header)?;
 
-		let mut chunk_slices: Vec<&[u8]> = Vec::new();
+		let mut chunk_slices: Vec<ChunkSlice> = Vec::new();
 		while cursor.position() < value.len() as u64 {
 			let start = cursor.stream_position()? as usize;
 
@@ -93,7 +136,7 @@ impl Png {
 					)
 				)));
 			}
-			chunk_slices.push(&value[start..end]);
+			chunk_slices.push((start, &value[start..end]));
 
 			cursor.seek(SeekFrom::Start(end as u64))?;
 		}
@@ -106,19 +149,23 @@ pub enum ValidationError {
 	#[error("invalid PNG signature")]
 	InvalidSignature,
 
-	#[error("error processing chunks")]
-	Chunk(#[from] chunk::ChunkError),
+	#[error("chunk {index} at offset {offset}: {source}")]
+	Chunk {
+		index: usize,
+		offset: usize,
+		source: chunk::ChunkError,
+	},
 }
 
 impl Png {
-	fn validate(header: &[u8; Self::STANDARD_HEADER.len()], chunk_slices: Vec<&[u8]>) -> Result<Png, ValidationError> {
+	fn validate(header: &[u8; Self::STANDARD_HEADER.len()], chunk_slices: Vec<ChunkSlice>) -> Result<Png, ValidationError> {
 		if header != &Self::STANDARD_HEADER {
 			return Err(ValidationError::InvalidSignature);
 		}
 
 		let mut chunks = vec!();
-		for slice in chunk_slices {
-			chunks.push(Chunk::try_from(slice)?);
+		for (index, (offset, slice)) in chunk_slices.into_iter().enumerate() {
+			chunks.push(Chunk::try_from(slice).map_err(|source| ValidationError::Chunk { index, offset, source })?);
 		}
 
 		Ok(Png {chunks})
@@ -144,6 +191,29 @@ impl TryFrom<&[u8]> for Png {
 	}
 }
 
+impl Png {
+	/// Parses a PNG whose chunks may have bad CRCs, returning the indices of
+	/// the damaged chunks alongside it.
+	pub fn try_from_damaged(value: &[u8]) -> Result<(Png, Vec<usize>), PngError> {
+		let (header, chunk_slices) = Self::read(value)?;
+		if header != Self::STANDARD_HEADER {
+			return Err(ValidationError::InvalidSignature.into());
+		}
+
+		let mut chunks = vec!();
+		let mut damaged = vec!();
+		for (index, (offset, slice)) in chunk_slices.into_iter().enumerate() {
+			let (chunk, crc_ok) = Chunk::try_from_damaged(slice).map_err(|source| ValidationError::Chunk { index, offset, source })?;
+			if !crc_ok {
+				damaged.push(index);
+			}
+			chunks.push(chunk);
+		}
+
+		Ok((Png {chunks}, damaged))
+	}
+}
+
 impl std::fmt::Display for Png {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		self.chunks.iter().try_for_each(|chunk| chunk.chunk_type().fmt(f))?;
@@ -249,6 +319,19 @@ mod tests {
 		assert!(png.is_err());
 	}
 
+	#[test]
+	fn test_damaged_crc_is_reported() {
+		let mut bytes = testing_png().as_bytes();
+		// last byte of the final chunk's CRC
+		*bytes.last_mut().unwrap() ^= 1;
+		let error = Png::try_from(bytes.as_slice()).err().unwrap();
+		assert!(matches!(error, PngError::Validation(ValidationError::Chunk { index: 2, offset: 70, .. })));
+
+		let (png, damaged) = Png::try_from_damaged(&bytes).unwrap();
+		assert_eq!(damaged, vec![2]);
+		assert_eq!(png.chunks().len(), 3);
+	}
+
 
 	#[test]
 	fn test_list_chunks() {
@@ -0,0 +1,383 @@
+use std::str::FromStr;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use crate::args::{EmbedOptions, StripOptions};
+use flate2::Compression;
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::commands::{self, CommandError};
+use crate::config::Config;
+use crate::embed::{self, ScatterKey, Target};
+use crate::png::Png;
+use crate::standard_chunk;
+use crate::strip;
+use crate::xmp::Property;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ScriptError {
+	#[error("line {line}: unterminated quote")]
+	Quote { line: usize },
+
+	#[error("line {line}: {message}")]
+	Syntax { line: usize, message: String },
+
+	#[error("line {line}: `{step}` failed: {source}")]
+	Step {
+		line: usize,
+		step: String,
+		source: crate::Error,
+	},
+
+	#[error("no text chunk with keyword {0}")]
+	NoText(String),
+
+	#[error("tEXt chunks only hold Latin-1 text; use `xmp set` for other characters")]
+	NotLatin1,
+
+	#[error("the script has no steps")]
+	Empty,
+}
+
+#[derive(Parser)]
+#[command(name = "script", no_binary_name = true, disable_help_subcommand = true)]
+struct Line {
+	#[command(subcommand)]
+	op: Op,
+}
+
+/// One step of a script; each mirrors the command of the same name.
+#[derive(Subcommand)]
+pub enum Op {
+	/// Remove the first chunk of a type
+	Remove {
+		#[arg(value_parser = ChunkType::from_str, help = "Chunk type (4 ASCII letters)")]
+		chunk_type: ChunkType,
+	},
+
+	/// Embed a message
+	#[command(allow_missing_positional = true)]
+	Encode(EmbedOptions),
+
+	/// Set or delete text chunks
+	#[command(subcommand)]
+	Text(TextStep),
+
+	/// Remove metadata chunks
+	Strip(StripOptions),
+
+	/// Edit Exif metadata
+	#[command(subcommand)]
+	Exif(ExifStep),
+
+	/// Edit XMP metadata
+	#[command(subcommand)]
+	Xmp(XmpStep),
+}
+
+#[derive(Subcommand)]
+pub enum TextStep {
+	/// Replace the text chunks with a keyword by one tEXt chunk
+	Set {
+		#[arg(help = "Keyword, e.g. Author")]
+		keyword: String,
+
+		#[arg(help = "Latin-1 text")]
+		text: String,
+	},
+
+	/// Remove the text chunks with a keyword
+	Delete {
+		#[arg(help = "Keyword, e.g. Author")]
+		keyword: String,
+	},
+}
+
+#[derive(Subcommand)]
+pub enum ExifStep {
+	/// Set a tag
+	Set {
+		#[arg(help = "Tag name, e.g. Artist")]
+		tag: String,
+
+		#[arg(help = "Text, or comma-separated numbers for numeric tags")]
+		value: String,
+	},
+
+	/// Delete tags
+	Delete {
+		#[arg(required = true, help = "Tag names")]
+		tags: Vec<String>,
+	},
+
+	/// Delete the GPS tags
+	StripGps,
+}
+
+#[derive(Subcommand)]
+pub enum XmpStep {
+	/// Set a property
+	Set {
+		#[arg(short, long = "namespace", value_name = "PREFIX=URI", value_parser = crate::args::parse_namespace, help = "Declare a custom namespace prefix (repeatable)")]
+		namespaces: Vec<(String, String)>,
+
+		#[arg(help = "Property as prefix:name, or title, creator, rights, description, subject")]
+		property: String,
+
+		#[arg(required = true, help = "Value; creator and subject take several")]
+		values: Vec<String>,
+	},
+}
+
+/// A parsed step and where it came from.
+pub struct Step {
+	pub line: usize,
+	pub text: String,
+	pub op: Op,
+}
+
+// Splits a script into the words of each step, with the line each step starts
+// on. Steps end at newlines and semicolons; words may be quoted as in a shell,
+// and `#` starts a comment.
+fn split(script: &str) -> Result<Vec<(usize, Vec<String>)>, ScriptError> {
+	let mut steps = Vec::new();
+	let (mut words, mut word) = (Vec::new(), None::<String>);
+	let (mut line, mut start) = (1, 1);
+	let mut chars = script.chars().peekable();
+	while let Some(c) = chars.next() {
+		match c {
+			'\n' | ';' => {
+				words.extend(word.take());
+				if !words.is_empty() {
+					steps.push((start, std::mem::take(&mut words)));
+				}
+				line += (c == '\n') as usize;
+				start = line;
+			}
+			'#' if word.is_none() => {
+				while chars.next_if(|&c| c != '\n').is_some() {}
+			}
+			c if c.is_whitespace() => words.extend(word.take()),
+			'\'' | '"' => {
+				let word = word.get_or_insert_with(String::new);
+				loop {
+					match chars.next() {
+						Some(end) if end == c => break,
+						Some('\\') if c == '"' && matches!(chars.peek(), Some('"' | '\\')) => word.push(chars.next().unwrap()),
+						Some(inner) => {
+							line += (inner == '\n') as usize;
+							word.push(inner);
+						}
+						None => return Err(ScriptError::Quote { line: start }),
+					}
+				}
+			}
+			'\\' => {
+				let escaped = chars.next().unwrap_or('\\');
+				word.get_or_insert_with(String::new).push(escaped);
+			}
+			c => word.get_or_insert_with(String::new).push(c),
+		}
+	}
+	words.extend(word);
+	if !words.is_empty() {
+		steps.push((start, words));
+	}
+	Ok(steps)
+}
+
+// The gist of a clap error, without the usage and tips.
+fn syntax_message(error: &clap::Error) -> String {
+	let rendered = error.to_string();
+	let gist = rendered.split("\n\n").next().unwrap_or_default();
+	gist.trim_start_matches("error: ").split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Parses a whole script before any step runs, so that a typo in the last
+/// step is reported before the file is touched. Steps take their defaults from
+/// `config` like the commands do.
+pub fn parse(script: &str, config: &Config) -> Result<Vec<Step>, ScriptError> {
+	let command = config.apply(Line::command());
+	let steps: Vec<Step> = split(script)?
+		.into_iter()
+		.map(|(line, words)| {
+			let parsed = command.clone().try_get_matches_from(&words).and_then(|matches| Line::from_arg_matches(&matches));
+			match parsed {
+				Ok(parsed) => Ok(Step { line, text: words.join(" "), op: parsed.op }),
+				Err(error) => Err(ScriptError::Syntax { line, message: syntax_message(&error) }),
+			}
+		})
+		.collect::<Result<_, _>>()?;
+	if steps.is_empty() {
+		return Err(ScriptError::Empty);
+	}
+	Ok(steps)
+}
+
+fn latin1(text: &str) -> Result<Vec<u8>, ScriptError> {
+	text.chars().map(|c| u8::try_from(c).map_err(|_| ScriptError::NotLatin1)).collect()
+}
+
+impl Op {
+	/// Applies the step to `png`, describing what it did.
+	fn apply(self, png: &mut Png) -> crate::Result<String> {
+		Ok(match self {
+			Op::Remove { chunk_type } => {
+				let chunk = png.remove_first_chunk(&chunk_type).ok_or(CommandError::NoChunk(chunk_type))?;
+				format!("removed {}, {} bytes", chunk.chunk_type(), chunk.as_bytes().len())
+			}
+			Op::Encode(step) => {
+				if let Some(chunk_type) = &step.chunk_type {
+					if let Some(warning) = commands::check_message_type(chunk_type, step.force)? {
+						eprintln!("Warning: {warning}");
+					}
+				}
+				let key = step.key.as_deref().map(ScatterKey::from_passphrase);
+				let target = Target::new(step.method, step.bits, step.alpha_lsb, step.chunk_type, key)?;
+				let recipients = if step.plain { &[][..] } else { &step.recipients };
+				let data = commands::payload(&step.message, recipients, step.ecc)?;
+				embed::embed(png, &target, &data, Compression::new(step.compression))?;
+				format!("embedded {} bytes with the {} method", data.len(), step.method)
+			}
+			Op::Text(TextStep::Set { keyword, text }) => {
+				let keyword = standard_chunk::keyword(&ChunkType::TEXT, &latin1(&keyword)?)?;
+				let data = [latin1(&keyword)?, vec![0], latin1(&text)?].concat();
+				let chunk = Chunk::new(ChunkType::TEXT, data);
+				// the first chunk with the keyword keeps its place
+				let mut chunks = Vec::new();
+				let mut replaced = false;
+				for existing in png.chunks() {
+					if strip::text_keyword(existing).as_ref() != Some(&keyword) {
+						chunks.push(existing.clone());
+					} else if !replaced {
+						chunks.push(chunk.clone());
+						replaced = true;
+					}
+				}
+				*png = Png::from_chunks(chunks);
+				if !replaced {
+					png.insert_before_image_data(chunk);
+				}
+				format!("set text {keyword}")
+			}
+			Op::Text(TextStep::Delete { keyword }) => {
+				let before = png.chunks().len();
+				let kept = png.chunks().iter().filter(|chunk| strip::text_keyword(chunk).as_ref() != Some(&keyword));
+				*png = Png::from_chunks(kept.cloned().collect());
+				match before - png.chunks().len() {
+					0 => return Err(ScriptError::NoText(keyword).into()),
+					removed => format!("deleted {removed} text chunks with keyword {keyword}"),
+				}
+			}
+			Op::Strip(step) => {
+				let removed = step.policy().apply(png);
+				let saved: usize = removed.iter().map(|chunk| chunk.as_bytes().len()).sum();
+				let still = match strip::removes_animation(&removed) {
+					true => ", leaving only the default image of the animation",
+					false => "",
+				};
+				format!("removed {} chunks, saved {saved} bytes{still}", removed.len())
+			}
+			Op::Exif(step) => {
+				let mut exif = commands::read_exif(png)?;
+				let output = match step {
+					ExifStep::Set { tag, value } => {
+						let info = commands::tag_info(&tag)?;
+						exif.set(info, &value)?;
+						format!("set {}", info.name)
+					}
+					ExifStep::Delete { tags } => {
+						let mut deleted = 0;
+						for name in &tags {
+							deleted += exif.delete(commands::tag_info(name)?) as usize;
+						}
+						format!("deleted {deleted} of {} Exif tags", tags.len())
+					}
+					ExifStep::StripGps => format!("removed {} GPS tags", exif.strip_gps()),
+				};
+				commands::replace_exif(png, &exif);
+				output
+			}
+			Op::Xmp(XmpStep::Set { namespaces, property, values }) => {
+				let property = Property::parse(&property, &namespaces)?;
+				let mut xmp = commands::read_xmp(png)?;
+				xmp.set(&property, &values);
+				commands::replace_xmp(png, &xmp);
+				format!("set {}:{}", property.prefix, property.name)
+			}
+		})
+	}
+}
+
+/// Applies `steps` to `png` in order, returning a line per step. Stops at the
+/// first step that fails.
+pub fn run(png: &mut Png, steps: Vec<Step>) -> Result<String, ScriptError> {
+	let mut output = String::new();
+	for Step { line, text, op } in steps {
+		match op.apply(png) {
+			Ok(done) => output.push_str(&format!("line {line}: {done}\n")),
+			Err(source) => return Err(ScriptError::Step { line, step: text, source }),
+		}
+	}
+	Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+
+	fn testing_png() -> Png {
+		let chunk = |chunk_type: &str, data: &[u8]| Chunk::new(chunk_type.parse().unwrap(), data.to_vec());
+		Png::from_chunks(vec![
+			chunk("IHDR", &[0; 13]),
+			chunk("tEXt", b"Author\0Jane Doe"),
+			chunk("tEXt", b"Comment\0draft"),
+			chunk("prIv", b"private"),
+			chunk("IDAT", &[0; 8]),
+			chunk("IEND", &[]),
+		])
+	}
+
+	fn chunk_types(png: &Png) -> Vec<String> {
+		png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+	}
+
+	#[test]
+	fn test_split() {
+		let script = "remove tEXt; text set Author 'Jane; Doe'\n# a comment\n\nencode ruSt \"say \\\"hi\\\"\" # trailing\n";
+		let steps = split(script).unwrap();
+		let words = |words: &[&str]| words.iter().map(|word| word.to_string()).collect::<Vec<_>>();
+		assert_eq!(
+			steps,
+			[
+				(1, words(&["remove", "tEXt"])),
+				(1, words(&["text", "set", "Author", "Jane; Doe"])),
+				(4, words(&["encode", "ruSt", "say \"hi\""])),
+			]
+		);
+		assert!(matches!(split("text set A 'open\n"), Err(ScriptError::Quote { line: 1 })));
+	}
+
+	#[test]
+	fn test_parse_reports_the_line() {
+		let error = parse("remove tEXt\nfrobnicate", &Config::default()).err().unwrap();
+		assert_eq!(error.to_string(), "line 2: unrecognized subcommand 'frobnicate'");
+		assert!(matches!(parse("# nothing\n", &Config::default()), Err(ScriptError::Empty)));
+	}
+
+	#[test]
+	fn test_run() {
+		let mut png = testing_png();
+		let steps = parse("strip --private-only; remove tEXt; text set Comment final\nencode ruSt hello", &Config::default()).unwrap();
+		let output = run(&mut png, steps).unwrap();
+		assert_eq!(chunk_types(&png), ["IHDR", "tEXt", "IDAT", "IEND", "ruSt"]);
+		assert_eq!(png.chunks()[1].data(), b"Comment\0final");
+		assert_eq!(output.lines().last(), Some("line 2: embedded 5 bytes with the chunk method"));
+	}
+
+	#[test]
+	fn test_failed_step() {
+		let mut png = testing_png();
+		let error = run(&mut png, parse("text delete Author\ntext delete Author", &Config::default()).unwrap()).err().unwrap();
+		assert_eq!(error.to_string(), "line 2: `text delete Author` failed: no text chunk with keyword Author");
+	}
+}
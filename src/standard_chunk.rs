@@ -0,0 +1,658 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::image::{ColorType, ImageHeader};
+
+/// How a colour management system should map colours outside the gamut.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderingIntent {
+	Perceptual,
+	RelativeColorimetric,
+	Saturation,
+	AbsoluteColorimetric,
+}
+
+/// A chromaticity in units of 1/100000 (cHRM) or 1/50000 (mDCv).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chromaticity<T> {
+	pub x: T,
+	pub y: T,
+}
+
+/// A colour given in the image's own sample format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sample {
+	PaletteIndex(u8),
+	Grey(u16),
+	Rgb(u16, u16, u16),
+}
+
+/// Alpha values of tRNS: one per palette entry, or a single colour that is
+/// fully transparent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Transparency {
+	Palette(Vec<u8>),
+	Colour(Sample),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaletteEntry {
+	pub red: u16,
+	pub green: u16,
+	pub blue: u16,
+	pub alpha: u16,
+	pub frequency: u16,
+}
+
+/// Decoded form of every standard ancillary chunk pngme understands.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StandardChunk {
+	/// Image gamma times 100000.
+	Gamma(u32),
+	Chromaticities {
+		white: Chromaticity<u32>,
+		red: Chromaticity<u32>,
+		green: Chromaticity<u32>,
+		blue: Chromaticity<u32>,
+	},
+	Srgb(RenderingIntent),
+	IccProfile {
+		name: String,
+		/// zlib-compressed profile
+		profile: Vec<u8>,
+	},
+	SignificantBits(Vec<u8>),
+	Background(Sample),
+	Histogram(Vec<u16>),
+	Transparency(Transparency),
+	PhysicalDimensions {
+		x: u32,
+		y: u32,
+		/// Pixels per metre if true, only the aspect ratio otherwise.
+		metre: bool,
+	},
+	SuggestedPalette {
+		name: String,
+		depth: u8,
+		entries: Vec<PaletteEntry>,
+	},
+	Time {
+		year: u16,
+		month: u8,
+		day: u8,
+		hour: u8,
+		minute: u8,
+		second: u8,
+	},
+	Exif(Vec<u8>),
+	CodingIndependentCodePoints {
+		colour_primaries: u8,
+		transfer_function: u8,
+		matrix_coefficients: u8,
+		full_range: bool,
+	},
+	MasteringDisplay {
+		primaries: [Chromaticity<u16>; 3],
+		white: Chromaticity<u16>,
+		/// cd/m² times 10000
+		max_luminance: u32,
+		min_luminance: u32,
+	},
+	ContentLightLevel {
+		/// cd/m² times 10000
+		max_content: u32,
+		max_frame_average: u32,
+	},
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum StandardChunkError {
+	#[error("{chunk_type} chunk has an invalid length of {actual} bytes")]
+	Length {
+		chunk_type: ChunkType,
+		actual: usize,
+	},
+
+	#[error("{chunk_type} {field} is out of range ({value})")]
+	Field {
+		chunk_type: ChunkType,
+		field: &'static str,
+		value: u64,
+	},
+
+	#[error("{0} keyword must be 1-79 printable Latin-1 characters without extra spaces")]
+	Keyword(ChunkType),
+
+	#[error("{0} chunk is not allowed for {1} images")]
+	ColorType(ChunkType, ColorType),
+}
+
+/// Checks a chunk keyword (iCCP, sPLT, text chunks) and converts it from Latin-1.
+pub fn keyword(chunk_type: &ChunkType, bytes: &[u8]) -> Result<String, StandardChunkError> {
+	let printable = bytes.iter().all(|&byte| (32..=126).contains(&byte) || byte >= 161);
+	let spaced = bytes.first() == Some(&b' ') || bytes.last() == Some(&b' ') || bytes.windows(2).any(|pair| pair == b"  ");
+	if bytes.is_empty() || bytes.len() > 79 || !printable || spaced {
+		return Err(StandardChunkError::Keyword(chunk_type.clone()));
+	}
+	Ok(bytes.iter().map(|&byte| byte as char).collect())
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+	u16::from_be_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+	u32::from_be_bytes(data[offset..offset + 4].try_into().expect("four bytes"))
+}
+
+// Splits `name\0rest` as used by iCCP and sPLT.
+fn split_keyword<'a>(chunk_type: &ChunkType, data: &'a [u8]) -> Result<(String, &'a [u8]), StandardChunkError> {
+	let end = data.iter().position(|&byte| byte == 0).ok_or(StandardChunkError::Keyword(chunk_type.clone()))?;
+	Ok((keyword(chunk_type, &data[..end])?, &data[end + 1..]))
+}
+
+fn sample(data: &[u8], color_type: ColorType) -> Option<Sample> {
+	match (color_type, data.len()) {
+		(ColorType::Indexed, 1) => Some(Sample::PaletteIndex(data[0])),
+		(ColorType::Grayscale | ColorType::GrayscaleAlpha, 2) => Some(Sample::Grey(u16_at(data, 0))),
+		(ColorType::Rgb | ColorType::Rgba, 6) => Some(Sample::Rgb(u16_at(data, 0), u16_at(data, 2), u16_at(data, 4))),
+		_ => None,
+	}
+}
+
+impl StandardChunk {
+	fn read(chunk: &Chunk, color_type: ColorType) -> Result<Option<StandardChunk>, StandardChunkError> {
+		let chunk_type = chunk.chunk_type().clone();
+		let data = chunk.data();
+		let length_error = || StandardChunkError::Length { chunk_type: chunk_type.clone(), actual: data.len() };
+		let expect_len = |expected: usize| if data.len() == expected { Ok(()) } else { Err(length_error()) };
+
+		let standard = match chunk_type {
+			ChunkType::GAMA => {
+				expect_len(4)?;
+				StandardChunk::Gamma(u32_at(data, 0))
+			}
+			ChunkType::CHRM => {
+				expect_len(32)?;
+				let point = |index: usize| Chromaticity { x: u32_at(data, index * 8), y: u32_at(data, index * 8 + 4) };
+				StandardChunk::Chromaticities { white: point(0), red: point(1), green: point(2), blue: point(3) }
+			}
+			ChunkType::SRGB => {
+				expect_len(1)?;
+				let intent = match data[0] {
+					0 => RenderingIntent::Perceptual,
+					1 => RenderingIntent::RelativeColorimetric,
+					2 => RenderingIntent::Saturation,
+					3 => RenderingIntent::AbsoluteColorimetric,
+					value => return Err(StandardChunkError::Field { chunk_type, field: "rendering intent", value: value as u64 }),
+				};
+				StandardChunk::Srgb(intent)
+			}
+			ChunkType::ICCP => {
+				let (name, rest) = split_keyword(&chunk_type, data)?;
+				let (&method, profile) = rest.split_first().ok_or_else(length_error)?;
+				if method != 0 {
+					return Err(StandardChunkError::Field { chunk_type, field: "compression method", value: method as u64 });
+				}
+				StandardChunk::IccProfile { name, profile: profile.to_vec() }
+			}
+			ChunkType::SBIT => {
+				let expected = match color_type {
+					ColorType::Grayscale => 1,
+					ColorType::GrayscaleAlpha => 2,
+					ColorType::Rgb | ColorType::Indexed => 3,
+					ColorType::Rgba => 4,
+				};
+				expect_len(expected)?;
+				StandardChunk::SignificantBits(data.to_vec())
+			}
+			ChunkType::BKGD => StandardChunk::Background(sample(data, color_type).ok_or_else(length_error)?),
+			ChunkType::HIST => {
+				if !data.len().is_multiple_of(2) {
+					return Err(length_error());
+				}
+				StandardChunk::Histogram(data.chunks_exact(2).map(|pair| u16_at(pair, 0)).collect())
+			}
+			ChunkType::TRNS => StandardChunk::Transparency(match color_type {
+				ColorType::Indexed => Transparency::Palette(data.to_vec()),
+				ColorType::GrayscaleAlpha | ColorType::Rgba => {
+					return Err(StandardChunkError::ColorType(chunk_type, color_type));
+				}
+				_ => Transparency::Colour(sample(data, color_type).ok_or_else(length_error)?),
+			}),
+			ChunkType::PHYS => {
+				expect_len(9)?;
+				let metre = match data[8] {
+					0 => false,
+					1 => true,
+					value => return Err(StandardChunkError::Field { chunk_type, field: "unit", value: value as u64 }),
+				};
+				StandardChunk::PhysicalDimensions { x: u32_at(data, 0), y: u32_at(data, 4), metre }
+			}
+			ChunkType::SPLT => {
+				let (name, rest) = split_keyword(&chunk_type, data)?;
+				let (&depth, entries) = rest.split_first().ok_or_else(length_error)?;
+				let entry_len = match depth {
+					8 => 6,
+					16 => 10,
+					value => return Err(StandardChunkError::Field { chunk_type, field: "sample depth", value: value as u64 }),
+				};
+				if !entries.len().is_multiple_of(entry_len) {
+					return Err(length_error());
+				}
+				let entries = entries
+					.chunks_exact(entry_len)
+					.map(|entry| match depth {
+						8 => PaletteEntry {
+							red: entry[0] as u16,
+							green: entry[1] as u16,
+							blue: entry[2] as u16,
+							alpha: entry[3] as u16,
+							frequency: u16_at(entry, 4),
+						},
+						_ => PaletteEntry {
+							red: u16_at(entry, 0),
+							green: u16_at(entry, 2),
+							blue: u16_at(entry, 4),
+							alpha: u16_at(entry, 6),
+							frequency: u16_at(entry, 8),
+						},
+					})
+					.collect();
+				StandardChunk::SuggestedPalette { name, depth, entries }
+			}
+			ChunkType::TIME => {
+				expect_len(7)?;
+				StandardChunk::Time {
+					year: u16_at(data, 0),
+					month: data[2],
+					day: data[3],
+					hour: data[4],
+					minute: data[5],
+					second: data[6],
+				}
+			}
+			ChunkType::EXIF => StandardChunk::Exif(data.to_vec()),
+			ChunkType::CICP => {
+				expect_len(4)?;
+				let full_range = match data[3] {
+					0 => false,
+					1 => true,
+					value => return Err(StandardChunkError::Field { chunk_type, field: "full range flag", value: value as u64 }),
+				};
+				StandardChunk::CodingIndependentCodePoints {
+					colour_primaries: data[0],
+					transfer_function: data[1],
+					matrix_coefficients: data[2],
+					full_range,
+				}
+			}
+			ChunkType::MDCV => {
+				expect_len(24)?;
+				let point = |index: usize| Chromaticity { x: u16_at(data, index * 4), y: u16_at(data, index * 4 + 2) };
+				StandardChunk::MasteringDisplay {
+					primaries: [point(0), point(1), point(2)],
+					white: point(3),
+					max_luminance: u32_at(data, 16),
+					min_luminance: u32_at(data, 20),
+				}
+			}
+			ChunkType::CLLI => {
+				expect_len(8)?;
+				StandardChunk::ContentLightLevel { max_content: u32_at(data, 0), max_frame_average: u32_at(data, 4) }
+			}
+			_ => return Ok(None),
+		};
+		Ok(Some(standard))
+	}
+
+	fn validate(self, header: &ImageHeader) -> Result<StandardChunk, StandardChunkError> {
+		let chunk_type = self.chunk_type();
+		let field = |field: &'static str, value: u64| StandardChunkError::Field { chunk_type: chunk_type.clone(), field, value };
+		let sample_depth = match header.color_type {
+			ColorType::Indexed => 8,
+			_ => header.bit_depth,
+		};
+		let sample_max = ((1u32 << header.bit_depth) - 1) as u16;
+
+		match &self {
+			StandardChunk::Gamma(0) => return Err(field("gamma", 0)),
+			StandardChunk::SignificantBits(bits) => {
+				if let Some(&bits) = bits.iter().find(|&&bits| bits == 0 || bits > sample_depth) {
+					return Err(field("significant bits", bits as u64));
+				}
+			}
+			StandardChunk::Background(sample) | StandardChunk::Transparency(Transparency::Colour(sample)) => {
+				let values = match sample {
+					Sample::PaletteIndex(_) => vec![],
+					Sample::Grey(grey) => vec![*grey],
+					Sample::Rgb(red, green, blue) => vec![*red, *green, *blue],
+				};
+				if let Some(&value) = values.iter().find(|&&value| value > sample_max) {
+					return Err(field("sample", value as u64));
+				}
+			}
+			StandardChunk::Transparency(Transparency::Palette(alphas)) if alphas.len() > 256 => {
+				return Err(StandardChunkError::Length { chunk_type, actual: alphas.len() });
+			}
+			StandardChunk::Histogram(frequencies) if frequencies.is_empty() || frequencies.len() > 256 => {
+				return Err(StandardChunkError::Length { chunk_type, actual: frequencies.len() * 2 });
+			}
+			StandardChunk::Time { month, day, hour, minute, second, .. } => {
+				let checks = [("month", *month, 1..=12), ("day", *day, 1..=31), ("hour", *hour, 0..=23), ("minute", *minute, 0..=59), ("second", *second, 0..=60)];
+				if let Some((name, value, _)) = checks.into_iter().find(|(_, value, range)| !range.contains(value)) {
+					return Err(field(name, value as u64));
+				}
+			}
+			StandardChunk::Exif(data) if !data.starts_with(b"MM\0*") && !data.starts_with(b"II*\0") => {
+				return Err(field("byte order mark", data.iter().take(4).fold(0, |acc, &byte| acc << 8 | byte as u64)));
+			}
+			// PNG only holds RGB data, which has no matrix
+			StandardChunk::CodingIndependentCodePoints { matrix_coefficients, .. } if *matrix_coefficients != 0 => {
+				return Err(field("matrix coefficients", *matrix_coefficients as u64));
+			}
+			_ => {}
+		}
+		Ok(self)
+	}
+
+	/// Decodes `chunk` if it is one of the standard ancillary chunks, using
+	/// the image header for chunks whose layout depends on the colour type.
+	pub fn parse(chunk: &Chunk, header: &ImageHeader) -> Result<Option<StandardChunk>, StandardChunkError> {
+		match StandardChunk::read(chunk, header.color_type)? {
+			Some(standard) => Ok(Some(standard.validate(header)?)),
+			None => Ok(None),
+		}
+	}
+
+	pub fn chunk_type(&self) -> ChunkType {
+		match self {
+			StandardChunk::Gamma(_) => ChunkType::GAMA,
+			StandardChunk::Chromaticities { .. } => ChunkType::CHRM,
+			StandardChunk::Srgb(_) => ChunkType::SRGB,
+			StandardChunk::IccProfile { .. } => ChunkType::ICCP,
+			StandardChunk::SignificantBits(_) => ChunkType::SBIT,
+			StandardChunk::Background(_) => ChunkType::BKGD,
+			StandardChunk::Histogram(_) => ChunkType::HIST,
+			StandardChunk::Transparency(_) => ChunkType::TRNS,
+			StandardChunk::PhysicalDimensions { .. } => ChunkType::PHYS,
+			StandardChunk::SuggestedPalette { .. } => ChunkType::SPLT,
+			StandardChunk::Time { .. } => ChunkType::TIME,
+			StandardChunk::Exif(_) => ChunkType::EXIF,
+			StandardChunk::CodingIndependentCodePoints { .. } => ChunkType::CICP,
+			StandardChunk::MasteringDisplay { .. } => ChunkType::MDCV,
+			StandardChunk::ContentLightLevel { .. } => ChunkType::CLLI,
+		}
+	}
+}
+
+fn sample_bytes(sample: &Sample) -> Vec<u8> {
+	match sample {
+		Sample::PaletteIndex(index) => vec![*index],
+		Sample::Grey(grey) => grey.to_be_bytes().to_vec(),
+		Sample::Rgb(red, green, blue) => [red, green, blue].iter().flat_map(|value| value.to_be_bytes()).collect(),
+	}
+}
+
+fn latin1(text: &str) -> impl Iterator<Item = u8> + '_ {
+	text.chars().map(|c| c as u32 as u8)
+}
+
+impl StandardChunk {
+	/// Serializes the chunk data.
+	pub fn data(&self) -> Vec<u8> {
+		match self {
+			StandardChunk::Gamma(gamma) => gamma.to_be_bytes().to_vec(),
+			StandardChunk::Chromaticities { white, red, green, blue } => [white, red, green, blue]
+				.iter()
+				.flat_map(|point| point.x.to_be_bytes().into_iter().chain(point.y.to_be_bytes()))
+				.collect(),
+			StandardChunk::Srgb(intent) => vec![*intent as u8],
+			StandardChunk::IccProfile { name, profile } => {
+				latin1(name).chain([0, 0]).chain(profile.iter().copied()).collect()
+			}
+			StandardChunk::SignificantBits(bits) => bits.clone(),
+			StandardChunk::Background(sample) => sample_bytes(sample),
+			StandardChunk::Histogram(frequencies) => frequencies.iter().flat_map(|value| value.to_be_bytes()).collect(),
+			StandardChunk::Transparency(Transparency::Palette(alphas)) => alphas.clone(),
+			StandardChunk::Transparency(Transparency::Colour(sample)) => sample_bytes(sample),
+			StandardChunk::PhysicalDimensions { x, y, metre } => {
+				x.to_be_bytes().into_iter().chain(y.to_be_bytes()).chain([*metre as u8]).collect()
+			}
+			StandardChunk::SuggestedPalette { name, depth, entries } => {
+				let mut data: Vec<u8> = latin1(name).chain([0, *depth]).collect();
+				for entry in entries {
+					let samples = [entry.red, entry.green, entry.blue, entry.alpha];
+					match depth {
+						8 => data.extend(samples.iter().map(|&value| value as u8)),
+						_ => data.extend(samples.iter().flat_map(|value| value.to_be_bytes())),
+					}
+					data.extend(entry.frequency.to_be_bytes());
+				}
+				data
+			}
+			StandardChunk::Time { year, month, day, hour, minute, second } => {
+				year.to_be_bytes().into_iter().chain([*month, *day, *hour, *minute, *second]).collect()
+			}
+			StandardChunk::Exif(data) => data.clone(),
+			StandardChunk::CodingIndependentCodePoints { colour_primaries, transfer_function, matrix_coefficients, full_range } => {
+				vec![*colour_primaries, *transfer_function, *matrix_coefficients, *full_range as u8]
+			}
+			StandardChunk::MasteringDisplay { primaries, white, max_luminance, min_luminance } => primaries
+				.iter()
+				.chain([white])
+				.flat_map(|point| point.x.to_be_bytes().into_iter().chain(point.y.to_be_bytes()))
+				.chain(max_luminance.to_be_bytes())
+				.chain(min_luminance.to_be_bytes())
+				.collect(),
+			StandardChunk::ContentLightLevel { max_content, max_frame_average } => {
+				max_content.to_be_bytes().into_iter().chain(max_frame_average.to_be_bytes()).collect()
+			}
+		}
+	}
+
+	pub fn to_chunk(&self) -> Chunk {
+		Chunk::new(self.chunk_type(), self.data())
+	}
+}
+
+impl std::fmt::Display for RenderingIntent {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let name = match self {
+			RenderingIntent::Perceptual => "perceptual",
+			RenderingIntent::RelativeColorimetric => "relative colorimetric",
+			RenderingIntent::Saturation => "saturation",
+			RenderingIntent::AbsoluteColorimetric => "absolute colorimetric",
+		};
+		write!(f, "{name}")
+	}
+}
+
+impl std::fmt::Display for Sample {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			Sample::PaletteIndex(index) => write!(f, "palette index {index}"),
+			Sample::Grey(grey) => write!(f, "grey {grey}"),
+			Sample::Rgb(red, green, blue) => write!(f, "rgb({red}, {green}, {blue})"),
+		}
+	}
+}
+
+impl std::fmt::Display for StandardChunk {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let point = |point: &Chromaticity<u32>| format!("({:.5}, {:.5})", point.x as f64 / 1e5, point.y as f64 / 1e5);
+		match self {
+			StandardChunk::Gamma(gamma) => write!(f, "gamma {:.5}", *gamma as f64 / 1e5),
+			StandardChunk::Chromaticities { white, red, green, blue } => write!(
+				f,
+				"white point {}, red {}, green {}, blue {}",
+				point(white),
+				point(red),
+				point(green),
+				point(blue),
+			),
+			StandardChunk::Srgb(intent) => write!(f, "sRGB, {intent} rendering intent"),
+			StandardChunk::IccProfile { name, profile } => {
+				write!(f, "ICC profile \"{name}\", {} bytes compressed", profile.len())
+			}
+			StandardChunk::SignificantBits(bits) => {
+				let bits: Vec<String> = bits.iter().map(u8::to_string).collect();
+				write!(f, "significant bits {}", bits.join(", "))
+			}
+			StandardChunk::Background(sample) => write!(f, "background {sample}"),
+			StandardChunk::Histogram(frequencies) => write!(f, "histogram of {} palette entries", frequencies.len()),
+			StandardChunk::Transparency(Transparency::Palette(alphas)) => {
+				write!(f, "alpha for {} palette entries", alphas.len())
+			}
+			StandardChunk::Transparency(Transparency::Colour(sample)) => write!(f, "transparent colour {sample}"),
+			StandardChunk::PhysicalDimensions { x, y, metre: true } => write!(
+				f,
+				"{x} x {y} pixels per metre ({:.0} x {:.0} dpi)",
+				*x as f64 * 0.0254,
+				*y as f64 * 0.0254,
+			),
+			StandardChunk::PhysicalDimensions { x, y, metre: false } => write!(f, "pixel aspect ratio {x}:{y}"),
+			StandardChunk::SuggestedPalette { name, depth, entries } => {
+				write!(f, "suggested palette \"{name}\", {depth}-bit, {} entries", entries.len())
+			}
+			StandardChunk::Time { year, month, day, hour, minute, second } => {
+				write!(f, "modified {year:04}-{month:02}-{day:02} {hour:02}:{minute:02}:{second:02} UTC")
+			}
+			StandardChunk::Exif(data) => {
+				let order = if data.starts_with(b"MM") { "big" } else { "little" };
+				write!(f, "Exif data, {} bytes, {order}-endian", data.len())
+			}
+			StandardChunk::CodingIndependentCodePoints { colour_primaries, transfer_function, matrix_coefficients, full_range } => write!(
+				f,
+				"colour primaries {colour_primaries}, transfer function {transfer_function}, matrix {matrix_coefficients}, {} range",
+				if *full_range { "full" } else { "narrow" },
+			),
+			StandardChunk::MasteringDisplay { primaries, white, max_luminance, min_luminance } => {
+				let point = |point: &Chromaticity<u16>| format!("({:.5}, {:.5})", point.x as f64 / 5e4, point.y as f64 / 5e4);
+				let primaries: Vec<String> = primaries.iter().map(point).collect();
+				write!(
+					f,
+					"mastering display primaries {}, white point {}, luminance {:.4}-{:.4} cd/m²",
+					primaries.join(" "),
+					point(white),
+					*min_luminance as f64 / 1e4,
+					*max_luminance as f64 / 1e4,
+				)
+			}
+			StandardChunk::ContentLightLevel { max_content, max_frame_average } => write!(
+				f,
+				"MaxCLL {:.4} cd/m², MaxFALL {:.4} cd/m²",
+				*max_content as f64 / 1e4,
+				*max_frame_average as f64 / 1e4,
+			),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+
+	fn header(color_type: ColorType, bit_depth: u8) -> ImageHeader {
+		ImageHeader { width: 1, height: 1, bit_depth, color_type, interlaced: false }
+	}
+
+	fn roundtrip(standard: StandardChunk, header: &ImageHeader) {
+		let chunk = standard.to_chunk();
+		assert_eq!(StandardChunk::parse(&chunk, header).unwrap(), Some(standard));
+	}
+
+	#[test]
+	fn test_roundtrip_every_chunk() {
+		let rgb = header(ColorType::Rgb, 8);
+		let point = |x, y| Chromaticity { x, y };
+		let standards = vec![
+			StandardChunk::Gamma(45455),
+			StandardChunk::Chromaticities { white: point(31270, 32900), red: point(64000, 33000), green: point(30000, 60000), blue: point(15000, 6000) },
+			StandardChunk::Srgb(RenderingIntent::Saturation),
+			StandardChunk::IccProfile { name: String::from("Display P3"), profile: vec![0x78, 0x9c, 3, 0] },
+			StandardChunk::SignificantBits(vec![5, 6, 5]),
+			StandardChunk::Background(Sample::Rgb(255, 0, 128)),
+			StandardChunk::Transparency(Transparency::Colour(Sample::Rgb(1, 2, 3))),
+			StandardChunk::PhysicalDimensions { x: 2835, y: 2835, metre: true },
+			StandardChunk::SuggestedPalette {
+				name: String::from("web safe"),
+				depth: 16,
+				entries: vec![PaletteEntry { red: 0, green: 0x3333, blue: 0xffff, alpha: 0xffff, frequency: 7 }],
+			},
+			StandardChunk::Time { year: 2024, month: 2, day: 29, hour: 23, minute: 59, second: 60 },
+			StandardChunk::Exif(b"MM\0*\0\0\0\x08".to_vec()),
+			StandardChunk::CodingIndependentCodePoints { colour_primaries: 9, transfer_function: 16, matrix_coefficients: 0, full_range: true },
+			StandardChunk::MasteringDisplay {
+				primaries: [Chromaticity { x: 35400, y: 14600 }, Chromaticity { x: 8500, y: 39850 }, Chromaticity { x: 6550, y: 2300 }],
+				white: Chromaticity { x: 15635, y: 16450 },
+				max_luminance: 10_000_000,
+				min_luminance: 1,
+			},
+			StandardChunk::ContentLightLevel { max_content: 10_000_000, max_frame_average: 4_000_000 },
+		];
+		for standard in standards {
+			roundtrip(standard, &rgb);
+		}
+
+		let indexed = header(ColorType::Indexed, 4);
+		roundtrip(StandardChunk::Histogram(vec![1, 2, 3]), &indexed);
+		roundtrip(StandardChunk::Transparency(Transparency::Palette(vec![0, 128])), &indexed);
+		roundtrip(StandardChunk::Background(Sample::PaletteIndex(3)), &indexed);
+	}
+
+	#[test]
+	fn test_layout_depends_on_colour_type() {
+		let chunk = Chunk::new(ChunkType::BKGD, vec![0, 200]);
+		let grey = StandardChunk::parse(&chunk, &header(ColorType::Grayscale, 8)).unwrap();
+		assert_eq!(grey, Some(StandardChunk::Background(Sample::Grey(200))));
+		assert!(matches!(
+			StandardChunk::parse(&chunk, &header(ColorType::Rgb, 8)),
+			Err(StandardChunkError::Length { .. })
+		));
+
+		let trns = Chunk::new(ChunkType::TRNS, vec![0, 0]);
+		assert!(matches!(
+			StandardChunk::parse(&trns, &header(ColorType::Rgba, 8)),
+			Err(StandardChunkError::ColorType(..))
+		));
+	}
+
+	#[test]
+	fn test_field_ranges() {
+		let rgb = header(ColorType::Rgb, 8);
+		let invalid = [
+			Chunk::new(ChunkType::GAMA, vec![0; 4]),
+			Chunk::new(ChunkType::SRGB, vec![4]),
+			Chunk::new(ChunkType::SBIT, vec![8, 9, 8]),
+			Chunk::new(ChunkType::BKGD, vec![1, 0, 0, 0, 0, 0]),
+			Chunk::new(ChunkType::PHYS, vec![0, 0, 0, 1, 0, 0, 0, 1, 2]),
+			Chunk::new(ChunkType::TIME, vec![7, 232, 13, 1, 0, 0, 0]),
+			Chunk::new(ChunkType::EXIF, b"JFIF".to_vec()),
+			Chunk::new(ChunkType::CICP, vec![1, 13, 1, 1]),
+			Chunk::new(ChunkType::ICCP, b" name\0\0".to_vec()),
+		];
+		for chunk in invalid {
+			assert!(StandardChunk::parse(&chunk, &rgb).is_err(), "{} should be rejected", chunk.chunk_type());
+		}
+	}
+
+	#[test]
+	fn test_unknown_chunks_are_skipped() {
+		let chunk = Chunk::new("ruSt".parse().unwrap(), b"message".to_vec());
+		assert_eq!(StandardChunk::parse(&chunk, &header(ColorType::Rgb, 8)).unwrap(), None);
+	}
+
+	#[test]
+	fn test_display() {
+		assert_eq!(StandardChunk::Gamma(45455).to_string(), "gamma 0.45455");
+		assert_eq!(
+			StandardChunk::PhysicalDimensions { x: 2835, y: 2835, metre: true }.to_string(),
+			"2835 x 2835 pixels per metre (72 x 72 dpi)"
+		);
+		assert_eq!(
+			StandardChunk::Time { year: 2024, month: 1, day: 2, hour: 3, minute: 4, second: 5 }.to_string(),
+			"modified 2024-01-02 03:04:05 UTC"
+		);
+	}
+}
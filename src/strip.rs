@@ -0,0 +1,147 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::{ChunkType, ANIMATION};
+use crate::png::Png;
+
+/// Ancillary chunks that change how colours are rendered.
+pub const COLOUR_CHUNKS: [ChunkType; 8] = [
+	ChunkType::GAMA,
+	ChunkType::CHRM,
+	ChunkType::SRGB,
+	ChunkType::ICCP,
+	ChunkType::SBIT,
+	ChunkType::CICP,
+	ChunkType::MDCV,
+	ChunkType::CLLI,
+];
+
+/// Ancillary chunks `strip` considers before applying the exceptions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StripScope {
+	/// Private ancillary chunks, such as pngme's own.
+	PrivateOnly,
+	/// Public ancillary chunks too.
+	AllAncillary,
+}
+
+/// Which ancillary chunks `strip` removes. Critical chunks and tRNS, which
+/// is part of the image content, are always kept.
+pub struct StripPolicy {
+	pub scope: StripScope,
+	pub keep_color: bool,
+	/// Keywords of tEXt, zTXt and iTXt chunks to keep.
+	pub keep_text: Vec<String>,
+}
+
+/// Keyword of a tEXt, zTXt or iTXt chunk.
+pub fn text_keyword(chunk: &Chunk) -> Option<String> {
+	if ![ChunkType::TEXT, ChunkType::ZTXT, ChunkType::ITXT].contains(chunk.chunk_type()) {
+		return None;
+	}
+	let keyword = chunk.data().split(|&byte| byte == 0).next().unwrap_or_default();
+	Some(keyword.iter().map(|&byte| byte as char).collect())
+}
+
+impl StripPolicy {
+	pub fn removes(&self, chunk: &Chunk) -> bool {
+		let chunk_type = chunk.chunk_type();
+		if chunk_type.is_critical() || chunk_type == &ChunkType::TRNS {
+			return false;
+		}
+		// APNG's chunks look private but are registered
+		if (chunk_type.is_public() || chunk_type.registered_name().is_some()) && self.scope == StripScope::PrivateOnly {
+			return false;
+		}
+		if self.keep_color && COLOUR_CHUNKS.contains(chunk_type) {
+			return false;
+		}
+		match text_keyword(chunk) {
+			Some(keyword) => !self.keep_text.contains(&keyword),
+			None => true,
+		}
+	}
+
+	/// Removes the chunks the policy selects, returning them in file order.
+	pub fn apply(&self, png: &mut Png) -> Vec<Chunk> {
+		let (removed, kept): (Vec<Chunk>, Vec<Chunk>) = png.chunks().iter().cloned().partition(|chunk| self.removes(chunk));
+		*png = Png::from_chunks(kept);
+		removed
+	}
+}
+
+/// Whether stripping `removed` turned an APNG into a still image.
+pub fn removes_animation(removed: &[Chunk]) -> bool {
+	removed.iter().any(|chunk| ANIMATION.contains(chunk.chunk_type()))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+
+	fn testing_png() -> Png {
+		let chunk = |chunk_type: &str, data: &[u8]| Chunk::new(chunk_type.parse().unwrap(), data.to_vec());
+		Png::from_chunks(vec![
+			chunk("IHDR", &[0; 13]),
+			chunk("gAMA", &[0, 0, 177, 143]),
+			chunk("tEXt", b"Author\0Jane Doe"),
+			chunk("tEXt", b"Copyright\0CC BY 4.0"),
+			chunk("eXIf", b"MM\0*\0\0\0\x08\0\0\0\0\0\0"),
+			chunk("tRNS", &[0, 0]),
+			chunk("ruSt", b"hidden"),
+			chunk("IDAT", &[0; 8]),
+			chunk("IEND", &[]),
+		])
+	}
+
+	fn types(chunks: &[Chunk]) -> Vec<String> {
+		chunks.iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+	}
+
+	#[test]
+	fn test_private_only() {
+		let mut png = testing_png();
+		let policy = StripPolicy { scope: StripScope::PrivateOnly, keep_color: false, keep_text: Vec::new() };
+		assert_eq!(types(&policy.apply(&mut png)), ["ruSt"]);
+		assert_eq!(png.chunks().len(), 8);
+	}
+
+	#[test]
+	fn test_all_ancillary_keeps_transparency() {
+		let mut png = testing_png();
+		let policy = StripPolicy { scope: StripScope::AllAncillary, keep_color: false, keep_text: Vec::new() };
+		assert_eq!(types(&policy.apply(&mut png)), ["gAMA", "tEXt", "tEXt", "eXIf", "ruSt"]);
+		assert_eq!(types(png.chunks()), ["IHDR", "tRNS", "IDAT", "IEND"]);
+	}
+
+	#[test]
+	fn test_keep_color_and_text() {
+		let mut png = testing_png();
+		let policy = StripPolicy { scope: StripScope::AllAncillary, keep_color: true, keep_text: vec![String::from("Copyright")] };
+		policy.apply(&mut png);
+		assert_eq!(types(png.chunks()), ["IHDR", "gAMA", "tEXt", "tRNS", "IDAT", "IEND"]);
+		assert_eq!(text_keyword(&png.chunks()[2]).as_deref(), Some("Copyright"));
+	}
+
+	#[test]
+	fn test_animation() {
+		let chunk = |chunk_type: &str, data: &[u8]| Chunk::new(chunk_type.parse().unwrap(), data.to_vec());
+		let apng = Png::from_chunks(vec![
+			chunk("IHDR", &[0; 13]),
+			chunk("acTL", &[0, 0, 0, 1, 0, 0, 0, 0]),
+			chunk("fcTL", &[0; 26]),
+			chunk("IDAT", &[0; 8]),
+			chunk("ruSt", b"hidden"),
+			chunk("IEND", &[]),
+		]);
+		let mut png = apng.clone();
+		let policy = StripPolicy { scope: StripScope::PrivateOnly, keep_color: false, keep_text: Vec::new() };
+		let removed = policy.apply(&mut png);
+		assert_eq!(types(&removed), ["ruSt"]);
+		assert!(!removes_animation(&removed));
+
+		let mut png = apng;
+		let policy = StripPolicy { scope: StripScope::AllAncillary, keep_color: false, keep_text: Vec::new() };
+		assert!(removes_animation(&policy.apply(&mut png)));
+		assert_eq!(types(png.chunks()), ["IHDR", "IDAT", "IEND"]);
+	}
+}
@@ -0,0 +1,197 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::{ChunkType, ANIMATION};
+use crate::image::{ImageError, ImageHeader};
+use crate::png::Png;
+use crate::standard_chunk::StandardChunk;
+
+// Where a chunk may go relative to the critical chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Region {
+	BeforePlte,
+	BeforeIdat,
+	AfterIdat,
+}
+
+const BEFORE_PLTE: [ChunkType; 8] = [
+	ChunkType::CHRM,
+	ChunkType::GAMA,
+	ChunkType::ICCP,
+	ChunkType::SBIT,
+	ChunkType::SRGB,
+	ChunkType::CICP,
+	ChunkType::MDCV,
+	ChunkType::CLLI,
+];
+const BEFORE_IDAT: [ChunkType; 6] = [ChunkType::BKGD, ChunkType::HIST, ChunkType::TRNS, ChunkType::PHYS, ChunkType::SPLT, ChunkType::EXIF];
+// Standard chunks that may appear more than once.
+const REPEATABLE: [ChunkType; 4] = [ChunkType::SPLT, ChunkType::TEXT, ChunkType::ZTXT, ChunkType::ITXT];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+	Copied,
+	/// The target's chunk of the same type was replaced.
+	Replaced,
+	Skipped(String),
+}
+
+pub struct Transplanted {
+	pub chunk_type: ChunkType,
+	pub action: Action,
+}
+
+// Critical chunks an unsafe-to-copy chunk may depend on.
+fn critical_data(png: &Png) -> (Option<&[u8]>, Option<&[u8]>, Vec<u8>) {
+	(
+		png.chunk_by_type(&ChunkType::IHDR).map(Chunk::data),
+		png.chunk_by_type(&ChunkType::PLTE).map(Chunk::data),
+		png.image_data(),
+	)
+}
+
+// Legal region of every chunk in `png`: fixed for standard chunks, and the
+// same as in the source image for the others, as PNG editors must preserve.
+fn regions(png: &Png) -> Vec<Region> {
+	let mut current = Region::BeforePlte;
+	png.chunks()
+		.iter()
+		.map(|chunk| {
+			let chunk_type = chunk.chunk_type();
+			if chunk_type == &ChunkType::PLTE {
+				current = Region::BeforeIdat;
+			} else if chunk_type == &ChunkType::IDAT {
+				current = Region::AfterIdat;
+			}
+			if BEFORE_PLTE.contains(chunk_type) {
+				Region::BeforePlte
+			} else if BEFORE_IDAT.contains(chunk_type) {
+				Region::BeforeIdat
+			} else {
+				current
+			}
+		})
+		.collect()
+}
+
+fn insert(chunks: &mut Vec<Chunk>, chunk: Chunk, region: Region) {
+	let anchors: &[ChunkType] = match region {
+		Region::BeforePlte => &[ChunkType::PLTE, ChunkType::IDAT, ChunkType::IEND],
+		Region::BeforeIdat => &[ChunkType::IDAT, ChunkType::IEND],
+		Region::AfterIdat => &[ChunkType::IEND],
+	};
+	let index = chunks.iter().position(|existing| anchors.contains(existing.chunk_type())).unwrap_or(chunks.len());
+	chunks.insert(index, chunk);
+}
+
+/// Copies the ancillary chunks of `from` (only those of `types`, if given)
+/// into `to`. Unsafe-to-copy chunks depend on the image data, so they are
+/// skipped when the critical chunks differ unless `force` is set.
+pub fn transplant(from: &Png, to: &mut Png, types: &[ChunkType], force: bool) -> Result<Vec<Transplanted>, ImageError> {
+	let header = ImageHeader::from_png(to)?;
+	let same_image = critical_data(from) == critical_data(to);
+	let mut chunks = to.chunks().to_vec();
+	let mut report = Vec::new();
+
+	for (chunk, region) in from.chunks().iter().zip(regions(from)) {
+		let chunk_type = chunk.chunk_type();
+		if chunk_type.is_critical() || (!types.is_empty() && !types.contains(chunk_type)) {
+			continue;
+		}
+		let skip = if ANIMATION.contains(chunk_type) {
+			Some(String::from("animation chunks belong to their frames"))
+		} else if !chunk_type.is_safe_to_copy() && !same_image && !force {
+			Some(String::from("unsafe to copy to a different image (use --force)"))
+		} else if let Err(e) = StandardChunk::parse(chunk, &header) {
+			Some(format!("not valid for the target image: {e}"))
+		} else if chunks.iter().any(|existing| existing.chunk_type() == chunk_type && existing.data() == chunk.data()) {
+			Some(String::from("already present"))
+		} else {
+			None
+		};
+		if let Some(reason) = skip {
+			report.push(Transplanted { chunk_type: chunk_type.clone(), action: Action::Skipped(reason) });
+			continue;
+		}
+
+		let unique = chunk_type.is_public() && !REPEATABLE.contains(chunk_type);
+		let before = chunks.len();
+		if unique {
+			chunks.retain(|existing| existing.chunk_type() != chunk_type);
+		}
+		let action = if chunks.len() < before { Action::Replaced } else { Action::Copied };
+		insert(&mut chunks, chunk.clone(), region);
+		report.push(Transplanted { chunk_type: chunk_type.clone(), action });
+	}
+
+	*to = Png::from_chunks(chunks);
+	Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+	use crate::image::ColorType;
+
+	fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+		Chunk::new(chunk_type.parse().unwrap(), data.to_vec())
+	}
+
+	fn testing_png(idat: &[u8], ancillary: Vec<Chunk>) -> Png {
+		let header = ImageHeader { width: 1, height: 1, bit_depth: 8, color_type: ColorType::Indexed, interlaced: false };
+		let mut chunks = vec![chunk("IHDR", &header.as_bytes()), chunk("PLTE", &[0, 0, 0])];
+		chunks.push(chunk("IDAT", idat));
+		chunks.extend(ancillary);
+		chunks.push(chunk("IEND", &[]));
+		Png::from_chunks(chunks)
+	}
+
+	fn types(png: &Png) -> Vec<String> {
+		png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+	}
+
+	#[test]
+	fn test_chunks_go_to_legal_positions() {
+		// gAMA and tRNS given after IDAT in the source
+		let from = testing_png(b"image", vec![chunk("gAMA", &[0, 0, 177, 143]), chunk("tRNS", &[0]), chunk("tEXt", b"Title\0x")]);
+		let mut to = testing_png(b"image", Vec::new());
+		let report = transplant(&from, &mut to, &[], false).unwrap();
+		assert!(report.iter().all(|transplanted| transplanted.action == Action::Copied));
+		assert_eq!(types(&to), ["IHDR", "gAMA", "PLTE", "tRNS", "IDAT", "tEXt", "IEND"]);
+	}
+
+	#[test]
+	fn test_unsafe_chunks_need_force_between_images() {
+		let from = testing_png(b"image", vec![chunk("gAMA", &[0, 0, 177, 143]), chunk("pHYs", &[0, 0, 0, 1, 0, 0, 0, 1, 0])]);
+		let mut to = testing_png(b"other", Vec::new());
+		let report = transplant(&from, &mut to, &[], false).unwrap();
+		assert!(matches!(report[0].action, Action::Skipped(_)));
+		assert_eq!(report[1].action, Action::Copied);
+
+		let report = transplant(&from, &mut to, &[ChunkType::GAMA], true).unwrap();
+		assert_eq!(report.len(), 1);
+		assert_eq!(report[0].action, Action::Copied);
+		assert_eq!(types(&to), ["IHDR", "gAMA", "PLTE", "pHYs", "IDAT", "IEND"]);
+	}
+
+	#[test]
+	fn test_unique_chunks_are_replaced() {
+		let from = testing_png(b"image", vec![chunk("pHYs", &[0, 0, 0, 2, 0, 0, 0, 2, 0]), chunk("ruSt", b"one")]);
+		let mut to = testing_png(b"image", vec![chunk("pHYs", &[0, 0, 0, 1, 0, 0, 0, 1, 0])]);
+		let report = transplant(&from, &mut to, &[], false).unwrap();
+		assert_eq!(report[0].action, Action::Replaced);
+		assert_eq!(to.chunk_by_type(&ChunkType::PHYS).unwrap().data()[3], 2);
+		assert_eq!(types(&to), ["IHDR", "PLTE", "pHYs", "IDAT", "ruSt", "IEND"]);
+
+		// copying again changes nothing
+		let report = transplant(&from, &mut to, &[], false).unwrap();
+		assert!(report.iter().all(|transplanted| matches!(transplanted.action, Action::Skipped(_))));
+	}
+
+	#[test]
+	fn test_invalid_for_target_is_skipped() {
+		let from = testing_png(b"image", vec![chunk("bKGD", &[0, 0])]);
+		let mut to = testing_png(b"image", Vec::new());
+		let report = transplant(&from, &mut to, &[], true).unwrap();
+		assert!(matches!(report[0].action, Action::Skipped(_)));
+	}
+}
@@ -0,0 +1,398 @@
+use std::path::{Path, PathBuf};
+use flate2::Compression;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::widgets::{Block, Paragraph, Row, Table, TableState, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use crate::args::OutputPolicy;
+use crate::chunk::Chunk;
+use crate::chunk_type::{ChunkType, ChunkTypeError};
+use crate::commands;
+use crate::diff;
+use crate::embed::{self, Target};
+use crate::envelope::Envelope;
+use crate::image::ImageHeader;
+use crate::png::Png;
+use crate::standard_chunk::StandardChunk;
+
+// Bytes shown in the hex pane; the rest is summarised.
+const HEX_LIMIT: usize = 64 * 1024;
+
+const HELP: &str = "↑↓ select  J/K move  d delete  e export  a add message  tab hex/decoded  s save  q quit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prompt {
+	Export,
+	Add,
+}
+
+impl Prompt {
+	fn label(&self) -> &'static str {
+		match self {
+			Prompt::Export => "Export data to file: ",
+			Prompt::Add => "Add message chunk (TYPE message): ",
+		}
+	}
+}
+
+/// State of the chunk browser. Edits apply to `png` in memory until saved.
+struct App {
+	path: PathBuf,
+	policy: OutputPolicy,
+	/// Whether saving already backed up the file this session, so later
+	/// saves don't replace the backup of the original.
+	backed_up: bool,
+	/// The file as last read or saved.
+	original: Png,
+	png: Png,
+	/// Set by every edit and cleared by saving.
+	modified: bool,
+	table: TableState,
+	hex: bool,
+	scroll: u16,
+	prompt: Option<(Prompt, String)>,
+	status: String,
+	confirm_quit: bool,
+}
+
+impl App {
+	fn new(path: PathBuf, png: Png, policy: OutputPolicy) -> App {
+		let mut table = TableState::default();
+		table.select(Some(0));
+		App {
+			path,
+			policy,
+			backed_up: false,
+			original: png.clone(),
+			png,
+			modified: false,
+			table,
+			hex: false,
+			scroll: 0,
+			prompt: None,
+			status: String::from(HELP),
+			confirm_quit: false,
+		}
+	}
+
+	fn selected(&self) -> Option<usize> {
+		self.table.selected().filter(|&index| index < self.png.chunks().len())
+	}
+
+	fn select(&mut self, index: usize) {
+		let last = self.png.chunks().len().saturating_sub(1);
+		self.table.select(Some(index.min(last)));
+		self.scroll = 0;
+	}
+
+	fn delete(&mut self) -> Result<String, String> {
+		let index = self.selected().ok_or("no chunk selected")?;
+		let mut chunks = self.png.chunks().to_vec();
+		let chunk = chunks.remove(index);
+		self.png = Png::from_chunks(chunks);
+		self.modified = true;
+		self.select(index);
+		Ok(format!("Deleted {}.", chunk.chunk_type()))
+	}
+
+	// Moves the selected chunk one place up (-1) or down (+1).
+	fn move_by(&mut self, step: isize) -> Result<String, String> {
+		let index = self.selected().ok_or("no chunk selected")?;
+		let target = index.checked_add_signed(step).filter(|&target| target < self.png.chunks().len()).ok_or("cannot move further")?;
+		let mut chunks = self.png.chunks().to_vec();
+		chunks.swap(index, target);
+		self.png = Png::from_chunks(chunks);
+		self.modified = true;
+		self.select(target);
+		Ok(format!("Moved {} to position {target}.", self.png.chunks()[target].chunk_type()))
+	}
+
+	fn export(&self, path: &str) -> Result<String, String> {
+		let chunk = &self.png.chunks()[self.selected().ok_or("no chunk selected")?];
+		commands::write_output(Path::new(path), chunk.data(), self.policy).map_err(|e| e.to_string())?;
+		Ok(format!("Wrote {} bytes of {} to {path}.", chunk.data().len(), chunk.chunk_type()))
+	}
+
+	// Adds a message the way `encode` does with the chunk method.
+	fn add(&mut self, input: &str) -> Result<String, String> {
+		let (chunk_type, message) = input.split_once(' ').ok_or("expected a chunk type and a message")?;
+		let chunk_type: ChunkType = chunk_type.parse().map_err(|e: ChunkTypeError| e.to_string())?;
+		let warning = commands::check_message_type(&chunk_type, false).map_err(|e| e.to_string())?;
+		embed::embed(&mut self.png, &Target::Chunk(chunk_type.clone()), message.as_bytes(), Compression::best()).map_err(|e| e.to_string())?;
+		self.modified = true;
+		self.select(self.png.chunks().len() - 1);
+		Ok(warning.unwrap_or_else(|| format!("Added {chunk_type}.")))
+	}
+
+	fn save(&mut self) -> Result<String, String> {
+		let policy = match self.policy {
+			OutputPolicy::Backup if self.backed_up => OutputPolicy::Overwrite,
+			policy => policy,
+		};
+		commands::save(&self.original, &self.png, &self.path, false, policy).map_err(|e| e.to_string())?;
+		self.backed_up |= policy == OutputPolicy::Backup;
+		self.original = self.png.clone();
+		self.modified = false;
+		Ok(format!("Saved {}.", self.path.display()))
+	}
+
+	// Handles a key press; returns false to quit.
+	fn key(&mut self, code: KeyCode) -> bool {
+		if let Some((prompt, mut input)) = self.prompt.take() {
+			match code {
+				KeyCode::Enter => {
+					let result = match prompt {
+						Prompt::Export => self.export(&input),
+						Prompt::Add => self.add(&input),
+					};
+					self.status = result.unwrap_or_else(|e| format!("Error: {e}"));
+				}
+				KeyCode::Esc => self.status = String::from(HELP),
+				KeyCode::Backspace => {
+					input.pop();
+					self.prompt = Some((prompt, input));
+				}
+				KeyCode::Char(c) => {
+					input.push(c);
+					self.prompt = Some((prompt, input));
+				}
+				_ => self.prompt = Some((prompt, input)),
+			}
+			return true;
+		}
+
+		let confirm_quit = std::mem::take(&mut self.confirm_quit);
+		let index = self.selected().unwrap_or(0);
+		let result = match code {
+			KeyCode::Char('q') | KeyCode::Esc => {
+				if confirm_quit || !self.modified {
+					return false;
+				}
+				self.confirm_quit = true;
+				Err(String::from("unsaved changes; press q again to quit without saving, or s to save"))
+			}
+			KeyCode::Up | KeyCode::Char('k') => {
+				self.select(index.saturating_sub(1));
+				return true;
+			}
+			KeyCode::Down | KeyCode::Char('j') => {
+				self.select(index + 1);
+				return true;
+			}
+			KeyCode::PageUp => {
+				self.scroll = self.scroll.saturating_sub(10);
+				return true;
+			}
+			KeyCode::PageDown => {
+				self.scroll = self.scroll.saturating_add(10);
+				return true;
+			}
+			KeyCode::Tab => {
+				self.hex = !self.hex;
+				self.scroll = 0;
+				return true;
+			}
+			KeyCode::Char('K') => self.move_by(-1),
+			KeyCode::Char('J') => self.move_by(1),
+			KeyCode::Char('d') | KeyCode::Delete => self.delete(),
+			KeyCode::Char('s') => self.save(),
+			KeyCode::Char('e') => {
+				self.prompt = Some((Prompt::Export, String::new()));
+				return true;
+			}
+			KeyCode::Char('a') => {
+				self.prompt = Some((Prompt::Add, String::new()));
+				return true;
+			}
+			_ => return true,
+		};
+		self.status = result.unwrap_or_else(|e| format!("Error: {e}"));
+		true
+	}
+
+	fn run(&mut self, terminal: &mut DefaultTerminal) -> crate::Result<()> {
+		loop {
+			terminal.draw(|frame| self.draw(frame))?;
+			if let Event::Key(key) = event::read()? {
+				if key.kind == KeyEventKind::Press && !self.key(key.code) {
+					return Ok(());
+				}
+			}
+		}
+	}
+
+	fn draw(&mut self, frame: &mut Frame) {
+		let [title, main, status] = Layout::vertical([Constraint::Length(1), Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+		let [list, detail] = Layout::horizontal([Constraint::Percentage(55), Constraint::Percentage(45)]).areas(main);
+
+		let modified = if self.modified { " [modified]" } else { "" };
+		frame.render_widget(Paragraph::new(format!("pngme: {}{modified}", self.path.display())), title);
+
+		let rows = self.png.chunks().iter().zip(diff::locations(&self.png)).map(|(chunk, location)| {
+			Row::new([
+				location.index.to_string(),
+				chunk.chunk_type().to_string(),
+				location.offset.to_string(),
+				location.length.to_string(),
+				flags(chunk.chunk_type()),
+			])
+		});
+		let widths = [Constraint::Length(4), Constraint::Length(5), Constraint::Length(10), Constraint::Length(10), Constraint::Min(20)];
+		let table = Table::new(rows, widths)
+			.header(Row::new(["#", "Type", "Offset", "Length", "Flags"]).style(Style::new().add_modifier(Modifier::BOLD)))
+			.block(Block::bordered().title("Chunks"))
+			.row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+		frame.render_stateful_widget(table, list, &mut self.table);
+
+		let (title, text) = match self.selected().map(|index| &self.png.chunks()[index]) {
+			Some(chunk) if self.hex => ("Hex", hex_dump(chunk.data())),
+			Some(chunk) => ("Decoded", decoded(&self.png, chunk)),
+			None => ("Decoded", String::new()),
+		};
+		let paragraph = Paragraph::new(text).block(Block::bordered().title(title)).scroll((self.scroll, 0));
+		let paragraph = if self.hex { paragraph } else { paragraph.wrap(Wrap { trim: false }) };
+		frame.render_widget(paragraph, detail);
+
+		let line = match &self.prompt {
+			Some((prompt, input)) => format!("{}{input}", prompt.label()),
+			None => self.status.clone(),
+		};
+		frame.render_widget(Paragraph::new(line), status);
+	}
+}
+
+// Property bits of a chunk type in words.
+fn flags(chunk_type: &ChunkType) -> String {
+	[
+		if chunk_type.is_critical() { "critical" } else { "ancillary" },
+		if chunk_type.is_public() { "public" } else { "private" },
+		if chunk_type.is_safe_to_copy() { "safe-to-copy" } else { "unsafe-to-copy" },
+	]
+	.join(" ")
+}
+
+fn hex_dump(data: &[u8]) -> String {
+	let mut lines: Vec<String> = data[..data.len().min(HEX_LIMIT)]
+		.chunks(16)
+		.enumerate()
+		.map(|(row, bytes)| {
+			let hex: Vec<String> = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+			let ascii: String = bytes.iter().map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' }).collect();
+			format!("{:08x}  {:<47}  {ascii}", row * 16, hex.join(" "))
+		})
+		.collect();
+	if data.len() > HEX_LIMIT {
+		lines.push(format!("… {} more bytes", data.len() - HEX_LIMIT));
+	}
+	lines.join("\n")
+}
+
+fn decoded(png: &Png, chunk: &Chunk) -> String {
+	if let Ok(header) = ImageHeader::from_png(png) {
+		match StandardChunk::parse(chunk, &header) {
+			Ok(Some(standard)) => return standard.to_string(),
+			Err(e) => return format!("invalid: {e}"),
+			Ok(None) => {}
+		}
+	}
+	let data = chunk.data();
+	if Envelope::parse_stored(data).is_some() {
+		return format!("pngme payload envelope, {} bytes", data.len());
+	}
+	if chunk.chunk_type() == &ChunkType::TEXT {
+		// keyword and text are Latin-1
+		return data.iter().map(|&byte| if byte == 0 { '\n' } else { byte as char }).collect();
+	}
+	match std::str::from_utf8(data) {
+		Ok(text) if !text.contains('\0') => text.to_string(),
+		_ => format!("{} bytes of binary data; press tab for a hex dump", data.len()),
+	}
+}
+
+/// Opens the chunk browser on `path` until the user quits.
+pub fn run(path: &Path, policy: OutputPolicy) -> crate::Result<()> {
+	let png = Png::try_from(commands::read_file(path)?.as_slice())?;
+	let mut app = App::new(path.to_path_buf(), png, policy);
+	let mut terminal = ratatui::init();
+	let result = app.run(&mut terminal);
+	ratatui::restore();
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+
+	fn testing_app() -> App {
+		let chunks = ["IHDR", "tEXt", "IDAT", "IEND"].iter().map(|chunk_type| Chunk::new(chunk_type.parse().unwrap(), b"data".to_vec())).collect();
+		App::new(PathBuf::from("test.png"), Png::from_chunks(chunks), OutputPolicy::Overwrite)
+	}
+
+	fn types(app: &App) -> Vec<String> {
+		app.png.chunks().iter().map(|chunk| chunk.chunk_type().to_string()).collect()
+	}
+
+	#[test]
+	fn test_delete_and_move() {
+		let mut app = testing_app();
+		app.select(1);
+		app.key(KeyCode::Char('J'));
+		assert_eq!(types(&app), ["IHDR", "IDAT", "tEXt", "IEND"]);
+		assert_eq!(app.selected(), Some(2));
+		assert!(app.modified);
+
+		app.key(KeyCode::Char('d'));
+		assert_eq!(types(&app), ["IHDR", "IDAT", "IEND"]);
+		assert_eq!(app.selected(), Some(2));
+		assert!(app.move_by(1).is_err());
+	}
+
+	#[test]
+	fn test_add_message() {
+		let mut app = testing_app();
+		assert!(app.add("IDAT hello").is_err());
+		assert_eq!(app.add("ruSt hello"), Ok(String::from("Added ruSt.")));
+		assert_eq!(app.png.chunks().last().unwrap().data(), b"hello");
+		assert_eq!(app.selected(), Some(4));
+	}
+
+	#[test]
+	fn test_quit_asks_before_dropping_changes() {
+		let mut app = testing_app();
+		app.key(KeyCode::Char('d'));
+		assert!(app.key(KeyCode::Char('q')));
+		assert!(!app.key(KeyCode::Char('q')));
+	}
+
+	#[test]
+	fn test_output_policy() {
+		let root = std::env::temp_dir().join(format!("pngme-tui-{}", std::process::id()));
+		std::fs::create_dir_all(&root).unwrap();
+		let path = root.join("image.png");
+		let mut app = testing_app();
+		std::fs::write(&path, app.png.as_bytes()).unwrap();
+		app.path = path.clone();
+		app.policy = OutputPolicy::Backup;
+
+		app.key(KeyCode::Char('d'));
+		app.save().unwrap();
+		app.key(KeyCode::Char('d'));
+		app.save().unwrap();
+		let original = testing_app().png.as_bytes();
+		assert_eq!(std::fs::read(root.join("image.png.bak")).unwrap(), original);
+		assert_eq!(std::fs::read(&path).unwrap(), app.png.as_bytes());
+		assert_eq!(types(&app), ["IDAT", "IEND"]);
+
+		app.policy = OutputPolicy::Refuse;
+		assert!(app.export(path.to_str().unwrap()).is_err());
+		assert!(app.export(root.join("chunk.bin").to_str().unwrap()).is_ok());
+		std::fs::remove_dir_all(&root).unwrap();
+	}
+
+	#[test]
+	fn test_hex_dump() {
+		assert_eq!(hex_dump(b"IHDR\x00\x01"), format!("00000000  {:<47}  IHDR..", "49 48 44 52 00 01"));
+	}
+}
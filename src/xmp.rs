@@ -0,0 +1,510 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use flate2::read::ZlibDecoder;
+use xml::reader::{EventReader, ParserConfig, XmlEvent};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+
+/// Keyword of the iTXt chunk that holds the XMP packet.
+pub const KEYWORD: &str = "XML:com.adobe.xmp";
+
+const RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const XML: &str = "http://www.w3.org/XML/1998/namespace";
+const META: &str = "adobe:ns:meta/";
+
+/// Prefixes that can be used without declaring their namespace.
+pub const NAMESPACES: [(&str, &str); 7] = [
+	("dc", "http://purl.org/dc/elements/1.1/"),
+	("xmp", "http://ns.adobe.com/xap/1.0/"),
+	("xmpRights", "http://ns.adobe.com/xap/1.0/rights/"),
+	("photoshop", "http://ns.adobe.com/photoshop/1.0/"),
+	("Iptc4xmpCore", "http://iptc.org/std/Iptc4xmpCore/1.0/xmlns/"),
+	("tiff", "http://ns.adobe.com/tiff/1.0/"),
+	("exif", "http://ns.adobe.com/exif/1.0/"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Name {
+	prefix: Option<String>,
+	namespace: Option<String>,
+	local: String,
+}
+
+impl Name {
+	fn new(prefix: &str, namespace: &str, local: &str) -> Name {
+		Name { prefix: Some(prefix.to_string()), namespace: Some(namespace.to_string()), local: local.to_string() }
+	}
+
+	fn is(&self, namespace: &str, local: &str) -> bool {
+		self.namespace.as_deref() == Some(namespace) && self.local == local
+	}
+}
+
+impl std::fmt::Display for Name {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match &self.prefix {
+			Some(prefix) => write!(f, "{prefix}:{}", self.local),
+			None => write!(f, "{}", self.local),
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+	Element(Element),
+	Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Element {
+	name: Name,
+	attributes: Vec<(Name, String)>,
+	/// Namespaces declared on this element, as (prefix, URI); "" is the default namespace.
+	declarations: Vec<(String, String)>,
+	children: Vec<Node>,
+}
+
+impl Element {
+	fn new(name: Name) -> Element {
+		Element { name, attributes: Vec::new(), declarations: Vec::new(), children: Vec::new() }
+	}
+
+	fn elements(&self) -> impl Iterator<Item = &Element> {
+		self.children.iter().filter_map(|node| match node {
+			Node::Element(element) => Some(element),
+			Node::Text(_) => None,
+		})
+	}
+
+	fn attribute(&self, namespace: &str, local: &str) -> Option<&str> {
+		self.attributes.iter().find(|(name, _)| name.is(namespace, local)).map(|(_, value)| value.as_str())
+	}
+
+	fn text(&self) -> String {
+		self.children
+			.iter()
+			.filter_map(|node| match node {
+				Node::Text(text) => Some(text.as_str()),
+				Node::Element(_) => None,
+			})
+			.collect()
+	}
+
+	fn find(&self, namespace: &str, local: &str) -> Option<&Element> {
+		if self.name.is(namespace, local) {
+			return Some(self);
+		}
+		self.elements().find_map(|child| child.find(namespace, local))
+	}
+
+	fn find_mut(&mut self, namespace: &str, local: &str) -> Option<&mut Element> {
+		if self.name.is(namespace, local) {
+			return Some(self);
+		}
+		self.children.iter_mut().find_map(|node| match node {
+			Node::Element(child) => child.find_mut(namespace, local),
+			Node::Text(_) => None,
+		})
+	}
+
+	// Human-readable value of a property element.
+	fn value(&self) -> String {
+		if let Some(resource) = self.attribute(RDF, "resource") {
+			return resource.to_string();
+		}
+		let Some(child) = self.elements().next() else {
+			return self.text().trim().to_string();
+		};
+		let items: Vec<&Element> = child.elements().filter(|item| item.name.is(RDF, "li")).collect();
+		if child.name.is(RDF, "Alt") {
+			let default = items.iter().find(|item| item.attribute(XML, "lang") == Some("x-default")).or(items.first());
+			return default.map(|item| item.value()).unwrap_or_default();
+		}
+		if child.name.is(RDF, "Seq") || child.name.is(RDF, "Bag") {
+			return items.iter().map(|item| item.value()).collect::<Vec<_>>().join("; ");
+		}
+		String::from("(structure)")
+	}
+
+	fn write(&self, out: &mut String, depth: usize) {
+		let indent = " ".repeat(depth);
+		out.push_str(&format!("{indent}<{}", self.name));
+		for (prefix, uri) in &self.declarations {
+			match prefix.as_str() {
+				"" => out.push_str(&format!(" xmlns=\"{}\"", escape(uri))),
+				prefix => out.push_str(&format!(" xmlns:{prefix}=\"{}\"", escape(uri))),
+			}
+		}
+		for (name, value) in &self.attributes {
+			out.push_str(&format!(" {name}=\"{}\"", escape(value)));
+		}
+		match self.children.as_slice() {
+			[] => out.push_str("/>\n"),
+			[Node::Text(text)] => out.push_str(&format!(">{}</{}>\n", escape(text), self.name)),
+			children => {
+				out.push_str(">\n");
+				for child in children {
+					match child {
+						Node::Element(element) => element.write(out, depth + 1),
+						Node::Text(text) => out.push_str(&format!("{indent} {}\n", escape(text))),
+					}
+				}
+				out.push_str(&format!("{indent}</{}>\n", self.name));
+			}
+		}
+	}
+}
+
+fn escape(text: &str) -> String {
+	text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum XmpError {
+	#[error("malformed iTXt chunk")]
+	Itxt,
+
+	#[error("failed to inflate XMP packet: {0}")]
+	Inflate(#[from] std::io::Error),
+
+	#[error("XMP packet is not valid XML: {0}")]
+	Xml(#[from] xml::reader::Error),
+
+	#[error("XMP packet has no rdf:RDF element")]
+	NoRdf,
+
+	#[error("property \"{0}\" must be written as prefix:name")]
+	Name(String),
+
+	#[error("unknown namespace prefix \"{0}\" (declare it with --namespace {0}=URI)")]
+	Prefix(String),
+}
+
+/// A property name resolved to its namespace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Property {
+	pub prefix: String,
+	pub namespace: String,
+	pub name: String,
+}
+
+impl Property {
+	/// Parses `prefix:name`, or one of the shorthands title, creator,
+	/// rights, description and subject for their Dublin Core properties.
+	pub fn parse(text: &str, custom: &[(String, String)]) -> Result<Property, XmpError> {
+		let (prefix, name) = match text.split_once(':') {
+			Some((prefix, name)) => (prefix, name),
+			None if ["title", "creator", "rights", "description", "subject"].contains(&text) => ("dc", text),
+			None => return Err(XmpError::Name(text.to_string())),
+		};
+		let namespace = custom
+			.iter()
+			.find(|(custom, _)| custom == prefix)
+			.map(|(_, uri)| uri.as_str())
+			.or_else(|| NAMESPACES.iter().find(|(known, _)| *known == prefix).map(|(_, uri)| *uri))
+			.ok_or_else(|| XmpError::Prefix(prefix.to_string()))?;
+		Ok(Property { prefix: prefix.to_string(), namespace: namespace.to_string(), name: name.to_string() })
+	}
+
+	fn array_kind(&self) -> Option<&'static str> {
+		match (self.namespace.as_str(), self.name.as_str()) {
+			("http://purl.org/dc/elements/1.1/", "title" | "rights" | "description")
+			| ("http://ns.adobe.com/xap/1.0/rights/", "UsageTerms") => Some("Alt"),
+			("http://purl.org/dc/elements/1.1/", "creator") => Some("Seq"),
+			("http://purl.org/dc/elements/1.1/", "subject") => Some("Bag"),
+			_ => None,
+		}
+	}
+}
+
+/// An XMP packet as an XML tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Xmp {
+	root: Element,
+}
+
+impl Xmp {
+	/// An empty packet with a single rdf:Description.
+	pub fn new() -> Xmp {
+		let mut description = Element::new(Name::new("rdf", RDF, "Description"));
+		description.attributes.push((Name::new("rdf", RDF, "about"), String::new()));
+		let mut rdf = Element::new(Name::new("rdf", RDF, "RDF"));
+		rdf.declarations.push((String::from("rdf"), RDF.to_string()));
+		rdf.children.push(Node::Element(description));
+		let mut root = Element::new(Name::new("x", META, "xmpmeta"));
+		root.declarations.push((String::from("x"), META.to_string()));
+		root.children.push(Node::Element(rdf));
+		Xmp { root }
+	}
+
+	/// Reads the packet from an iTXt chunk, which may be compressed.
+	pub fn from_chunk(chunk: &Chunk) -> Result<Xmp, XmpError> {
+		let data = chunk.data();
+		let mut fields = data.splitn(2, |&byte| byte == 0);
+		let (Some(_keyword), Some(rest)) = (fields.next(), fields.next()) else {
+			return Err(XmpError::Itxt);
+		};
+		let [compressed, _method, rest @ ..] = rest else {
+			return Err(XmpError::Itxt);
+		};
+		// skip the language tag and translated keyword
+		let mut fields = rest.splitn(3, |&byte| byte == 0);
+		let (Some(_), Some(_), Some(text)) = (fields.next(), fields.next(), fields.next()) else {
+			return Err(XmpError::Itxt);
+		};
+		let mut packet = Vec::new();
+		match compressed {
+			0 => packet.extend_from_slice(text),
+			_ => {
+				ZlibDecoder::new(text).read_to_end(&mut packet)?;
+			}
+		}
+		Xmp::parse(&packet)
+	}
+
+	fn parse(packet: &[u8]) -> Result<Xmp, XmpError> {
+		let config = ParserConfig::new().cdata_to_characters(true).ignore_comments(true);
+		let mut stack: Vec<(Element, BTreeMap<String, String>)> = Vec::new();
+		let mut scope: BTreeMap<String, String> = BTreeMap::new();
+		for event in EventReader::new_with_config(packet, config) {
+			match event? {
+				XmlEvent::StartElement { name, attributes, namespace } => {
+					let current: BTreeMap<String, String> =
+						namespace.iter().map(|(prefix, uri)| (prefix.to_string(), uri.to_string())).collect();
+					let mut element = Element::new(Name { prefix: name.prefix, namespace: name.namespace, local: name.local_name });
+					element.declarations = current
+						.iter()
+						.filter(|(prefix, uri)| !matches!(prefix.as_str(), "xml" | "xmlns") && !uri.is_empty() && scope.get(*prefix) != Some(uri))
+						.map(|(prefix, uri)| (prefix.clone(), uri.clone()))
+						.collect();
+					element.attributes = attributes
+						.into_iter()
+						.map(|attribute| {
+							let name = attribute.name;
+							(Name { prefix: name.prefix, namespace: name.namespace, local: name.local_name }, attribute.value)
+						})
+						.collect();
+					stack.push((element, std::mem::replace(&mut scope, current)));
+				}
+				XmlEvent::EndElement { .. } => {
+					let (element, parent_scope) = stack.pop().expect("balanced elements");
+					scope = parent_scope;
+					match stack.last_mut() {
+						Some((parent, _)) => parent.children.push(Node::Element(element)),
+						None => {
+							let xmp = Xmp { root: element };
+							xmp.rdf().ok_or(XmpError::NoRdf)?;
+							return Ok(xmp);
+						}
+					}
+				}
+				XmlEvent::Characters(text) => {
+					if let Some((element, _)) = stack.last_mut() {
+						element.children.push(Node::Text(text));
+					}
+				}
+				_ => {}
+			}
+		}
+		Err(XmpError::NoRdf)
+	}
+
+	fn rdf(&self) -> Option<&Element> {
+		self.root.find(RDF, "RDF")
+	}
+
+	fn descriptions(&self) -> impl Iterator<Item = &Element> {
+		self.rdf().into_iter().flat_map(|rdf| rdf.elements().filter(|child| child.name.is(RDF, "Description")))
+	}
+
+	/// Every top-level property as (name, value), in document order.
+	pub fn properties(&self) -> Vec<(String, String)> {
+		let mut properties = Vec::new();
+		for description in self.descriptions() {
+			for (name, value) in &description.attributes {
+				if name.namespace.as_deref() != Some(RDF) {
+					properties.push((name.to_string(), value.clone()));
+				}
+			}
+			for property in description.elements() {
+				properties.push((property.name.to_string(), property.value()));
+			}
+		}
+		properties
+	}
+
+	pub fn get(&self, property: &Property) -> Option<String> {
+		self.descriptions().find_map(|description| {
+			description
+				.attribute(&property.namespace, &property.name)
+				.map(str::to_string)
+				.or_else(|| description.elements().find(|element| element.name.is(&property.namespace, &property.name)).map(Element::value))
+		})
+	}
+
+	/// Sets a property, replacing any existing value. Language alternatives
+	/// are written as x-default, and creator and subject as arrays of `values`.
+	pub fn set(&mut self, property: &Property, values: &[String]) {
+		let rdf = self.root.find_mut(RDF, "RDF").expect("parsed packets have rdf:RDF");
+		for node in rdf.children.iter_mut() {
+			if let Node::Element(description) = node {
+				description.attributes.retain(|(name, _)| !name.is(&property.namespace, &property.name));
+				description.children.retain(|node| !matches!(node, Node::Element(element) if element.name.is(&property.namespace, &property.name)));
+			}
+		}
+
+		// reuse the prefix the document already has for this namespace; a
+		// prefix declared on one rdf:Description is only bound inside it
+		let declared = |element: &Element| element.declarations.iter().find(|(_, uri)| *uri == property.namespace).map(|(prefix, _)| prefix.clone());
+		let inherited = declared(&self.root).or_else(|| self.rdf().and_then(declared));
+		let local = self.descriptions().enumerate().find_map(|(position, description)| declared(description).map(|prefix| (position, prefix)));
+
+		let rdf = self.root.find_mut(RDF, "RDF").expect("parsed packets have rdf:RDF");
+		if !rdf.elements().any(|child| child.name.is(RDF, "Description")) {
+			let mut description = Element::new(Name::new("rdf", RDF, "Description"));
+			description.attributes.push((Name::new("rdf", RDF, "about"), String::new()));
+			rdf.children.push(Node::Element(description));
+		}
+		let undeclared = inherited.is_none() && local.is_none();
+		let (position, prefix) = match (inherited, local) {
+			(Some(prefix), _) => (0, prefix),
+			(None, Some((position, prefix))) => (position, prefix),
+			(None, None) => (0, property.prefix.clone()),
+		};
+		let description = rdf
+			.children
+			.iter_mut()
+			.filter_map(|node| match node {
+				Node::Element(element) if element.name.is(RDF, "Description") => Some(element),
+				_ => None,
+			})
+			.nth(position)
+			.expect("just ensured");
+		if undeclared && !description.declarations.iter().any(|(declared, _)| *declared == prefix) {
+			description.declarations.push((prefix.clone(), property.namespace.clone()));
+			description.declarations.sort();
+		}
+
+		let mut element = Element::new(Name::new(&prefix, &property.namespace, &property.name));
+		match property.array_kind() {
+			Some(kind) => {
+				let mut array = Element::new(Name::new("rdf", RDF, kind));
+				for value in values {
+					let mut item = Element::new(Name::new("rdf", RDF, "li"));
+					if kind == "Alt" {
+						item.attributes.push((Name::new("xml", XML, "lang"), String::from("x-default")));
+					}
+					item.children.push(Node::Text(value.clone()));
+					array.children.push(Node::Element(item));
+					if kind == "Alt" {
+						break;
+					}
+				}
+				element.children.push(Node::Element(array));
+			}
+			None => element.children.push(Node::Text(values.join(", "))),
+		}
+		description.children.push(Node::Element(element));
+	}
+
+	/// Serializes as a complete packet with xpacket wrapper.
+	pub fn as_bytes(&self) -> Vec<u8> {
+		let mut packet = String::from("<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n");
+		self.root.write(&mut packet, 0);
+		packet.push_str("<?xpacket end=\"w\"?>");
+		packet.into_bytes()
+	}
+
+	/// An uncompressed iTXt chunk holding the packet.
+	pub fn to_chunk(&self) -> Chunk {
+		let data = KEYWORD.bytes().chain([0, 0, 0, 0, 0]).chain(self.as_bytes()).collect();
+		Chunk::new(ChunkType::ITXT, data)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use pretty_assertions::assert_eq;
+
+	const PACKET: &str = r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="" xmlns:xmp="http://ns.adobe.com/xap/1.0/" xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmp:CreatorTool="Editor &amp; Co">
+   <dc:title><rdf:Alt><rdf:li xml:lang="de">Titel</rdf:li><rdf:li xml:lang="x-default">Title</rdf:li></rdf:Alt></dc:title>
+   <dc:creator><rdf:Seq><rdf:li>Jane</rdf:li><rdf:li>John</rdf:li></rdf:Seq></dc:creator>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#;
+
+	fn property(text: &str) -> Property {
+		Property::parse(text, &[(String::from("acme"), String::from("http://example.com/acme/"))]).unwrap()
+	}
+
+	#[test]
+	fn test_get_properties() {
+		let xmp = Xmp::parse(PACKET.as_bytes()).unwrap();
+		assert_eq!(xmp.get(&property("title")).as_deref(), Some("Title"));
+		assert_eq!(xmp.get(&property("creator")).as_deref(), Some("Jane; John"));
+		assert_eq!(xmp.get(&property("xmp:CreatorTool")).as_deref(), Some("Editor & Co"));
+		assert_eq!(xmp.get(&property("rights")), None);
+		assert_eq!(xmp.properties().len(), 3);
+	}
+
+	#[test]
+	fn test_set_and_write_back() {
+		let mut xmp = Xmp::parse(PACKET.as_bytes()).unwrap();
+		xmp.set(&property("title"), &[String::from("New <title>")]);
+		xmp.set(&property("rights"), &[String::from("CC BY 4.0")]);
+		xmp.set(&property("acme:AssetId"), &[String::from("42")]);
+		xmp.set(&property("xmp:CreatorTool"), &[String::from("pngme")]);
+
+		let reparsed = Xmp::from_chunk(&xmp.to_chunk()).unwrap();
+		assert_eq!(reparsed, xmp);
+		assert_eq!(reparsed.get(&property("title")).as_deref(), Some("New <title>"));
+		assert_eq!(reparsed.get(&property("rights")).as_deref(), Some("CC BY 4.0"));
+		assert_eq!(reparsed.get(&property("acme:AssetId")).as_deref(), Some("42"));
+		assert_eq!(reparsed.get(&property("xmp:CreatorTool")).as_deref(), Some("pngme"));
+		assert_eq!(reparsed.get(&property("creator")).as_deref(), Some("Jane; John"));
+	}
+
+	#[test]
+	fn test_set_in_declaring_description() {
+		let packet = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="" xmlns:xmp="http://ns.adobe.com/xap/1.0/" xmp:CreatorTool="Editor"/>
+  <rdf:Description rdf:about="" xmlns:dc="http://purl.org/dc/elements/1.1/">
+   <dc:creator><rdf:Seq><rdf:li>Jane</rdf:li></rdf:Seq></dc:creator>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>"#;
+		let mut xmp = Xmp::parse(packet.as_bytes()).unwrap();
+		xmp.set(&property("title"), &[String::from("Title")]);
+		xmp.set(&property("acme:AssetId"), &[String::from("42")]);
+
+		let reparsed = Xmp::parse(&xmp.as_bytes()).unwrap();
+		assert_eq!(reparsed, xmp);
+		assert_eq!(reparsed.get(&property("title")).as_deref(), Some("Title"));
+		assert_eq!(reparsed.get(&property("acme:AssetId")).as_deref(), Some("42"));
+		let descriptions: Vec<Vec<String>> = reparsed.descriptions().map(|description| description.elements().map(|element| element.name.to_string()).collect()).collect();
+		assert_eq!(descriptions, [vec!["acme:AssetId"], vec!["dc:creator", "dc:title"]]);
+	}
+
+	#[test]
+	fn test_new_packet() {
+		let mut xmp = Xmp::new();
+		xmp.set(&property("creator"), &[String::from("Jane"), String::from("John")]);
+		let reparsed = Xmp::parse(&xmp.as_bytes()).unwrap();
+		assert_eq!(reparsed.properties(), [(String::from("dc:creator"), String::from("Jane; John"))]);
+	}
+
+	#[test]
+	fn test_rejects_unknown_prefix_and_non_xmp() {
+		assert!(matches!(Property::parse("foo:Bar", &[]), Err(XmpError::Prefix(_))));
+		assert!(matches!(Property::parse("Bar", &[]), Err(XmpError::Name(_))));
+		assert!(matches!(Xmp::parse(b"<html/>"), Err(XmpError::NoRdf)));
+		assert!(matches!(Xmp::parse(b"<x:xmpmeta"), Err(XmpError::Xml(_))));
+	}
+}